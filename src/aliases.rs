@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::shell_env::ShellKind;
+
+/// `alias` output passed in by a shell integration, e.g.
+/// `SHAID_ALIASES="$(alias)" sh-aid ...`. Takes precedence over the rc file.
+pub const ALIASES_ENV: &str = "SHAID_ALIASES";
+
+/// Words that mark an alias value as possibly holding a credential.
+const SECRET_MARKERS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "passwd",
+    "api_key",
+    "apikey",
+    "api-key",
+    "bearer",
+    "authorization",
+];
+
+pub(crate) const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShellAlias {
+    pub name: String,
+    pub value: String,
+}
+
+/// Parses the output of the shell's `alias` builtin: bash prints
+/// `alias ll='ls -l'`, zsh prints bare `ll='ls -l'` pairs.
+pub fn parse_alias_output(text: &str) -> Vec<ShellAlias> {
+    parse_aliases(text, true)
+}
+
+/// Parses the `alias` lines of an rc file, including fish's
+/// `alias ll 'ls -l'`. Variable assignments and other lines are ignored.
+pub fn parse_rc_file(text: &str) -> Vec<ShellAlias> {
+    parse_aliases(text, false)
+}
+
+fn parse_aliases(text: &str, allow_bare: bool) -> Vec<ShellAlias> {
+    let mut aliases: Vec<ShellAlias> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        let Some(alias) = parse_alias_line(line, allow_bare) else {
+            continue;
+        };
+        // Later definitions win, as in the shell
+        aliases.retain(|existing| existing.name != alias.name);
+        aliases.push(alias);
+    }
+
+    aliases
+}
+
+fn parse_alias_line(line: &str, allow_bare: bool) -> Option<ShellAlias> {
+    let (definition, separators): (&str, &[char]) = match line.strip_prefix("alias ") {
+        Some(rest) => (rest.trim_start(), &['=', ' ']),
+        None if allow_bare => (line, &['=']),
+        None => return None,
+    };
+
+    // zsh `alias -g` / `alias -s` flags
+    let definition = definition
+        .strip_prefix("-g ")
+        .or_else(|| definition.strip_prefix("-s "))
+        .map_or(definition, str::trim_start);
+
+    let split = definition.find(separators)?;
+    let (name, value) = (&definition[..split], &definition[split + 1..]);
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.:@+".contains(c))
+    {
+        return None;
+    }
+
+    let value = unquote(value.trim());
+    if value.is_empty() {
+        return None;
+    }
+
+    Some(ShellAlias {
+        name: name.to_string(),
+        value,
+    })
+}
+
+/// Removes shell quoting from an alias value: `'...'` (with bash's `'\''`
+/// escape), `"..."` and unquoted text. A trailing `# comment` is dropped.
+fn unquote(value: &str) -> String {
+    let mut output = String::new();
+    let mut chars = value.chars();
+    let mut quote: Option<char> = None;
+
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    output.push(next);
+                }
+            }
+            (None, '#') if output.is_empty() || output.ends_with(' ') => break,
+            (_, c) => output.push(c),
+        }
+    }
+
+    output.trim().to_string()
+}
+
+/// Whether `text` mentions something that usually goes with a credential.
+pub(crate) fn looks_secret(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    SECRET_MARKERS.iter().any(|marker| lower.contains(marker))
+}
+
+/// Replaces the value of aliases that look like they carry credentials.
+pub fn redact_secrets(aliases: Vec<ShellAlias>) -> Vec<ShellAlias> {
+    aliases
+        .into_iter()
+        .map(|alias| {
+            if looks_secret(&alias.value) {
+                ShellAlias {
+                    value: REDACTED.to_string(),
+                    ..alias
+                }
+            } else {
+                alias
+            }
+        })
+        .collect()
+}
+
+/// The rc file aliases are usually defined in for `shell`.
+pub fn rc_file(shell: ShellKind, home: &Path) -> Option<std::path::PathBuf> {
+    match shell {
+        ShellKind::Bash => Some(home.join(".bashrc")),
+        ShellKind::Zsh => Some(home.join(".zshrc")),
+        ShellKind::Fish => Some(home.join(".config/fish/config.fish")),
+        ShellKind::PowerShell => None,
+    }
+}
+
+/// Reads the user's aliases from `env_aliases` (the `SHAID_ALIASES` value)
+/// or else from the shell's rc file, with secrets redacted.
+pub fn load_aliases(
+    shell: ShellKind,
+    env_aliases: Option<&str>,
+    home: Option<&Path>,
+) -> Vec<ShellAlias> {
+    let aliases = match env_aliases.filter(|text| !text.trim().is_empty()) {
+        Some(text) => parse_alias_output(text),
+        None => home
+            .and_then(|home| rc_file(shell, home))
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| parse_rc_file(&text))
+            .unwrap_or_default(),
+    };
+
+    redact_secrets(aliases)
+}
+
+/// One `name = value` line per alias, for the context.
+pub fn format_aliases(aliases: &[ShellAlias]) -> String {
+    aliases
+        .iter()
+        .map(|alias| format!("{} = {}\n", alias.name, alias.value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn alias(name: &str, value: &str) -> ShellAlias {
+        ShellAlias {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_bash_alias_output() {
+        let output = "alias gs='git status'\n\
+                      alias ll='ls -alF'\n\
+                      alias say='echo '\\''hi'\\'''\n";
+        assert_eq!(
+            parse_alias_output(output),
+            vec![
+                alias("gs", "git status"),
+                alias("ll", "ls -alF"),
+                alias("say", "echo 'hi'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_zsh_alias_output() {
+        assert_eq!(
+            parse_alias_output("ll='ls -l'\nrun-help=man\n"),
+            vec![alias("ll", "ls -l"), alias("run-help", "man")]
+        );
+    }
+
+    #[test]
+    fn test_parse_rc_file() {
+        let text = "# alias old='ignored'\n\
+                    export PATH=\"$HOME/bin:$PATH\"\n\
+                    EDITOR=vim\n\
+                    alias ll='ls -l'\n\
+                    alias -g G='| grep'\n\
+                    alias k=\"kubectl\"  # short\n\
+                    alias gco 'git checkout'\n\
+                    alias ll='ls -la'\n";
+        assert_eq!(
+            parse_rc_file(text),
+            vec![
+                alias("G", "| grep"),
+                alias("k", "kubectl"),
+                alias("gco", "git checkout"),
+                alias("ll", "ls -la"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let aliases = redact_secrets(vec![
+            alias(
+                "api",
+                "curl -H 'Authorization: Bearer abc123' https://api.example",
+            ),
+            alias("deploy", "GITHUB_TOKEN=ghp_x ./deploy.sh"),
+            alias("ll", "ls -l"),
+        ]);
+        assert_eq!(aliases[0].value, REDACTED);
+        assert_eq!(aliases[1].value, REDACTED);
+        assert_eq!(aliases[2].value, "ls -l");
+    }
+
+    #[test]
+    fn test_load_aliases_prefers_env_then_rc_file() {
+        let home = TempDir::new().unwrap();
+        std::fs::write(home.path().join(".zshrc"), "alias ll='ls -l'\n").unwrap();
+
+        assert_eq!(
+            load_aliases(ShellKind::Zsh, Some("gs='git status'\n"), Some(home.path())),
+            vec![alias("gs", "git status")]
+        );
+        assert_eq!(
+            load_aliases(ShellKind::Zsh, Some(""), Some(home.path())),
+            vec![alias("ll", "ls -l")]
+        );
+        assert!(load_aliases(ShellKind::Bash, None, Some(home.path())).is_empty());
+    }
+
+    #[test]
+    fn test_format_aliases() {
+        assert_eq!(
+            format_aliases(&[alias("ll", "ls -l"), alias("gs", "git status")]),
+            "ll = ls -l\ngs = git status\n"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::generate::Strictness;
+
+/// How far in the future a stored timestamp may be before it is treated as
+/// bogus. Small drifts happen when NTP adjusts the clock between runs.
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+/// Current time in seconds since the Unix epoch, or `None` when the system
+/// clock reports a time before 1970.
+pub fn now_unix() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Returns true when an entry stored at `stored_at` is still within `ttl_secs`.
+///
+/// Any doubt about the clock counts as expired: an unknown current time, or a
+/// timestamp further in the future than the allowed skew.
+pub fn is_fresh(stored_at: u64, ttl_secs: u64, now: Option<u64>) -> bool {
+    let Some(now) = now else {
+        return false;
+    };
+
+    match now.checked_sub(stored_at) {
+        Some(age) => age < ttl_secs,
+        None => stored_at - now <= MAX_CLOCK_SKEW_SECS,
+    }
+}
+
+/// Stable cache key for a request: the hex SHA-256 of a canonical JSON array
+/// of the inputs. Unlike `DefaultHasher`, this is identical across runs,
+/// Rust versions and platforms, and the JSON encoding keeps field
+/// boundaries unambiguous.
+pub fn cache_key(provider: &str, model: &str, system_prompt: &str, user_prompt: &str) -> String {
+    hash_inputs(serde_json::json!([
+        provider,
+        model,
+        system_prompt,
+        user_prompt
+    ]))
+}
+
+/// [`cache_key`] for the response cache, which also depends on
+/// `strictness`: it decides how the response is cleaned up and whether an
+/// unusable one is asked for again.
+pub fn response_key(
+    provider: &str,
+    model: &str,
+    system_prompt: &str,
+    user_prompt: &str,
+    strictness: Strictness,
+) -> String {
+    hash_inputs(serde_json::json!([
+        provider,
+        model,
+        system_prompt,
+        user_prompt,
+        strictness.level,
+        strictness.retries,
+        strictness.retry_on_invalid,
+    ]))
+}
+
+fn hash_inputs(inputs: serde_json::Value) -> String {
+    let digest = Sha256::digest(inputs.to_string().as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Where generated commands are cached, keyed by [`cache_key`].
+pub fn response_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("sh-aid").join("cache.json"))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedResponse {
+    response: String,
+    stored_at: u64,
+}
+
+/// Responses to earlier requests, so repeating a prompt in the same
+/// context does not call the provider again. The clock is passed in (see
+/// [`now_unix`]) rather than read, so tests can fix it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseCache {
+    entries: HashMap<String, CachedResponse>,
+}
+
+impl ResponseCache {
+    /// Loads the cache at `path`. A missing or unreadable file is an empty
+    /// cache.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// The response stored under `key`, if it is younger than `ttl_secs`.
+    pub fn get(&self, key: &str, ttl_secs: u64, now: Option<u64>) -> Option<&str> {
+        self.entries
+            .get(key)
+            .filter(|entry| is_fresh(entry.stored_at, ttl_secs, now))
+            .map(|entry| entry.response.as_str())
+    }
+
+    /// Stores `response` under `key` and drops entries older than
+    /// `ttl_secs`, so the file does not grow without bound. Nothing is
+    /// stored when the clock is unknown.
+    pub fn insert(&mut self, key: &str, response: &str, ttl_secs: u64, now: Option<u64>) {
+        let Some(now) = now else {
+            return;
+        };
+        self.entries
+            .retain(|_, entry| is_fresh(entry.stored_at, ttl_secs, Some(now)));
+        self.entries.insert(
+            key.to_string(),
+            CachedResponse {
+                response: response.to_string(),
+                stored_at: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn test_now_unix_is_after_epoch() {
+        assert!(now_unix().is_some_and(|t| t > 0));
+    }
+
+    #[test]
+    fn test_fresh_within_ttl() {
+        assert!(is_fresh(NOW - 10, 60, Some(NOW)));
+        assert!(is_fresh(NOW, 60, Some(NOW)));
+    }
+
+    #[test]
+    fn test_expired_past_ttl() {
+        assert!(!is_fresh(NOW - 60, 60, Some(NOW)));
+        assert!(!is_fresh(0, 60, Some(NOW)));
+    }
+
+    #[test]
+    fn test_future_timestamps() {
+        // Slight skew is tolerated
+        assert!(is_fresh(NOW + 5, 60, Some(NOW)));
+
+        // Far-future entries were written with a broken clock
+        assert!(!is_fresh(NOW + 3600, 60, Some(NOW)));
+        assert!(!is_fresh(u64::MAX, u64::MAX, Some(NOW)));
+    }
+
+    #[test]
+    fn test_unknown_clock_is_expired() {
+        assert!(!is_fresh(NOW, 60, None));
+    }
+
+    #[test]
+    fn test_cache_key_is_stable() {
+        let key = cache_key("OpenAI", "gpt-4o", "system", "list files");
+        assert_eq!(key, cache_key("OpenAI", "gpt-4o", "system", "list files"));
+        assert_eq!(key.len(), 64);
+
+        // Pinned so an accidental change to the canonical form is caught
+        assert_eq!(
+            key,
+            "51678fd4a18c30229925d792a7d415e9c4b8a8d87a9734e9318b72e4da597709"
+        );
+    }
+
+    #[test]
+    fn test_response_cache_hit_and_miss() {
+        let mut cache = ResponseCache::default();
+        let key = cache_key("OpenAI", "gpt-4o", "system", "list files");
+        assert_eq!(cache.get(&key, 60, Some(NOW)), None);
+
+        cache.insert(&key, "ls -la", 60, Some(NOW));
+        assert_eq!(cache.get(&key, 60, Some(NOW + 30)), Some("ls -la"));
+        let other = cache_key("OpenAI", "gpt-4o", "system", "list all files");
+        assert_eq!(cache.get(&other, 60, Some(NOW + 30)), None);
+
+        // Nothing is stored without a clock
+        cache.insert(&other, "ls -a", 60, None);
+        assert_eq!(cache.get(&other, 60, Some(NOW)), None);
+    }
+
+    #[test]
+    fn test_response_cache_expiry() {
+        let mut cache = ResponseCache::default();
+        cache.insert("old", "ls", 60, Some(NOW));
+        assert_eq!(cache.get("old", 60, Some(NOW + 59)), Some("ls"));
+        assert_eq!(cache.get("old", 60, Some(NOW + 60)), None);
+        assert_eq!(cache.get("old", 0, Some(NOW)), None);
+
+        // Expired entries are dropped on the next insert
+        cache.insert("new", "pwd", 60, Some(NOW + 120));
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.get("new", 60, Some(NOW + 120)), Some("pwd"));
+    }
+
+    #[test]
+    fn test_response_cache_round_trips_through_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("nested").join("cache.json");
+        assert_eq!(ResponseCache::load(&path), ResponseCache::default());
+
+        let mut cache = ResponseCache::default();
+        cache.insert("key", "du -sh *", 60, Some(NOW));
+        cache.save(&path).unwrap();
+        assert_eq!(ResponseCache::load(&path), cache);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(ResponseCache::load(&path), ResponseCache::default());
+    }
+
+    #[test]
+    fn test_cache_key_distinguishes_inputs() {
+        let base = cache_key("OpenAI", "gpt-4o", "system", "list files");
+        assert_ne!(base, cache_key("Claude", "gpt-4o", "system", "list files"));
+        assert_ne!(
+            base,
+            cache_key("OpenAI", "gpt-4o-mini", "system", "list files")
+        );
+        assert_ne!(
+            base,
+            cache_key("OpenAI", "gpt-4o", "system", "list all files")
+        );
+
+        // Moving text across a field boundary must change the key
+        assert_ne!(cache_key("a", "bc", "", ""), cache_key("ab", "c", "", ""));
+    }
+
+    #[test]
+    fn test_response_key_depends_on_strictness() {
+        let key = |strictness| response_key("OpenAI", "gpt-4o", "system", "list", strictness);
+        let strict = Strictness {
+            level: 1,
+            retries: 3,
+            retry_on_invalid: false,
+        };
+        assert_eq!(key(strict), key(strict));
+        assert_ne!(key(strict), key(Strictness::OFF));
+        assert_ne!(
+            key(Strictness::OFF),
+            key(Strictness {
+                retry_on_invalid: true,
+                ..Strictness::OFF
+            })
+        );
+        assert_ne!(
+            key(Strictness::OFF),
+            cache_key("OpenAI", "gpt-4o", "system", "list")
+        );
+    }
+}
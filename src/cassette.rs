@@ -0,0 +1,316 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::cache::cache_key;
+use crate::config::Config;
+use crate::providers::{AIProvider, GenerateOptions, ModelInfo, ProviderError, Quota};
+use crate::usage::TokenUsage;
+
+/// Path of the cassette file; setting it enables record/replay.
+pub const CASSETTE_ENV: &str = "SHAID_CASSETTE";
+/// `record` or `replay`. Defaults to replay when the cassette exists.
+pub const CASSETTE_MODE_ENV: &str = "SHAID_CASSETTE_MODE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send requests to the provider and save each response.
+    Record,
+    /// Serve responses from the cassette; the network is never used.
+    Replay,
+}
+
+impl CassetteMode {
+    fn from_env_value(value: Option<&str>, cassette_exists: bool) -> Result<Self, ProviderError> {
+        match value.map(str::trim).filter(|v| !v.is_empty()) {
+            None if cassette_exists => Ok(CassetteMode::Replay),
+            None => Ok(CassetteMode::Record),
+            Some(v) if v.eq_ignore_ascii_case("record") => Ok(CassetteMode::Record),
+            Some(v) if v.eq_ignore_ascii_case("replay") => Ok(CassetteMode::Replay),
+            Some(v) => Err(ProviderError::ConfigError(format!(
+                "{CASSETTE_MODE_ENV} must be 'record' or 'replay', got '{v}'"
+            ))),
+        }
+    }
+}
+
+/// One recorded request/response pair. The prompts are kept for readability;
+/// matching only uses `key`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interaction {
+    pub key: String,
+    pub provider: String,
+    pub model: String,
+    pub user_prompt: String,
+    pub response: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    #[serde(default)]
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Loads a cassette, or an empty one when the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, ProviderError> {
+        if !path.exists() {
+            return Ok(Cassette::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| {
+            ProviderError::ConfigError(format!("Failed to read cassette {}: {e}", path.display()))
+        })?;
+        serde_json::from_str(&text).map_err(|e| {
+            ProviderError::ConfigError(format!("Invalid cassette {}: {e}", path.display()))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ProviderError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| ProviderError::Unknown(format!("Failed to encode cassette: {e}")))?;
+        fs::write(path, json).map_err(|e| {
+            ProviderError::ConfigError(format!("Failed to write cassette {}: {e}", path.display()))
+        })
+    }
+
+    pub fn find(&self, key: &str) -> Option<&Interaction> {
+        self.interactions.iter().find(|i| i.key == key)
+    }
+
+    /// Adds an interaction, replacing an earlier recording of the same request.
+    pub fn record(&mut self, interaction: Interaction) {
+        self.interactions.retain(|i| i.key != interaction.key);
+        self.interactions.push(interaction);
+    }
+}
+
+/// Wraps a provider to record its responses to a cassette file, or to replay
+/// them from it, matching requests on the hash of provider, model and prompts.
+pub struct CassetteProvider {
+    inner: Box<dyn AIProvider>,
+    mode: CassetteMode,
+    path: PathBuf,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl CassetteProvider {
+    pub fn new(
+        inner: Box<dyn AIProvider>,
+        path: PathBuf,
+        mode: CassetteMode,
+    ) -> Result<Self, ProviderError> {
+        let cassette = Cassette::load(&path)?;
+        Ok(Self {
+            inner,
+            mode,
+            path,
+            cassette: Arc::new(Mutex::new(cassette)),
+        })
+    }
+
+    /// Wraps `inner` when `SHAID_CASSETTE` is set; otherwise returns it as is.
+    pub fn wrap_from_env(inner: Box<dyn AIProvider>) -> Result<Box<dyn AIProvider>, ProviderError> {
+        let Some(path) = std::env::var_os(CASSETTE_ENV).filter(|p| !p.is_empty()) else {
+            return Ok(inner);
+        };
+        let path = PathBuf::from(path);
+        let mode = CassetteMode::from_env_value(
+            std::env::var(CASSETTE_MODE_ENV).ok().as_deref(),
+            path.exists(),
+        )?;
+        Ok(Box::new(Self::new(inner, path, mode)?))
+    }
+
+    fn request_key(&self, system_prompt: &str, user_prompt: &str) -> String {
+        cache_key(
+            self.inner.get_provider_name(),
+            self.inner.model_id(),
+            system_prompt,
+            user_prompt,
+        )
+    }
+
+    fn cassette(&self) -> std::sync::MutexGuard<'_, Cassette> {
+        self.cassette
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl AIProvider for CassetteProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        let key = self.request_key(system_prompt, user_prompt);
+
+        if self.mode == CassetteMode::Replay {
+            return self
+                .cassette()
+                .find(&key)
+                .map(|interaction| interaction.response.clone())
+                .ok_or_else(|| {
+                    ProviderError::ConfigError(format!(
+                        "No recorded response for this request in cassette {}",
+                        self.path.display()
+                    ))
+                });
+        }
+
+        let response = self
+            .inner
+            .generate_command_with(system_prompt, user_prompt, options)
+            .await?;
+
+        let mut cassette = self.cassette();
+        cassette.record(Interaction {
+            key,
+            provider: self.inner.get_provider_name().to_string(),
+            model: self.inner.model_id().to_string(),
+            user_prompt: user_prompt.to_string(),
+            response: response.clone(),
+        });
+        cassette.save(&self.path)?;
+
+        Ok(response)
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(CassetteProvider {
+            inner: self.inner.clone_box(),
+            mode: self.mode,
+            path: self.path.clone(),
+            cassette: Arc::clone(&self.cassette),
+        })
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cassette.json");
+
+        let mut mock = MockProvider::new();
+        mock.add_response(Ok("du -sh *".to_string()));
+        mock.add_response(Ok("git status".to_string()));
+        let recorder =
+            CassetteProvider::new(Box::new(mock), path.clone(), CassetteMode::Record).unwrap();
+        let recorded = vec![
+            recorder
+                .generate_command("sys", "disk usage")
+                .await
+                .unwrap(),
+            recorder
+                .generate_command("sys", "repo state")
+                .await
+                .unwrap(),
+        ];
+
+        // The replaying provider would fail every request if it were called
+        let offline = MockProvider::with_error(ProviderError::Unknown("offline".to_string()));
+        let player = CassetteProvider::new(Box::new(offline), path, CassetteMode::Replay).unwrap();
+        let replayed = vec![
+            player.generate_command("sys", "disk usage").await.unwrap(),
+            player.generate_command("sys", "repo state").await.unwrap(),
+        ];
+
+        assert_eq!(recorded, vec!["du -sh *", "git status"]);
+        assert_eq!(replayed, recorded);
+    }
+
+    #[tokio::test]
+    async fn test_replay_miss_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let player = CassetteProvider::new(
+            Box::new(MockProvider::new()),
+            dir.path().join("empty.json"),
+            CassetteMode::Replay,
+        )
+        .unwrap();
+
+        let result = player.generate_command("sys", "anything").await;
+        assert!(matches!(result, Err(ProviderError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_rerecording_replaces_entry() {
+        let interaction = |response: &str| Interaction {
+            key: "k".to_string(),
+            provider: "mock".to_string(),
+            model: "mock-model".to_string(),
+            user_prompt: "u".to_string(),
+            response: response.to_string(),
+        };
+        let mut cassette = Cassette::default();
+        cassette.record(interaction("ls"));
+        cassette.record(interaction("ls -la"));
+
+        assert_eq!(cassette.interactions.len(), 1);
+        assert_eq!(cassette.find("k").unwrap().response, "ls -la");
+    }
+
+    #[test]
+    fn test_mode_from_env_value() {
+        assert_eq!(
+            CassetteMode::from_env_value(None, true).unwrap(),
+            CassetteMode::Replay
+        );
+        assert_eq!(
+            CassetteMode::from_env_value(None, false).unwrap(),
+            CassetteMode::Record
+        );
+        assert_eq!(
+            CassetteMode::from_env_value(Some("RECORD"), true).unwrap(),
+            CassetteMode::Record
+        );
+        assert!(CassetteMode::from_env_value(Some("rewind"), true).is_err());
+    }
+}
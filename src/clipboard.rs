@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::context::detect_tools;
+
+/// A program that puts its stdin on the system clipboard (`--copy`). Using
+/// the platform's own tools keeps a clipboard crate out of the build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardTool {
+    /// macOS
+    Pbcopy,
+    /// Wayland
+    WlCopy,
+    /// X11
+    Xclip,
+    /// Windows
+    Clip,
+}
+
+impl ClipboardTool {
+    pub fn binary(&self) -> &'static str {
+        match self {
+            ClipboardTool::Pbcopy => "pbcopy",
+            ClipboardTool::WlCopy => "wl-copy",
+            ClipboardTool::Xclip => "xclip",
+            ClipboardTool::Clip => "clip",
+        }
+    }
+
+    /// The arguments that make the tool read the text from stdin.
+    pub fn args(&self) -> &'static [&'static str] {
+        match self {
+            ClipboardTool::Xclip => &["-selection", "clipboard"],
+            _ => &[],
+        }
+    }
+
+    /// The tools supported on `os` (as in `std::env::consts::OS`), most
+    /// preferred first. On Linux `wl-copy` comes first in a Wayland session.
+    pub fn candidates(os: &str, wayland: bool) -> &'static [ClipboardTool] {
+        match os {
+            "linux" | "freebsd" | "openbsd" | "netbsd" if wayland => {
+                &[ClipboardTool::WlCopy, ClipboardTool::Xclip]
+            }
+            "linux" | "freebsd" | "openbsd" | "netbsd" => {
+                &[ClipboardTool::Xclip, ClipboardTool::WlCopy]
+            }
+            "macos" => &[ClipboardTool::Pbcopy],
+            "windows" => &[ClipboardTool::Clip],
+            _ => &[],
+        }
+    }
+}
+
+/// The first supported clipboard tool installed in `dirs`.
+pub fn detect_clipboard(os: &str, wayland: bool, dirs: &[PathBuf]) -> Option<ClipboardTool> {
+    let candidates = ClipboardTool::candidates(os, wayland);
+    let names: Vec<&str> = candidates.iter().map(ClipboardTool::binary).collect();
+    let found = detect_tools(&names, dirs);
+
+    candidates
+        .iter()
+        .copied()
+        .find(|tool| found.iter().any(|name| name == tool.binary()))
+}
+
+/// The error when no clipboard tool is installed.
+pub fn unavailable_error(os: &str, wayland: bool) -> String {
+    match ClipboardTool::candidates(os, wayland) {
+        [] => format!("copying to the clipboard is not supported on {os}"),
+        candidates => {
+            let names: Vec<&str> = candidates.iter().map(ClipboardTool::binary).collect();
+            format!("no clipboard tool found (install {})", names.join(" or "))
+        }
+    }
+}
+
+/// Copies `text` with the clipboard tool detected in `dirs`.
+pub fn copy_to_clipboard(text: &str, dirs: &[PathBuf]) -> io::Result<ClipboardTool> {
+    let os = std::env::consts::OS;
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let tool = detect_clipboard(os, wayland, dirs)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, unavailable_error(os, wayland)))?;
+
+    let mut process = Command::new(tool.binary());
+    process.args(tool.args());
+    pipe_to(process, text)?;
+    Ok(tool)
+}
+
+/// Runs `process` with `text` on its stdin and fails unless it exits
+/// successfully.
+fn pipe_to(mut process: Command, text: &str) -> io::Result<()> {
+    let mut child = process
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {status}",
+            process.get_program().to_string_lossy()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(
+            ClipboardTool::candidates("linux", true),
+            [ClipboardTool::WlCopy, ClipboardTool::Xclip]
+        );
+        assert_eq!(
+            ClipboardTool::candidates("linux", false)[0],
+            ClipboardTool::Xclip
+        );
+        assert_eq!(
+            ClipboardTool::candidates("macos", false),
+            [ClipboardTool::Pbcopy]
+        );
+        assert_eq!(
+            ClipboardTool::candidates("windows", false),
+            [ClipboardTool::Clip]
+        );
+    }
+
+    #[cfg(unix)]
+    fn fake_tool(dir: &TempDir, name: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.path().join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_detect_clipboard() {
+        let dir = TempDir::new().unwrap();
+        let dirs = vec![dir.path().to_path_buf()];
+        assert_eq!(detect_clipboard("linux", false, &dirs), None);
+        assert_eq!(
+            unavailable_error("linux", false),
+            "no clipboard tool found (install xclip or wl-copy)"
+        );
+
+        fake_tool(&dir, "wl-copy");
+        assert_eq!(
+            detect_clipboard("linux", false, &dirs),
+            Some(ClipboardTool::WlCopy)
+        );
+
+        fake_tool(&dir, "xclip");
+        assert_eq!(
+            detect_clipboard("linux", false, &dirs),
+            Some(ClipboardTool::Xclip)
+        );
+        assert_eq!(
+            detect_clipboard("linux", true, &dirs),
+            Some(ClipboardTool::WlCopy)
+        );
+
+        fake_tool(&dir, "pbcopy");
+        assert_eq!(
+            detect_clipboard("macos", false, &dirs),
+            Some(ClipboardTool::Pbcopy)
+        );
+        assert_eq!(detect_clipboard("haiku", false, &dirs), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pipe_to_writes_stdin() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("clipboard");
+
+        let mut process = Command::new("sh");
+        process.args(["-c", &format!("cat > '{}'", out.display())]);
+        pipe_to(process, "ls -la | sort").unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "ls -la | sort");
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "exit 1"]);
+        assert!(pipe_to(failing, "ls").is_err());
+    }
+}
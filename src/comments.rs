@@ -0,0 +1,114 @@
+use crate::heredoc::HeredocTracker;
+
+/// Removes trailing shell `#` comments from a generated command.
+///
+/// A `#` only starts a comment at the beginning of a word (start of line or
+/// after whitespace) and outside quotes, so `'a # b'`, `"#fff"`, `${#var}` and
+/// URL fragments like `https://host/page#section` are kept. Lines that were
+/// only a comment are dropped. Heredoc bodies are kept verbatim.
+pub fn strip_comments(command: &str) -> String {
+    let mut heredoc = HeredocTracker::default();
+    command
+        .lines()
+        .filter_map(|line| {
+            if heredoc.in_body(line) {
+                return Some(line);
+            }
+            let line = strip_line_comment(line);
+            (!line.is_empty()).then_some(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn strip_line_comment(line: &str) -> &str {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut escaped = false;
+    let mut prev_is_space = true;
+
+    for (index, ch) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            prev_is_space = false;
+            continue;
+        }
+        match ch {
+            '\\' if !in_single => escaped = true,
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '#' if !in_single && !in_double && prev_is_space => {
+                return line[..index].trim_end();
+            }
+            _ => {}
+        }
+        prev_is_space = ch.is_whitespace();
+    }
+
+    line.trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strips_trailing_comments() {
+        assert_eq!(
+            strip_comments("du -sh * | sort -h  # biggest last"),
+            "du -sh * | sort -h"
+        );
+        assert_eq!(strip_comments("# list files\nls -la\n# done"), "ls -la");
+        assert_eq!(strip_comments("ls -la"), "ls -la");
+    }
+
+    #[test]
+    fn test_keeps_hash_inside_quotes() {
+        assert_eq!(
+            strip_comments("git commit -m 'fix #42' # commit"),
+            "git commit -m 'fix #42'"
+        );
+        assert_eq!(
+            strip_comments(r##"echo "color: #fff" # print"##),
+            r##"echo "color: #fff""##
+        );
+        assert_eq!(
+            strip_comments(r"echo \# not a comment"),
+            r"echo \# not a comment"
+        );
+    }
+
+    #[test]
+    fn test_heredoc_bodies_are_kept() {
+        let command = "cat <<'EOF' > setup.sh # write the script\n\
+                       #!/bin/sh\n\
+                       \n\
+                       # install deps\n\
+                       \x20 npm ci   \n\
+                       EOF\n\
+                       chmod +x setup.sh # make it executable";
+        assert_eq!(
+            strip_comments(command),
+            "cat <<'EOF' > setup.sh\n#!/bin/sh\n\n# install deps\n  npm ci   \nEOF\nchmod +x setup.sh"
+        );
+    }
+
+    #[test]
+    fn test_indented_heredoc_is_kept() {
+        let command = "if true; then\n\tcat <<-END\n\t# not a comment\n\tEND\nfi # done";
+        assert_eq!(
+            strip_comments(command),
+            "if true; then\n\tcat <<-END\n\t# not a comment\n\tEND\nfi"
+        );
+    }
+
+    #[test]
+    fn test_keeps_url_fragments_and_expansions() {
+        assert_eq!(
+            strip_comments("curl https://example.com/docs#install # fetch docs"),
+            "curl https://example.com/docs#install"
+        );
+        assert_eq!(strip_comments("echo ${#PATH}"), "echo ${#PATH}");
+        assert_eq!(strip_comments("echo $#"), "echo $#");
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{self, BufRead, Write};
+
+use crate::generate::{CommandResult, Strictness, run};
+use crate::policy::find_never_execute;
+use crate::prompt::estimate_tokens;
+use crate::providers::{AIProvider, ProviderError};
+
+/// Writes `question` and reads one line of input. Only `y`/`yes` (any case)
+/// count as consent; anything else, including EOF, is a no.
+pub fn ask_yes_no<R: BufRead, W: Write>(
+    question: &str,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<bool> {
+    write!(output, "{question} [y/N] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Like [`ask_yes_no`], but only the full word `yes` counts, so that a
+/// reflexive `y` cannot run a dangerous command.
+pub fn ask_typed_yes<R: BufRead, W: Write>(
+    question: &str,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<bool> {
+    write!(output, "{question} [type 'yes' to confirm] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+/// The answer to the run prompt of a generated command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunAnswer {
+    Yes,
+    No,
+    /// Edit the command first, then ask again.
+    Edit,
+}
+
+/// Like [`ask_yes_no`] (or [`ask_typed_yes`] when `typed_yes` is set), with
+/// `e`/`edit` as a third answer.
+pub fn ask_run_or_edit<R: BufRead, W: Write>(
+    question: &str,
+    typed_yes: bool,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<RunAnswer> {
+    if typed_yes {
+        write!(output, "{question} [type 'yes' to confirm, 'e' to edit] ")?;
+    } else {
+        write!(output, "{question} [y/N/e] ")?;
+    }
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "e" | "edit" => RunAnswer::Edit,
+        "yes" => RunAnswer::Yes,
+        "y" if !typed_yes => RunAnswer::Yes,
+        _ => RunAnswer::No,
+    })
+}
+
+/// Asks for one of `count` numbered choices. Returns its 0-based index, or
+/// `None` for an empty answer, EOF, or anything that is not a listed number.
+pub fn ask_choice<R: BufRead, W: Write>(
+    question: &str,
+    count: usize,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<Option<usize>> {
+    write!(output, "{question} [1-{count}, Enter to cancel] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(answer
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|number| (1..=count).contains(number))
+        .map(|number| number - 1))
+}
+
+/// What to do with a generated command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunDecision {
+    Run,
+    Declined,
+    /// The command matches this `neverExecute` pattern.
+    Refused {
+        pattern: String,
+    },
+}
+
+/// Decides whether to run `command`. Commands matching a `never_execute`
+/// pattern are refused without asking, even with `yes`; otherwise `yes` or
+/// the answer from `ask` decides.
+pub fn decide_run<F>(command: &str, never_execute: &[String], yes: bool, ask: F) -> RunDecision
+where
+    F: FnOnce() -> bool,
+{
+    if let Some(pattern) = find_never_execute(command, never_execute) {
+        return RunDecision::Refused {
+            pattern: pattern.to_string(),
+        };
+    }
+
+    if yes || ask() {
+        RunDecision::Run
+    } else {
+        RunDecision::Declined
+    }
+}
+
+/// One-line summary of the request about to be sent, for the pre-request
+/// confirmation.
+pub fn describe_request(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+) -> String {
+    let model_info = provider.get_model_info();
+    let tokens = estimate_tokens(system_prompt) + estimate_tokens(user_prompt);
+
+    format!(
+        "Send request to {} (model: {}, ~{} prompt tokens)?",
+        provider.get_provider_name(),
+        model_info.name,
+        tokens
+    )
+}
+
+/// Calls the provider only if `confirm` approves the request summary.
+/// Returns `Ok(None)` when the user declines, without touching the network.
+pub async fn generate_if_confirmed<F>(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    strictness: Strictness,
+    confirm: F,
+) -> Result<Option<CommandResult>, ProviderError>
+where
+    F: FnOnce(&str) -> bool,
+{
+    let summary = describe_request(provider, system_prompt, user_prompt);
+    if !confirm(&summary) {
+        return Ok(None);
+    }
+
+    run(provider, system_prompt, user_prompt, strictness)
+        .await
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_ask_yes_no() {
+        for (answer, expected) in [
+            ("y\n", true),
+            ("YES\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+            ("yep\n", false),
+        ] {
+            let mut output = Vec::new();
+            let result = ask_yes_no("Continue?", &mut Cursor::new(answer), &mut output).unwrap();
+            assert_eq!(result, expected, "answer {answer:?}");
+            assert_eq!(String::from_utf8(output).unwrap(), "Continue? [y/N] ");
+        }
+    }
+
+    #[test]
+    fn test_ask_typed_yes() {
+        for (answer, expected) in [
+            ("yes\n", true),
+            ("YES\n", true),
+            ("y\n", false),
+            ("", false),
+        ] {
+            let mut output = Vec::new();
+            let result = ask_typed_yes("Run?", &mut Cursor::new(answer), &mut output).unwrap();
+            assert_eq!(result, expected, "answer {answer:?}");
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "Run? [type 'yes' to confirm] "
+            );
+        }
+    }
+
+    #[test]
+    fn test_ask_run_or_edit() {
+        for (answer, typed_yes, expected) in [
+            ("y\n", false, RunAnswer::Yes),
+            ("e\n", false, RunAnswer::Edit),
+            ("EDIT\n", true, RunAnswer::Edit),
+            ("\n", false, RunAnswer::No),
+            ("", false, RunAnswer::No),
+            ("y\n", true, RunAnswer::No),
+            ("yes\n", true, RunAnswer::Yes),
+        ] {
+            let mut output = Vec::new();
+            let result =
+                ask_run_or_edit("Run?", typed_yes, &mut Cursor::new(answer), &mut output).unwrap();
+            assert_eq!(result, expected, "answer {answer:?}");
+        }
+
+        let mut output = Vec::new();
+        ask_run_or_edit("Run?", false, &mut Cursor::new(""), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Run? [y/N/e] ");
+    }
+
+    #[test]
+    fn test_ask_choice() {
+        for (answer, expected) in [
+            ("1\n", Some(0)),
+            (" 3 \n", Some(2)),
+            ("4\n", None),
+            ("0\n", None),
+            ("two\n", None),
+            ("\n", None),
+            ("", None),
+        ] {
+            let mut output = Vec::new();
+            let result =
+                ask_choice("Run which?", 3, &mut Cursor::new(answer), &mut output).unwrap();
+            assert_eq!(result, expected, "answer {answer:?}");
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "Run which? [1-3, Enter to cancel] "
+            );
+        }
+    }
+
+    #[test]
+    fn test_never_execute_refuses_even_with_yes() {
+        let never_execute = vec!["shutdown".to_string(), "reboot".to_string()];
+        let decision = decide_run("sudo shutdown -h now", &never_execute, true, || {
+            panic!("a refused command must not prompt")
+        });
+        assert_eq!(
+            decision,
+            RunDecision::Refused {
+                pattern: "shutdown".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_other_commands_go_through_confirmation() {
+        let never_execute = vec!["shutdown".to_string()];
+        assert_eq!(
+            decide_run("ls -la", &never_execute, false, || true),
+            RunDecision::Run
+        );
+        assert_eq!(
+            decide_run("ls -la", &never_execute, false, || false),
+            RunDecision::Declined
+        );
+        assert_eq!(
+            decide_run("ls -la", &never_execute, true, || false),
+            RunDecision::Run
+        );
+    }
+
+    #[test]
+    fn test_describe_request() {
+        let provider = MockProvider::new();
+        let summary = describe_request(&provider, "abcd", "efgh");
+        assert_eq!(
+            summary,
+            "Send request to mock (model: mock-model, ~2 prompt tokens)?"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_declined_request_skips_provider() {
+        let provider = MockProvider::with_response("rm -rf build".to_string());
+
+        let result =
+            generate_if_confirmed(&provider, "system", "user", Strictness::OFF, |_| false).await;
+        assert!(matches!(result, Ok(None)));
+
+        // The queued response is still there, so the provider was never called
+        let result = provider.generate_command("system", "user").await;
+        assert_eq!(result.unwrap(), "rm -rf build");
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_request_calls_provider() {
+        let provider = MockProvider::with_response("ls -la".to_string());
+
+        let mut seen = String::new();
+        let result =
+            generate_if_confirmed(&provider, "system", "user", Strictness::OFF, |summary| {
+                seen = summary.to_string();
+                true
+            })
+            .await;
+
+        let result = result.unwrap().unwrap();
+        assert_eq!(result.command, "ls -la");
+        assert_eq!(result.provider, "mock");
+        assert!(seen.contains("mock-model"));
+    }
+}
@@ -1,9 +1,79 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, SystemTime};
 use sysinfo::System;
 
+use crate::aliases::{ALIASES_ENV, ShellAlias, format_aliases, load_aliases};
+use crate::file_context::{IncludeLimits, IncludedFile, collect_files, format_included_files};
+use crate::listing::{
+    DEFAULT_CONTEXT_MAX_ENTRIES, DEFAULT_MAX_LISTING_ENTRIES, render_listing_guarded,
+};
+use crate::prompt::fence_untrusted;
+use crate::shell_env::ShellKind;
+use crate::shell_history::{format_history, load_history};
+
+/// Commonly useful tools probed on PATH so the model knows what it can use.
+const PROBED_TOOLS: &[&str] = &[
+    "git", "docker", "podman", "kubectl", "curl", "wget", "jq", "rg", "fd", "fzf", "python3",
+    "node", "npm", "cargo", "go", "make", "brew", "apt", "dnf", "pacman", "rsync", "ffmpeg",
+];
+
+/// Address used for the connectivity check: a public DNS resolver, by IP so
+/// no name resolution is needed.
+const CONNECTIVITY_PROBE_ADDR: &str = "1.1.1.1:443";
+const CONNECTIVITY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Groups of context that can be withheld from a provider (`contextFields`
+/// in the config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContextField {
+    /// Operating system, release and architecture.
+    Os,
+    Shell,
+    /// Current working and home directory.
+    Paths,
+    /// CPU and memory.
+    Hardware,
+    Tools,
+    Network,
+    DirectoryListing,
+    IncludedFiles,
+    Aliases,
+    /// Recent commands from the shell history.
+    History,
+}
+
+/// Knobs that control what `SystemContext::gather_with` collects.
+#[derive(Debug, Clone, Default)]
+pub struct ContextOptions {
+    /// Directories searched for tools in addition to `PATH`.
+    pub extra_path: Vec<String>,
+    /// Globs of small text files whose contents are added to the context.
+    pub include_files: Vec<String>,
+    /// Omit the directory listing above this many entries
+    /// (default [`DEFAULT_MAX_LISTING_ENTRIES`]).
+    pub max_listing_entries: Option<usize>,
+    /// Entries shown in the directory listing, directories first (default
+    /// [`DEFAULT_CONTEXT_MAX_ENTRIES`]).
+    pub context_max_entries: Option<usize>,
+    /// Check whether the machine can reach the internet (adds up to
+    /// half a second).
+    pub check_connectivity: bool,
+    /// Read the user's shell aliases (from `SHAID_ALIASES` or the rc file).
+    pub include_aliases: bool,
+    /// Read this many recent commands from the shell history; `None` reads
+    /// none.
+    pub history_lines: Option<usize>,
+    /// Shell to generate commands for instead of the detected one
+    /// (`--shell`).
+    pub shell: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemContext {
     pub os_type: String,
@@ -18,10 +88,40 @@ pub struct SystemContext {
     pub total_memory_mb: u64,
     pub free_memory_mb: u64,
     pub directory_listing: String,
+    pub available_tools: Vec<String>,
+    #[serde(default)]
+    pub included_files: Vec<IncludedFile>,
+    /// Running under Windows Subsystem for Linux.
+    #[serde(default)]
+    pub is_wsl: bool,
+    /// Whether the internet was reachable; `None` when not checked.
+    #[serde(default)]
+    pub online: Option<bool>,
+    /// The user's shell aliases, with secret-looking values redacted.
+    #[serde(default)]
+    pub aliases: Vec<ShellAlias>,
+    /// Recent commands from the shell history, oldest first, with
+    /// secret-looking ones redacted.
+    #[serde(default)]
+    pub recent_commands: Vec<String>,
+    /// Fields the prompt may contain; `None` allows all of them.
+    #[serde(skip)]
+    pub allowed_fields: Option<Vec<ContextField>>,
+    /// When the context was gathered. The CPU and memory figures are a
+    /// snapshot of that moment.
+    #[serde(default = "SystemTime::now")]
+    pub gathered_at: SystemTime,
+    /// Note the snapshot's age in the prompt once it is older than this.
+    #[serde(skip)]
+    pub note_age_after: Option<Duration>,
 }
 
 impl SystemContext {
     pub fn gather() -> Result<Self> {
+        Self::gather_with(&ContextOptions::default())
+    }
+
+    pub fn gather_with(options: &ContextOptions) -> Result<Self> {
         let mut sys = System::new_all();
         sys.refresh_all();
 
@@ -32,7 +132,18 @@ impl SystemContext {
         // Get OS release/version information
         let os_release = get_os_release().unwrap_or_else(|| "unknown".to_string());
 
-        let shell = env::var("SHELL").unwrap_or_else(|_| "unknown".to_string());
+        let detected_shell = detect_shell(
+            env::var("SHELL").ok().as_deref(),
+            env::var("PSModulePath").ok().as_deref(),
+            env::var("COMSPEC").ok().as_deref(),
+            cfg!(target_os = "windows"),
+        );
+
+        let is_wsl = cfg!(target_os = "linux")
+            && detect_wsl(
+                std::fs::read_to_string("/proc/version").ok().as_deref(),
+                env::var("WSL_DISTRO_NAME").ok().as_deref(),
+            );
 
         let current_dir = env::current_dir()
             .context("Failed to get current directory")?
@@ -56,15 +167,57 @@ impl SystemContext {
         let free_memory_mb = sys.available_memory() / 1024 / 1024;
 
         // Get directory listing
-        let directory_listing = get_directory_listing()
+        let max_entries = options
+            .max_listing_entries
+            .unwrap_or(DEFAULT_MAX_LISTING_ENTRIES);
+        let max_shown = options
+            .context_max_entries
+            .unwrap_or(DEFAULT_CONTEXT_MAX_ENTRIES);
+        let directory_listing = get_directory_listing(max_entries, max_shown)
             .unwrap_or_else(|e| format!("Unable to get directory listing: {e}"));
 
+        let available_tools = detect_tools(PROBED_TOOLS, &tool_search_dirs(&options.extra_path));
+
+        let included_files = collect_files(
+            &options.include_files,
+            Path::new(&current_dir),
+            IncludeLimits::default(),
+        )?;
+
+        let online = options.check_connectivity.then(|| {
+            CONNECTIVITY_PROBE_ADDR
+                .parse()
+                .is_ok_and(|addr| check_connectivity(addr, CONNECTIVITY_TIMEOUT))
+        });
+
+        let aliases = if options.include_aliases {
+            load_aliases(
+                ShellKind::detect(Some(&detected_shell)),
+                env::var(ALIASES_ENV).ok().as_deref(),
+                dirs::home_dir().as_deref(),
+            )
+        } else {
+            Vec::new()
+        };
+
+        let recent_commands = options
+            .history_lines
+            .map(|lines| {
+                load_history(
+                    ShellKind::detect(Some(&detected_shell)),
+                    env::var("HISTFILE").ok().as_deref(),
+                    dirs::home_dir().as_deref(),
+                    lines,
+                )
+            })
+            .unwrap_or_default();
+
         Ok(SystemContext {
             os_type,
             os_release,
             platform,
             arch,
-            shell,
+            shell: options.shell.clone().unwrap_or(detected_shell),
             current_dir,
             home_dir,
             cpu_model,
@@ -72,45 +225,251 @@ impl SystemContext {
             total_memory_mb,
             free_memory_mb,
             directory_listing,
+            available_tools,
+            included_files,
+            is_wsl,
+            online,
+            aliases,
+            recent_commands,
+            allowed_fields: None,
+            gathered_at: SystemTime::now(),
+            note_age_after: None,
         })
     }
 
+    /// Restricts the prompt to `fields`; `None` sends everything.
+    pub fn restricted_to(self, fields: Option<&[ContextField]>) -> Self {
+        SystemContext {
+            allowed_fields: fields.map(<[ContextField]>::to_vec),
+            ..self
+        }
+    }
+
+    /// Makes the prompt say how old the snapshot is once it is older than
+    /// `max_age`, for callers that reuse a context over a long time.
+    pub fn noting_age_after(self, max_age: Duration) -> Self {
+        SystemContext {
+            note_age_after: Some(max_age),
+            ..self
+        }
+    }
+
+    /// A copy without what changes from one gather to the next (free memory,
+    /// the listing's sizes and times, the snapshot's age), so the prompt it
+    /// renders can key cached responses.
+    pub fn without_volatile_fields(&self) -> Self {
+        SystemContext {
+            free_memory_mb: 0,
+            directory_listing: String::new(),
+            gathered_at: SystemTime::UNIX_EPOCH,
+            note_age_after: None,
+            ..self.clone()
+        }
+    }
+
+    /// Time since the context was gathered; zero if the clock went back.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.gathered_at)
+            .unwrap_or_default()
+    }
+
+    /// True when the context was gathered more than `max_age` ago, so its
+    /// memory figures may no longer hold.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age() > max_age
+    }
+
+    fn allows(&self, field: ContextField) -> bool {
+        self.allowed_fields
+            .as_ref()
+            .is_none_or(|fields| fields.contains(&field))
+    }
+
     pub fn build_environment_context(&self) -> String {
-        format!(
-            r#"
-Operating System: {} {} ({} - {})
-Shell: {}
-Current Working Directory: {}
-Home Directory: {}
-CPU Info: {} ({} cores)
-Total Memory: {} MB
-Free Memory: {} MB
-"#,
-            self.os_type,
-            self.os_release,
-            self.platform,
-            self.arch,
-            self.shell,
-            self.current_dir,
-            self.home_dir,
-            self.cpu_model,
-            self.cpu_cores,
-            self.total_memory_mb,
-            self.free_memory_mb
-        )
+        let mut context = String::from("\n");
+
+        if self.allows(ContextField::Os) {
+            context.push_str(&format!(
+                "Operating System: {} {} ({} - {})\n",
+                self.os_type, self.os_release, self.platform, self.arch
+            ));
+        }
+        if self.allows(ContextField::Shell) {
+            context.push_str(&format!("Shell: {}\n", self.shell));
+        }
+        if self.allows(ContextField::Paths) {
+            context.push_str(&format!(
+                "Current Working Directory: {}\nHome Directory: {}\n",
+                self.current_dir, self.home_dir
+            ));
+        }
+        if self.allows(ContextField::Hardware) {
+            context.push_str(&format!(
+                "CPU Info: {} ({} cores)\nTotal Memory: {} MB\nFree Memory: {} MB\n",
+                self.cpu_model, self.cpu_cores, self.total_memory_mb, self.free_memory_mb
+            ));
+            if let Some(max_age) = self.note_age_after
+                && self.is_stale(max_age)
+            {
+                context.push_str(&format!(
+                    "Note: these CPU and memory figures are from {} ago and may be out of date.\n",
+                    format_age(self.age())
+                ));
+            }
+        }
+        if self.allows(ContextField::Tools) {
+            let tools = if self.available_tools.is_empty() {
+                "unknown".to_string()
+            } else {
+                self.available_tools.join(", ")
+            };
+            context.push_str(&format!("Available Tools: {tools}\n"));
+        }
+
+        if let Some(online) = self.online
+            && self.allows(ContextField::Network)
+        {
+            context.push_str(if online {
+                "Network: online\n"
+            } else {
+                "Network: offline (commands that download or install packages will fail)\n"
+            });
+        }
+
+        if self.is_wsl && self.allows(ContextField::Os) {
+            context.push_str(
+                "Note: running under WSL. Windows drives are mounted at /mnt/<drive> (e.g. /mnt/c), \
+                 `wslpath` converts between Windows and Linux paths, and Windows programs can be \
+                 run by their .exe name (e.g. explorer.exe).\n",
+            );
+        }
+
+        context
     }
 
+    /// The environment context plus the untrusted parts (directory listing,
+    /// included files, aliases, history), each fenced as data.
     pub fn build_full_context(&self) -> String {
-        format!(
-            "{}
-Result of `ls -l` in working directory:
-{}",
-            self.build_environment_context(),
-            self.directory_listing
-        )
+        let mut context = self.build_environment_context();
+
+        if !self.directory_listing.is_empty() && self.allows(ContextField::DirectoryListing) {
+            context.push_str(&format!(
+                "\nResult of `ls -l` in working directory:\n{}",
+                fence_untrusted("directory listing", &self.directory_listing)
+            ));
+        }
+
+        if !self.included_files.is_empty() && self.allows(ContextField::IncludedFiles) {
+            context.push_str("\nContents of included files:\n");
+            context.push_str(&fence_untrusted(
+                "included files",
+                &format_included_files(&self.included_files),
+            ));
+        }
+
+        if !self.aliases.is_empty() && self.allows(ContextField::Aliases) {
+            context.push_str("\nUser's shell aliases (usable in the command):\n");
+            context.push_str(&fence_untrusted(
+                "shell aliases",
+                &format_aliases(&self.aliases),
+            ));
+        }
+
+        if !self.recent_commands.is_empty() && self.allows(ContextField::History) {
+            context.push_str("\nCommands the user ran recently, oldest first:\n");
+            context.push_str(&fence_untrusted(
+                "shell history",
+                &format_history(&self.recent_commands),
+            ));
+        }
+
+        context
     }
 }
 
+/// `45s`, `12m` or `3h 20m`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// The user's shell: `$SHELL` when set, which includes Git Bash and MSYS on
+/// Windows. Otherwise Windows sessions are assumed to be PowerShell, the
+/// default there, when `PSModulePath` is set, and `COMSPEC` (cmd.exe) when
+/// not.
+fn detect_shell(
+    shell: Option<&str>,
+    ps_module_path: Option<&str>,
+    comspec: Option<&str>,
+    windows: bool,
+) -> String {
+    if let Some(shell) = shell.filter(|shell| !shell.is_empty()) {
+        return shell.to_string();
+    }
+    if windows {
+        if ps_module_path.is_some_and(|path| !path.is_empty()) {
+            return "powershell".to_string();
+        }
+        if let Some(comspec) = comspec.filter(|comspec| !comspec.is_empty()) {
+            return comspec.to_string();
+        }
+    }
+    "unknown".to_string()
+}
+
+/// WSL kernels report "microsoft" in `/proc/version`; WSL also sets
+/// `WSL_DISTRO_NAME` in every session.
+fn detect_wsl(proc_version: Option<&str>, distro_name: Option<&str>) -> bool {
+    distro_name.is_some_and(|name| !name.is_empty())
+        || proc_version.is_some_and(|version| version.to_lowercase().contains("microsoft"))
+}
+
+/// True when a TCP connection to `addr` succeeds within `timeout`. Refused
+/// connections, unreachable networks and timeouts all count as offline.
+pub fn check_connectivity(addr: SocketAddr, timeout: Duration) -> bool {
+    TcpStream::connect_timeout(&addr, timeout).is_ok()
+}
+
+/// Directories to probe for tools: `PATH` entries followed by `extra`.
+pub fn tool_search_dirs(extra: &[String]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    dirs.extend(extra.iter().map(PathBuf::from));
+    dirs
+}
+
+/// Returns the subset of `tools` found as executables in any of `dirs`,
+/// preserving the order of `tools`.
+pub fn detect_tools(tools: &[&str], dirs: &[PathBuf]) -> Vec<String> {
+    tools
+        .iter()
+        .filter(|tool| dirs.iter().any(|dir| is_executable_in(dir, tool)))
+        .map(|tool| tool.to_string())
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable_in(dir: &Path, name: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(dir.join(name))
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable_in(dir: &Path, name: &str) -> bool {
+    ["", ".exe", ".cmd", ".bat"]
+        .iter()
+        .any(|ext| dir.join(format!("{name}{ext}")).is_file())
+}
+
 fn get_os_release() -> Option<String> {
     // Try different methods to get OS version information
     if cfg!(target_os = "macos") {
@@ -179,31 +538,52 @@ fn get_windows_version() -> Option<String> {
     None
 }
 
-fn get_directory_listing() -> Result<String> {
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .args(["/C", "dir"])
-            .output()
-            .context("Failed to execute 'dir' command")?
-    } else {
-        Command::new("ls")
-            .output()
-            .context("Failed to execute 'ls' command")?
-    };
-
-    if !output.status.success() {
-        anyhow::bail!(
-            "Directory listing command failed with exit code: {}",
-            output.status
-        );
-    }
+fn get_directory_listing(max_entries: usize, max_shown: usize) -> Result<String> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    render_listing_guarded(&current_dir, max_entries, max_shown)
+        .with_context(|| format!("Failed to list directory: {current_dir:?}"))
+}
 
-    String::from_utf8(output.stdout).context("Failed to convert directory listing output to UTF-8")
+#[cfg(test)]
+pub mod test_utils {
+    use super::*;
+
+    pub fn sample_context() -> SystemContext {
+        SystemContext {
+            os_type: "linux".to_string(),
+            os_release: "20.04".to_string(),
+            platform: "unix".to_string(),
+            arch: "x86_64".to_string(),
+            shell: "/bin/bash".to_string(),
+            current_dir: "/home/user".to_string(),
+            home_dir: "/home/user".to_string(),
+            cpu_model: "Intel Core i7".to_string(),
+            cpu_cores: 8,
+            total_memory_mb: 16384,
+            free_memory_mb: 8192,
+            directory_listing: "file1\nfile2".to_string(),
+            available_tools: vec!["git".to_string(), "jq".to_string()],
+            included_files: Vec::new(),
+            is_wsl: false,
+            online: None,
+            aliases: Vec::new(),
+            recent_commands: Vec::new(),
+            allowed_fields: None,
+            gathered_at: SystemTime::now(),
+            note_age_after: None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::test_utils::sample_context;
     use super::*;
+    use crate::cache::response_key;
+    use crate::generate::Strictness;
+    use crate::prompt::build_system_prompt_with;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
 
     #[test]
     fn test_system_context_creation() {
@@ -221,59 +601,319 @@ mod tests {
 
     #[test]
     fn test_environment_context_format() {
-        let context = SystemContext {
-            os_type: "linux".to_string(),
-            os_release: "20.04".to_string(),
-            platform: "unix".to_string(),
-            arch: "x86_64".to_string(),
-            shell: "/bin/bash".to_string(),
-            current_dir: "/home/user".to_string(),
-            home_dir: "/home/user".to_string(),
-            cpu_model: "Intel Core i7".to_string(),
-            cpu_cores: 8,
-            total_memory_mb: 16384,
-            free_memory_mb: 8192,
-            directory_listing: "file1\nfile2".to_string(),
-        };
+        let context = sample_context();
 
         let env_context = context.build_environment_context();
         assert!(env_context.contains("Operating System: linux 20.04"));
         assert!(env_context.contains("Shell: /bin/bash"));
         assert!(env_context.contains("CPU Info: Intel Core i7 (8 cores)"));
         assert!(env_context.contains("Total Memory: 16384 MB"));
+        assert!(env_context.contains("Available Tools: git, jq"));
     }
 
     #[test]
     fn test_full_context_includes_directory_listing() {
-        let context = SystemContext {
-            os_type: "linux".to_string(),
-            os_release: "20.04".to_string(),
-            platform: "unix".to_string(),
-            arch: "x86_64".to_string(),
-            shell: "/bin/bash".to_string(),
-            current_dir: "/home/user".to_string(),
-            home_dir: "/home/user".to_string(),
-            cpu_model: "Intel Core i7".to_string(),
-            cpu_cores: 8,
-            total_memory_mb: 16384,
-            free_memory_mb: 8192,
-            directory_listing: "file1\nfile2".to_string(),
-        };
+        let context = sample_context();
 
         let full_context = context.build_full_context();
         assert!(full_context.contains("Result of `ls -l` in working directory:"));
         assert!(full_context.contains("file1\nfile2"));
+        assert!(full_context.contains("--- BEGIN UNTRUSTED DATA: directory listing ---"));
+        assert!(!full_context.contains("Contents of included files"));
+
+        let context = SystemContext {
+            included_files: vec![IncludedFile {
+                path: "app.toml".to_string(),
+                content: "port = 8080".to_string(),
+            }],
+            ..sample_context()
+        };
+        let full_context = context.build_full_context();
+        assert!(
+            full_context.contains(
+                "Contents of included files:\n--- BEGIN UNTRUSTED DATA: included files ---"
+            )
+        );
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let hour = Duration::from_secs(3600);
+        let fresh = sample_context();
+        assert!(!fresh.is_stale(hour));
+
+        let old = SystemContext {
+            gathered_at: SystemTime::now() - 2 * hour,
+            ..sample_context()
+        };
+        assert!(old.is_stale(hour));
+        assert!(!old.is_stale(3 * hour));
+
+        // A timestamp in the future counts as fresh
+        let future = SystemContext {
+            gathered_at: SystemTime::now() + hour,
+            ..sample_context()
+        };
+        assert_eq!(future.age(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_age_note() {
+        let minute = Duration::from_secs(60);
+        let context = SystemContext {
+            gathered_at: SystemTime::now() - 125 * minute,
+            ..sample_context()
+        };
+        assert!(!context.build_environment_context().contains("Note: these"));
+
+        let noted = context.clone().noting_age_after(10 * minute);
+        assert!(noted.build_environment_context().contains(
+            "Free Memory: 8192 MB\nNote: these CPU and memory figures are from 2h 5m ago"
+        ));
+
+        let recent = context.noting_age_after(180 * minute);
+        assert!(!recent.build_environment_context().contains("Note: these"));
+
+        assert_eq!(format_age(Duration::from_secs(45)), "45s");
+        assert_eq!(format_age(12 * minute), "12m");
+    }
+
+    #[test]
+    fn test_detect_shell() {
+        assert_eq!(
+            detect_shell(Some("/bin/zsh"), None, None, false),
+            "/bin/zsh"
+        );
+        assert_eq!(detect_shell(None, Some("C:\\ps"), None, false), "unknown");
+
+        let comspec = Some("C:\\Windows\\system32\\cmd.exe");
+        assert_eq!(
+            detect_shell(Some("/usr/bin/bash"), Some("C:\\ps"), comspec, true),
+            "/usr/bin/bash"
+        );
+        assert_eq!(
+            detect_shell(None, Some("C:\\ps"), comspec, true),
+            "powershell"
+        );
+        assert_eq!(
+            detect_shell(Some(""), None, comspec, true),
+            "C:\\Windows\\system32\\cmd.exe"
+        );
+    }
+
+    #[test]
+    fn test_shell_override_reaches_environment_context() {
+        let options = ContextOptions {
+            shell: Some("fish".to_string()),
+            ..ContextOptions::default()
+        };
+        let context = SystemContext::gather_with(&options).unwrap();
+        assert!(
+            context
+                .build_environment_context()
+                .contains("Shell: fish\n")
+        );
+    }
+
+    #[test]
+    fn test_cache_key_is_stable_across_gathers() {
+        let key = |context: &SystemContext| {
+            let prompt = build_system_prompt_with(
+                &crate::system_prompt_template(None),
+                &context.without_volatile_fields(),
+                &HashMap::new(),
+                false,
+            );
+            response_key("openai", "gpt-4o", &prompt, "list files", Strictness::OFF)
+        };
+        let first = SystemContext::gather().unwrap();
+        let mut second = SystemContext::gather().unwrap();
+        // What a later run sees anyway
+        second.free_memory_mb += 1;
+        second
+            .directory_listing
+            .push_str("- 1 2024-01-01 00:00 new.txt\n");
+        second.gathered_at += Duration::from_secs(60);
+
+        assert_eq!(key(&first), key(&second));
+    }
+
+    #[test]
+    fn test_detect_wsl() {
+        let wsl2 = "Linux version 5.15.133.1-microsoft-standard-WSL2 (root@1c602f52c2e4) \
+                    (gcc (GCC) 11.2.0) #1 SMP Thu Oct 5 21:02:42 UTC 2023";
+        let wsl1 = "Linux version 4.4.0-19041-Microsoft (Microsoft@Microsoft.com) \
+                    (gcc version 5.4.0 (GCC) ) #1237-Microsoft Sat Sep 11 14:32:00 PST 2021";
+        let native = "Linux version 6.5.0-35-generic (buildd@lcy02-amd64-079) \
+                      (x86_64-linux-gnu-gcc-12 (Ubuntu 12.3.0-1ubuntu1~23.04) 12.3.0)";
+
+        assert!(detect_wsl(Some(wsl2), None));
+        assert!(detect_wsl(Some(wsl1), None));
+        assert!(!detect_wsl(Some(native), None));
+        assert!(!detect_wsl(None, None));
+        assert!(detect_wsl(None, Some("Ubuntu")));
+        assert!(!detect_wsl(Some(native), Some("")));
+    }
+
+    #[test]
+    fn test_wsl_note_in_context() {
+        let context = sample_context();
+        assert!(!context.build_environment_context().contains("WSL"));
+
+        let context = SystemContext {
+            is_wsl: true,
+            ..sample_context()
+        };
+        let env_context = context.build_environment_context();
+        assert!(env_context.contains("running under WSL"));
+        assert!(env_context.contains("wslpath"));
+        assert!(env_context.contains("/mnt/c"));
+    }
+
+    #[test]
+    fn test_restricted_context_fields() {
+        let full = sample_context().build_full_context();
+
+        let context =
+            sample_context().restricted_to(Some(&[ContextField::Os, ContextField::Tools]));
+        let restricted = context.build_full_context();
+        assert!(restricted.contains("Operating System: linux 20.04"));
+        assert!(restricted.contains("Available Tools: git, jq"));
+        assert!(!restricted.contains("Home Directory"));
+        assert!(!restricted.contains("CPU Info"));
+        assert!(!restricted.contains("file1"));
+
+        assert_eq!(
+            sample_context().restricted_to(None).build_full_context(),
+            full
+        );
+    }
+
+    #[test]
+    fn test_check_connectivity() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(check_connectivity(addr, Duration::from_secs(1)));
+
+        // Nothing listens on the port once the listener is gone
+        drop(listener);
+        assert!(!check_connectivity(addr, Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_connectivity_timeout_is_offline() {
+        // TEST-NET-1 is never routed, so this either times out or fails fast
+        let start = std::time::Instant::now();
+        let addr = "192.0.2.1:443".parse().unwrap();
+        assert!(!check_connectivity(addr, Duration::from_millis(100)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_network_status_in_context() {
+        let context = sample_context();
+        assert!(!context.build_environment_context().contains("Network:"));
+
+        let online = SystemContext {
+            online: Some(true),
+            ..sample_context()
+        };
+        assert!(
+            online
+                .build_environment_context()
+                .contains("Network: online\n")
+        );
+
+        let offline = SystemContext {
+            online: Some(false),
+            ..sample_context()
+        };
+        assert!(
+            offline
+                .build_environment_context()
+                .contains("Network: offline")
+        );
+    }
+
+    #[test]
+    fn test_aliases_in_full_context() {
+        assert!(!sample_context().build_full_context().contains("aliases"));
+
+        let context = SystemContext {
+            aliases: crate::aliases::parse_alias_output("alias gs='git status'\n"),
+            ..sample_context()
+        };
+        let full = context.build_full_context();
+        assert!(full.contains("User's shell aliases"));
+        assert!(full.contains("gs = git status\n"));
+
+        let restricted = context.restricted_to(Some(&[ContextField::Os]));
+        assert!(!restricted.build_full_context().contains("gs = git status"));
+    }
+
+    #[test]
+    fn test_history_in_full_context() {
+        assert!(!sample_context().build_full_context().contains("history"));
+
+        let context = SystemContext {
+            recent_commands: vec!["cd api".to_string(), "cargo test".to_string()],
+            ..sample_context()
+        };
+        let full = context.build_full_context();
+        assert!(full.contains("Commands the user ran recently"));
+        assert!(full.contains("cd api\ncargo test\n"));
+
+        let restricted = context.restricted_to(Some(&[ContextField::Aliases]));
+        assert!(!restricted.build_full_context().contains("cargo test"));
     }
 
     #[test]
     fn test_directory_listing_fallback() {
         // This test verifies that directory listing returns a meaningful error message
         // when the command fails, rather than panicking
-        let result = get_directory_listing();
+        let result =
+            get_directory_listing(DEFAULT_MAX_LISTING_ENTRIES, DEFAULT_CONTEXT_MAX_ENTRIES);
         // The result should either be Ok or contain an error message
         match result {
             Ok(listing) => assert!(!listing.is_empty()),
             Err(e) => assert!(!e.to_string().is_empty()),
         }
     }
+
+    fn make_tool(dir: &Path, name: &str) {
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_tool_in_appended_dir_is_detected() {
+        let dir = TempDir::new().unwrap();
+        make_tool(dir.path(), "my-custom-tool");
+
+        let extra = vec![dir.path().to_string_lossy().to_string()];
+        let dirs = tool_search_dirs(&extra);
+        assert_eq!(dirs.last(), Some(&dir.path().to_path_buf()));
+
+        let found = detect_tools(&["my-custom-tool", "not-a-real-tool-xyz"], &dirs);
+        assert_eq!(found, vec!["my-custom-tool".to_string()]);
+
+        // Without the appended directory the tool is not found
+        let found = detect_tools(&["my-custom-tool"], &tool_search_dirs(&[]));
+        assert!(found.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_executable_file_is_ignored() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("plain"), "data").unwrap();
+
+        let found = detect_tools(&["plain"], &[dir.path().to_path_buf()]);
+        assert!(found.is_empty());
+    }
 }
@@ -0,0 +1,292 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use crate::config::Config;
+use crate::providers::{AIProvider, GenerateOptions, ModelInfo, ProviderError, Quota};
+use crate::usage::TokenUsage;
+
+/// Words that open a compound command, which needs a closing keyword
+/// before it is complete.
+const COMPOUND_OPENERS: &[(&str, &str)] = &[
+    ("if", "fi"),
+    ("for", "done"),
+    ("while", "done"),
+    ("until", "done"),
+    ("select", "done"),
+    ("case", "esac"),
+];
+
+/// Returns the first line of streamed `text` once that line is followed by a
+/// newline and is a syntactically complete single-line command: quotes and
+/// brackets balanced, no trailing `|`, `&&`, `||` or `\`, no heredoc, and
+/// compound commands closed on the same line. `None` means keep reading.
+pub fn complete_command_line(text: &str) -> Option<&str> {
+    let (line, _) = text.split_once('\n')?;
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with("```") || line.contains("<<") {
+        return None;
+    }
+    if !is_balanced(line) {
+        return None;
+    }
+    if ["|", "&&", "\\"].iter().any(|end| line.ends_with(end)) {
+        return None;
+    }
+
+    let first_word = line.split_whitespace().next().unwrap_or_default();
+    if let Some((_, closer)) = COMPOUND_OPENERS
+        .iter()
+        .find(|(opener, _)| *opener == first_word)
+        && line.split([' ', ';']).next_back() != Some(closer)
+    {
+        return None;
+    }
+
+    Some(line)
+}
+
+/// True when quotes are closed and `()`, `{}`, `[]` pair up outside quotes.
+fn is_balanced(line: &str) -> bool {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut depth = 0i32;
+
+    for c in line.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"') | None, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '{' | '[') => depth += 1,
+            (None, ')' | '}' | ']') => depth -= 1,
+            (None, _) => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+
+    quote.is_none() && !escaped && depth == 0
+}
+
+/// Streams the command, forwarding chunks to `on_token`, and stops reading
+/// as soon as [`complete_command_line`] finds a complete command. Dropping
+/// the stream closes the HTTP response, so the model's trailing prose is
+/// neither shown nor downloaded.
+pub async fn stream_until_command(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    options: &GenerateOptions,
+    mut on_token: impl FnMut(&str),
+) -> Result<String, ProviderError> {
+    let mut stream = provider
+        .generate_command_stream(system_prompt, user_prompt, options)
+        .await?;
+
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        let start = text.len();
+        text.push_str(&chunk);
+
+        if let Some(line) = complete_command_line(&text) {
+            // Forward only up to the end of the command line
+            let end = text.find('\n').unwrap_or(text.len());
+            if end > start {
+                on_token(&text[start..end]);
+            }
+            return Ok(line.to_string());
+        }
+        on_token(&chunk);
+    }
+
+    Ok(text)
+}
+
+/// Uses the inner provider's stream and cuts it off after the first complete
+/// command (`--stop-at-command`), saving the tokens of any explanation the
+/// model adds after it.
+pub struct EarlyStopProvider {
+    inner: Box<dyn AIProvider>,
+}
+
+impl EarlyStopProvider {
+    pub fn new(inner: Box<dyn AIProvider>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl AIProvider for EarlyStopProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        stream_until_command(
+            self.inner.as_ref(),
+            system_prompt,
+            user_prompt,
+            options,
+            |_| {},
+        )
+        .await
+    }
+
+    /// Alternatives are sampled in full; there is no stream to cut short.
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> Result<Vec<String>, ProviderError> {
+        self.inner
+            .generate_commands(system_prompt, user_prompt, n, options)
+            .await
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(EarlyStopProvider::new(self.inner.clone_box()))
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+
+    #[test]
+    fn test_complete_command_line() {
+        assert_eq!(complete_command_line("ls -la\nThis lists"), Some("ls -la"));
+        assert_eq!(
+            complete_command_line("for f in *; do echo \"$f\"; done\n"),
+            Some("for f in *; do echo \"$f\"; done")
+        );
+        assert_eq!(
+            complete_command_line("find . -name '*.rs' | xargs wc -l\n\nExplanation"),
+            Some("find . -name '*.rs' | xargs wc -l")
+        );
+
+        // Not complete yet
+        assert_eq!(complete_command_line("ls -la"), None);
+        assert_eq!(complete_command_line("echo 'a\nb'"), None);
+        assert_eq!(complete_command_line("ls |\ngrep x"), None);
+        assert_eq!(complete_command_line("make \\\n  all"), None);
+        assert_eq!(complete_command_line("for f in *; do\n"), None);
+        assert_eq!(complete_command_line("cat <<EOF\nhi\nEOF\n"), None);
+        assert_eq!(complete_command_line("(cd src &&\n"), None);
+        assert_eq!(complete_command_line("```bash\nls\n```\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_is_cut_after_command() {
+        let provider = MockProvider::with_stream(&[
+            "du -sh",
+            " * | sort -h",
+            "\nThis shows",
+            " the size of each entry",
+            " sorted by size.",
+        ]);
+
+        let mut tokens = Vec::new();
+        let command = stream_until_command(
+            &provider,
+            "system",
+            "sizes",
+            &GenerateOptions::default(),
+            |token| tokens.push(token.to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(command, "du -sh * | sort -h");
+        assert_eq!(tokens, vec!["du -sh", " * | sort -h"]);
+    }
+
+    #[tokio::test]
+    async fn test_multiline_command_streams_to_the_end() {
+        let provider =
+            MockProvider::with_stream(&["for f in *.log; do\n", "  gzip \"$f\"\n", "done"]);
+
+        let command = stream_until_command(
+            &provider,
+            "system",
+            "compress",
+            &GenerateOptions::default(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(command, "for f in *.log; do\n  gzip \"$f\"\ndone");
+    }
+
+    #[tokio::test]
+    async fn test_early_stop_provider() {
+        let provider = EarlyStopProvider::new(Box::new(MockProvider::with_stream(&[
+            "git log --oneline -5\n",
+            "Shows the last five commits.",
+        ])));
+
+        let command = provider
+            .generate_command("system", "commits")
+            .await
+            .unwrap();
+        assert_eq!(command, "git log --oneline -5");
+
+        // Options such as --temperature no longer turn stopping early off
+        let options = GenerateOptions {
+            temperature: Some(0.2),
+            ..GenerateOptions::default()
+        };
+        let command = provider
+            .clone_box()
+            .generate_command_with("system", "commits", &options)
+            .await
+            .unwrap();
+        assert_eq!(command, "git log --oneline -5");
+    }
+}
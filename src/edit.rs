@@ -0,0 +1,130 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Lets the user change `initial` before it is run (`e` at the run
+/// prompt): in `$VISUAL` or `$EDITOR` when one is set, otherwise by typing
+/// a replacement line. Returns the edited command, trimmed; an empty result
+/// means the user cleared it.
+pub fn edit_command(initial: &str) -> io::Result<String> {
+    match editor_from_env() {
+        Some(editor) => edit_in_editor(&editor, initial),
+        None => edit_inline(initial, &mut io::stdin().lock(), &mut io::stderr()),
+    }
+}
+
+/// `$VISUAL`, then `$EDITOR`, ignoring empty values.
+fn editor_from_env() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+/// Writes `initial` to a temporary file, opens it with `editor` (a program
+/// plus optional arguments, e.g. `code --wait`) and reads it back once the
+/// editor exits successfully.
+pub fn edit_in_editor(editor: &str, initial: &str) -> io::Result<String> {
+    let path = create_temp_file(initial)?;
+    let edited = run_editor(editor, &path).and_then(|()| fs::read_to_string(&path));
+    let _ = fs::remove_file(&path);
+    Ok(edited?.trim().to_string())
+}
+
+fn run_editor(editor: &str, path: &Path) -> io::Result<()> {
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no editor given"))?;
+
+    let status = Command::new(program).args(parts).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{program} exited with {status}")))
+    }
+}
+
+/// A new file in the temp directory holding `contents`. The `.sh`
+/// extension gets editors to highlight it as shell.
+fn create_temp_file(contents: &str) -> io::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    let path =
+        std::env::temp_dir().join(format!("sh-aid-command-{}-{nanos}.sh", std::process::id()));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    writeln!(file, "{contents}")?;
+    Ok(path)
+}
+
+/// Shows `initial` and reads a replacement line. An empty answer keeps the
+/// command unchanged.
+pub fn edit_inline<R: BufRead, W: Write>(
+    initial: &str,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<String> {
+    write!(
+        output,
+        "Current command:\n{initial}\nNew command (Enter to keep it): "
+    )?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(match answer.trim() {
+        "" => initial.to_string(),
+        edited => edited.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_in_editor_round_trip() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A stub editor that appends a flag to the command in the file
+        let dir = tempfile::TempDir::new().unwrap();
+        let editor = dir.path().join("editor");
+        fs::write(
+            &editor,
+            "#!/bin/sh\ncmd=$(cat \"$1\")\nprintf '%s -la\\n' \"$cmd\" > \"$1\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let edited = edit_in_editor(&editor.display().to_string(), "ls").unwrap();
+        assert_eq!(edited, "ls -la");
+
+        let failing = edit_in_editor("false", "ls").unwrap_err();
+        assert!(failing.to_string().contains("false exited with"));
+    }
+
+    #[test]
+    fn test_edit_inline() {
+        let mut output = Vec::new();
+        let edited = edit_inline("ls", &mut Cursor::new("ls -la\n"), &mut output).unwrap();
+        assert_eq!(edited, "ls -la");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Current command:\nls\nNew command (Enter to keep it): "
+        );
+
+        let kept = edit_inline("ls", &mut Cursor::new("\n"), &mut Vec::new()).unwrap();
+        assert_eq!(kept, "ls");
+    }
+}
@@ -15,6 +15,82 @@ pub enum ShaidError {
 
     #[error("Failed to gather system context: {0}")]
     Context(String),
+
+    #[error("History error: {0}")]
+    History(String),
+
+    #[error("Refused by policy: {0}")]
+    Policy(String),
+
+    #[error("Usage error: {0}")]
+    Usage(String),
+}
+
+impl ShaidError {
+    /// True when stdout was closed by the reader, e.g. `sh-aid ... | head -1`.
+    /// That is a normal way for a pipeline to end, not a failure.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, ShaidError::Io(e) if e.kind() == std::io::ErrorKind::BrokenPipe)
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ShaidError>;
+
+/// Renders `error` followed by every `source()` level, one per line.
+///
+/// Wrappers like `Configuration error: {0}` already include their source's
+/// message, so a level whose text the previous line ends with is skipped.
+pub fn format_error_chain(error: &dyn std::error::Error) -> String {
+    let mut output = format!("Error: {error}");
+    let mut previous = error.to_string();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        let message = cause.to_string();
+        if !previous.ends_with(&message) {
+            output.push_str(&format!("\n  Caused by: {message}"));
+        }
+        previous = message;
+        source = cause.source();
+    }
+
+    output
+}
+
+/// Prints [`format_error_chain`] to stderr, for the CLI's error path.
+pub fn print_error_chain(error: &dyn std::error::Error) {
+    eprintln!("{}", format_error_chain(error));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::io;
+
+    #[test]
+    fn test_multi_level_chain_is_rendered() {
+        let io_error = io::Error::new(io::ErrorKind::PermissionDenied, "permission denied");
+        let config_error = Err::<(), _>(io_error)
+            .context("Failed to read config file: \"/etc/sh-aid/config.json\"")
+            .context("Failed to load configuration")
+            .unwrap_err();
+        let error = ShaidError::from(config_error);
+
+        assert_eq!(
+            format_error_chain(&error),
+            "Error: Configuration error: Failed to load configuration\n  \
+             Caused by: Failed to read config file: \"/etc/sh-aid/config.json\"\n  \
+             Caused by: permission denied"
+        );
+    }
+
+    #[test]
+    fn test_single_level_error() {
+        let error = ShaidError::History("No history entry at index 3".to_string());
+        assert_eq!(
+            format_error_chain(&error),
+            "Error: History error: No history entry at index 3"
+        );
+    }
+}
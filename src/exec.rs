@@ -0,0 +1,98 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::sandbox::SandboxTool;
+
+/// The process running `command` through the user's shell (`$SHELL -c` on
+/// Unix, `cmd /C` on Windows).
+pub fn shell_command(command: &str) -> Command {
+    let mut process = if cfg!(target_os = "windows") {
+        let mut process = Command::new("cmd");
+        process.arg("/C");
+        process
+    } else {
+        let mut process = Command::new(user_shell());
+        process.arg("-c");
+        process
+    };
+    process.arg(command);
+    process
+}
+
+/// Like [`shell_command`], but inside `sandbox` with a read-only filesystem
+/// apart from `writable` (`--sandbox`).
+pub fn sandboxed_command(sandbox: SandboxTool, command: &str, writable: &[PathBuf]) -> Command {
+    let argv = sandbox.wrap(&user_shell(), command, writable);
+    let mut process = Command::new(&argv[0]);
+    process.args(&argv[1..]);
+    process
+}
+
+/// Exit code and error output of a finished command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandOutput {
+    pub exit_code: Option<i32>,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs `process` with stdin and stdout inherited and its stderr both shown
+/// as it is written and captured (`--run-and-fix`).
+pub fn run_capturing_stderr(mut process: Command) -> io::Result<CommandOutput> {
+    let mut child = process.stderr(Stdio::piped()).spawn()?;
+
+    let mut captured = Vec::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let mut terminal = io::stderr();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = pipe.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            // Showing the output is best effort; capturing it is the point
+            let _ = terminal.write_all(&buffer[..read]);
+            captured.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    Ok(CommandOutput {
+        exit_code: child.wait()?.code(),
+        stderr: String::from_utf8_lossy(&captured).into_owned(),
+    })
+}
+
+fn user_shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_capturing_stderr() {
+        let mut process = Command::new("sh");
+        process.args(["-c", "echo fine; echo 'no such file' >&2; exit 3"]);
+        process.stdout(Stdio::null());
+
+        let output = run_capturing_stderr(process).unwrap();
+        assert_eq!(
+            output,
+            CommandOutput {
+                exit_code: Some(3),
+                stderr: "no such file\n".to_string(),
+            }
+        );
+        assert!(!output.success());
+    }
+}
@@ -0,0 +1,90 @@
+/// Appended to the system prompt for `--explain`. The response shape matches
+/// `--improve`: the command, a `---` line, then the explanation.
+pub const EXPLAIN_INSTRUCTION: &str = "Also explain the command for someone learning the shell. \
+Respond in this format and nothing else, without markdown fences around the command:
+<the command>
+---
+<a short plain-English explanation of what the command and its options do>";
+
+/// A command together with the model's explanation of it (`--explain`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedCommand {
+    pub command: String,
+    /// `None` when the model returned only the command.
+    pub explanation: Option<String>,
+}
+
+/// Adds [`EXPLAIN_INSTRUCTION`] to a rendered system prompt.
+pub fn with_explain_instruction(system_prompt: &str) -> String {
+    format!("{system_prompt}\n{EXPLAIN_INSTRUCTION}\n")
+}
+
+/// Splits an `--explain` response at its first `---` line into the command
+/// and the explanation. A response without the separator is all command.
+pub fn parse_explained_response(response: &str) -> GeneratedCommand {
+    let (command, explanation) = match response.lines().position(|line| line.trim() == "---") {
+        Some(split) => {
+            let lines: Vec<&str> = response.lines().collect();
+            (lines[..split].join("\n"), lines[split + 1..].join("\n"))
+        }
+        None => (response.to_string(), String::new()),
+    };
+
+    let explanation = explanation.trim();
+    GeneratedCommand {
+        command: command.trim().to_string(),
+        explanation: (!explanation.is_empty()).then(|| explanation.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_explained_response() {
+        let response = "du -sh * | sort -h\n---\n`du -sh` prints the size of each entry; \
+                        `sort -h` orders them by size.\n";
+        assert_eq!(
+            parse_explained_response(response),
+            GeneratedCommand {
+                command: "du -sh * | sort -h".to_string(),
+                explanation: Some(
+                    "`du -sh` prints the size of each entry; `sort -h` orders them by size."
+                        .to_string()
+                ),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_multiline_command_and_explanation() {
+        let response =
+            "for f in *.log; do\n  gzip \"$f\"\ndone\n---\n- loops over logs\n- compresses each\n";
+        let generated = parse_explained_response(response);
+        assert_eq!(generated.command, "for f in *.log; do\n  gzip \"$f\"\ndone");
+        assert_eq!(
+            generated.explanation.as_deref(),
+            Some("- loops over logs\n- compresses each")
+        );
+    }
+
+    #[test]
+    fn test_parse_response_without_explanation() {
+        assert_eq!(
+            parse_explained_response("ls -la\n"),
+            GeneratedCommand {
+                command: "ls -la".to_string(),
+                explanation: None,
+            }
+        );
+        assert_eq!(parse_explained_response("ls -la\n---\n").explanation, None);
+    }
+
+    #[test]
+    fn test_explain_instruction_is_appended() {
+        let prompt = with_explain_instruction("Base prompt");
+        assert!(prompt.starts_with("Base prompt\n"));
+        assert!(prompt.contains("\n---\n"));
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncludedFile {
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncludeLimits {
+    /// Files larger than this are skipped entirely.
+    pub max_file_bytes: u64,
+    /// Total content budget across all included files.
+    pub max_total_bytes: usize,
+}
+
+impl Default for IncludeLimits {
+    fn default() -> Self {
+        IncludeLimits {
+            max_file_bytes: 16 * 1024,
+            max_total_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Reads the text files matching `patterns` (relative to `base_dir`).
+///
+/// Binary and oversized files are skipped, and files stop being added once
+/// the total budget is used up. Paths are reported relative to `base_dir`.
+pub fn collect_files(
+    patterns: &[String],
+    base_dir: &Path,
+    limits: IncludeLimits,
+) -> Result<Vec<IncludedFile>> {
+    let mut files: Vec<IncludedFile> = Vec::new();
+    let mut total = 0usize;
+
+    for pattern in patterns {
+        let full_pattern = base_dir.join(pattern);
+        let matches = glob::glob(&full_pattern.to_string_lossy())
+            .with_context(|| format!("Invalid file glob: {pattern}"))?;
+
+        for path in matches.filter_map(|entry| entry.ok()) {
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            if !meta.is_file() || meta.len() > limits.max_file_bytes {
+                continue;
+            }
+
+            let display = path
+                .strip_prefix(base_dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            if files.iter().any(|f| f.path == display) {
+                continue;
+            }
+
+            let Some(content) = read_text_file(&path) else {
+                continue;
+            };
+            if total + content.len() > limits.max_total_bytes {
+                continue;
+            }
+
+            total += content.len();
+            files.push(IncludedFile {
+                path: display,
+                content,
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the file content when it looks like UTF-8 text without NUL bytes.
+fn read_text_file(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+pub fn format_included_files(files: &[IncludedFile]) -> String {
+    files
+        .iter()
+        .map(|file| {
+            format!(
+                "--- {} ---\n{}\n--- end {} ---\n",
+                file.path,
+                file.content.trim_end(),
+                file.path
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("app.toml"), "port = 8080\n").unwrap();
+        fs::write(dir.path().join("db.toml"), "url = \"postgres://\"\n").unwrap();
+        fs::write(dir.path().join("notes.md"), "# Notes\n").unwrap();
+        fs::write(
+            dir.path().join("image.toml"),
+            [0x89, b'P', b'N', b'G', 0, 1],
+        )
+        .unwrap();
+        dir
+    }
+
+    fn paths(files: &[IncludedFile]) -> Vec<&str> {
+        files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_glob_matching_skips_binary() {
+        let dir = setup();
+        let mut files = collect_files(
+            &["*.toml".to_string()],
+            dir.path(),
+            IncludeLimits::default(),
+        )
+        .unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert_eq!(paths(&files), vec!["app.toml", "db.toml"]);
+        assert_eq!(files[0].content, "port = 8080\n");
+    }
+
+    #[test]
+    fn test_duplicate_matches_are_included_once() {
+        let dir = setup();
+        let files = collect_files(
+            &["notes.md".to_string(), "*.md".to_string()],
+            dir.path(),
+            IncludeLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(paths(&files), vec!["notes.md"]);
+    }
+
+    #[test]
+    fn test_size_caps() {
+        let dir = setup();
+        fs::write(dir.path().join("big.md"), "x".repeat(100)).unwrap();
+
+        let limits = IncludeLimits {
+            max_file_bytes: 50,
+            max_total_bytes: 1024,
+        };
+        let files = collect_files(&["*.md".to_string()], dir.path(), limits).unwrap();
+        assert_eq!(paths(&files), vec!["notes.md"]);
+
+        // Total budget: only the first file fits
+        let limits = IncludeLimits {
+            max_file_bytes: 1024,
+            max_total_bytes: 15,
+        };
+        let files = collect_files(
+            &["app.toml".to_string(), "db.toml".to_string()],
+            dir.path(),
+            limits,
+        )
+        .unwrap();
+        assert_eq!(paths(&files), vec!["app.toml"]);
+    }
+
+    #[test]
+    fn test_invalid_glob_is_an_error() {
+        let dir = setup();
+        let result = collect_files(&["[".to_string()], dir.path(), IncludeLimits::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_format_included_files() {
+        let files = vec![IncludedFile {
+            path: "app.toml".to_string(),
+            content: "port = 8080\n".to_string(),
+        }];
+        assert_eq!(
+            format_included_files(&files),
+            "--- app.toml ---\nport = 8080\n--- end app.toml ---\n"
+        );
+    }
+}
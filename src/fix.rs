@@ -0,0 +1,234 @@
+use std::io;
+
+use crate::error::Result;
+use crate::exec::CommandOutput;
+use crate::generate::{Strictness, run};
+use crate::providers::AIProvider;
+
+/// How much of a failed command's error output goes back to the model. The
+/// end is kept, since that is where the error usually is.
+pub const MAX_FEEDBACK_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixOutcome {
+    /// `command` succeeded on try number `attempt` (1 is the original).
+    Succeeded { attempt: u32, command: String },
+    /// The corrected command of this attempt was not approved.
+    Declined { attempt: u32 },
+    /// Every attempt failed; the last one exited with `exit_code`.
+    GaveUp {
+        attempts: u32,
+        exit_code: Option<i32>,
+    },
+}
+
+/// The user prompt asking to correct `command`, which was generated for
+/// `request` and failed with `output`.
+pub fn build_fix_prompt(request: &str, command: &str, output: &CommandOutput) -> String {
+    let status = match output.exit_code {
+        Some(code) => format!("exit code {code}"),
+        None => "a signal".to_string(),
+    };
+    let stderr = output.stderr.trim();
+    let stderr = if stderr.is_empty() {
+        "(no error output)".to_string()
+    } else {
+        let skip = stderr.chars().count().saturating_sub(MAX_FEEDBACK_CHARS);
+        stderr.chars().skip(skip).collect()
+    };
+
+    format!(
+        "{request}\n\n\
+         This command was run for the request above and failed with {status}:\n\
+         {command}\n\n\
+         Its error output:\n\
+         {stderr}\n\n\
+         Return a corrected command that does what was asked."
+    )
+}
+
+/// Runs `command` (already approved) and, while it fails, sends its error
+/// output back to the model for a corrected command, up to `max_fixes`
+/// times. Every corrected command must be approved by `confirm`, which gets
+/// the attempt number, before `run` executes it.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_and_fix<C, R>(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    command: &str,
+    max_fixes: u32,
+    strictness: Strictness,
+    mut confirm: C,
+    mut run_command: R,
+) -> Result<FixOutcome>
+where
+    C: FnMut(u32, &str) -> bool,
+    R: FnMut(&str) -> io::Result<CommandOutput>,
+{
+    let mut command = command.to_string();
+    let mut attempt = 1;
+    loop {
+        let output = run_command(&command)?;
+        if output.success() {
+            return Ok(FixOutcome::Succeeded { attempt, command });
+        }
+        if attempt > max_fixes {
+            return Ok(FixOutcome::GaveUp {
+                attempts: attempt,
+                exit_code: output.exit_code,
+            });
+        }
+
+        let fix_prompt = build_fix_prompt(user_prompt, &command, &output);
+        command = run(provider, system_prompt, &fix_prompt, strictness)
+            .await?
+            .command;
+        attempt += 1;
+        if !confirm(attempt, &command) {
+            return Ok(FixOutcome::Declined { attempt });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+
+    fn failed(code: i32, stderr: &str) -> CommandOutput {
+        CommandOutput {
+            exit_code: Some(code),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    fn succeeded() -> CommandOutput {
+        failed(0, "")
+    }
+
+    #[test]
+    fn test_build_fix_prompt() {
+        let prompt = build_fix_prompt(
+            "count lines in rust files",
+            "wc -l **/*.rs",
+            &failed(1, "wc: '**/*.rs': No such file or directory\n"),
+        );
+        assert_eq!(
+            prompt,
+            "count lines in rust files\n\n\
+             This command was run for the request above and failed with exit code 1:\n\
+             wc -l **/*.rs\n\n\
+             Its error output:\n\
+             wc: '**/*.rs': No such file or directory\n\n\
+             Return a corrected command that does what was asked."
+        );
+
+        let long = format!("{}the real error", "x".repeat(MAX_FEEDBACK_CHARS));
+        let prompt = build_fix_prompt("r", "c", &failed(2, &long));
+        assert!(prompt.contains("the real error"));
+        assert!(!prompt.contains(&"x".repeat(MAX_FEEDBACK_CHARS)));
+    }
+
+    #[tokio::test]
+    async fn test_fail_then_fix() {
+        let provider = MockProvider::with_response("find . -name '*.rs' | xargs wc -l".to_string());
+        let mut outputs = vec![failed(1, "wc: '**/*.rs': No such file"), succeeded()].into_iter();
+        let mut ran = Vec::new();
+        let mut confirmed = Vec::new();
+
+        let outcome = run_and_fix(
+            &provider,
+            "system",
+            "count lines",
+            "wc -l **/*.rs",
+            3,
+            Strictness::OFF,
+            |attempt, command| {
+                confirmed.push((attempt, command.to_string()));
+                true
+            },
+            |command| {
+                ran.push(command.to_string());
+                Ok(outputs.next().unwrap())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcome,
+            FixOutcome::Succeeded {
+                attempt: 2,
+                command: "find . -name '*.rs' | xargs wc -l".to_string(),
+            }
+        );
+        assert_eq!(
+            ran,
+            vec!["wc -l **/*.rs", "find . -name '*.rs' | xargs wc -l"]
+        );
+        assert_eq!(
+            confirmed,
+            vec![(2, "find . -name '*.rs' | xargs wc -l".to_string())]
+        );
+        let (_, fix_prompt) = &provider.requests()[0];
+        assert!(fix_prompt.contains("No such file"));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_fixes() {
+        let provider = MockProvider::new();
+        let mut runs = 0;
+
+        let outcome = run_and_fix(
+            &provider,
+            "system",
+            "list",
+            "lss",
+            2,
+            Strictness::OFF,
+            |_, _| true,
+            |_| {
+                runs += 1;
+                Ok(failed(127, "command not found"))
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcome,
+            FixOutcome::GaveUp {
+                attempts: 3,
+                exit_code: Some(127),
+            }
+        );
+        assert_eq!(runs, 3);
+        assert_eq!(provider.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_declined_fix_is_not_run() {
+        let provider = MockProvider::with_response("rm -rf build".to_string());
+        let mut runs = 0;
+
+        let outcome = run_and_fix(
+            &provider,
+            "system",
+            "clean",
+            "make clean",
+            3,
+            Strictness::OFF,
+            |_, _| false,
+            |_| {
+                runs += 1;
+                Ok(failed(2, "No rule to make target 'clean'"))
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome, FixOutcome::Declined { attempt: 2 });
+        assert_eq!(runs, 1);
+    }
+}
@@ -0,0 +1,770 @@
+use futures::StreamExt;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::context::SystemContext;
+use crate::heredoc::HeredocTracker;
+use crate::prompt::{
+    build_system_prompt_with, estimate_tokens, render_system_prompt, with_target_shell,
+};
+use crate::providers::sanitize::sanitize_command;
+use crate::providers::{AIProvider, GenerateOptions, ProviderError};
+use crate::usage::TokenUsage;
+
+/// Structured outcome of a generation, for callers embedding sh-aid as a
+/// library. Nothing on this path writes to stdout; the CLI does the printing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandResult {
+    pub command: String,
+    pub provider: String,
+    pub model: String,
+    /// Rough size of the prompt that was sent (see [`estimate_tokens`]).
+    pub estimated_prompt_tokens: usize,
+    /// Tokens billed for the request(s) that produced the command, when the
+    /// provider reports them.
+    pub usage: Option<TokenUsage>,
+    /// The response stopped at the token limit, so the command may be cut
+    /// off.
+    pub truncated: bool,
+    /// The configured model, when it was unavailable and `model` is the
+    /// provider's default it fell back to.
+    pub fell_back_from: Option<String>,
+}
+
+/// Appended to the system prompt when retrying after an unusable response.
+pub const STRICT_REPROMPT: &str = "IMPORTANT: Return ONLY the shell command, nothing else. \
+No explanations, no markdown, no text before or after the command.";
+
+/// Reprompts allowed at strict level 2 when `strictRetries` is not set.
+pub const DEFAULT_STRICT_RETRIES: u32 = 3;
+
+/// How hard [`run`] works to get a clean single command out of a chatty
+/// model (`strictLevel`, `--strict`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strictness {
+    /// 0: the response is used as is. 1: prose and fences are stripped and
+    /// an unusable response is reprompted once. 2: reprompted up to
+    /// `retries` times.
+    pub level: u8,
+    pub retries: u32,
+    /// At level 0, reprompt once when the response is prose
+    /// (`retryOnInvalid`). The response itself is never rewritten.
+    pub retry_on_invalid: bool,
+}
+
+impl Strictness {
+    pub const OFF: Strictness = Strictness {
+        level: 0,
+        retries: 0,
+        retry_on_invalid: false,
+    };
+
+    /// The level from `--strict` when given (`cli_level` > 0), otherwise
+    /// `strictLevel`, otherwise 0. Prose stripping is opt-in; by default only
+    /// `retryOnInvalid` applies.
+    pub fn from_config(config: &Config, cli_level: u8) -> Self {
+        let level = match cli_level {
+            0 => config.strict_level.unwrap_or(0),
+            level => level.min(2),
+        };
+        Strictness {
+            level,
+            retries: config.strict_retries.unwrap_or(DEFAULT_STRICT_RETRIES),
+            retry_on_invalid: config.retry_on_invalid,
+        }
+    }
+
+    /// Reprompts allowed after an unusable response.
+    fn max_reprompts(&self) -> u32 {
+        match self.level {
+            0 => u32::from(self.retry_on_invalid),
+            1 => 1,
+            _ => self.retries,
+        }
+    }
+
+    /// Checks a response at this level: above 0 it is cleaned with
+    /// [`strip_prose`] first.
+    fn check(&self, response: &str) -> Result<String, ProviderError> {
+        match self.level {
+            0 => check_command_response(response),
+            _ => clean_command(response),
+        }
+    }
+}
+
+/// Lowercase openers of chatty answers that are not a command.
+const PROSE_OPENERS: &[&str] = &[
+    "sure",
+    "certainly",
+    "of course",
+    "here is",
+    "here's",
+    "you can",
+    "to do this",
+    "the following",
+    "the command",
+    "i can",
+    "i'm",
+    "i am",
+];
+
+/// Rejects responses that read like prose rather than a command, such as
+/// "Sure! You can use: ...". Only the opener phrases count; a trailing
+/// colon alone is valid shell (`scp notes.txt host:`).
+pub fn check_command_response(response: &str) -> Result<String, ProviderError> {
+    let first_line = response.lines().next().unwrap_or_default().trim();
+    if is_prose_line(first_line) {
+        return Err(ProviderError::InvalidResponse(format!(
+            "Response is prose, not a command: {first_line}"
+        )));
+    }
+
+    Ok(response.to_string())
+}
+
+fn is_prose_line(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    PROSE_OPENERS.iter().any(|opener| {
+        lower
+            .strip_prefix(opener)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', ',', '!', '.', ':']))
+    })
+}
+
+/// Drops a chatty lead-in ("Here's the command:"), code fences and any
+/// explanation after the command, which ends at the first blank line outside
+/// a heredoc. Pure prose comes back empty.
+pub fn strip_prose(response: &str) -> String {
+    let lines: Vec<&str> = response.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty() && !is_prose_line(line.trim()))
+        .unwrap_or(lines.len());
+
+    let command = sanitize_command(&lines[start..].join("\n"));
+    let mut heredoc = HeredocTracker::default();
+    command
+        .lines()
+        .take_while(|line| heredoc.in_body(line) || !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Strips `response` and rejects what is left if it is empty or still prose.
+fn clean_command(response: &str) -> Result<String, ProviderError> {
+    let command = strip_prose(response);
+    if command.is_empty() {
+        return Err(ProviderError::InvalidResponse(
+            "Response contains no command".to_string(),
+        ));
+    }
+    check_command_response(&command)
+}
+
+/// Sends the prompts to `provider` and returns the command along with what
+/// produced it. Above strictness level 0 the response is cleaned with
+/// [`strip_prose`]; at level 0 it is returned unchanged. An unusable one
+/// (empty, or prose instead of a command) is reprompted with
+/// [`STRICT_REPROMPT`] added to the system prompt, as often as `strictness`
+/// allows.
+pub async fn run(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    strictness: Strictness,
+) -> Result<CommandResult, ProviderError> {
+    let usage_before = provider.token_usage();
+    let mut truncated_before = provider.truncated_responses();
+    let response = provider.generate_command(system_prompt, user_prompt).await;
+
+    let command = if strictness.level == 0 && !strictness.retry_on_invalid {
+        response?
+    } else {
+        let strict_prompt = format!("{system_prompt}\n{STRICT_REPROMPT}\n");
+        let mut checked = response.and_then(|response| strictness.check(&response));
+        let mut reprompts = 0;
+        while matches!(checked, Err(ProviderError::InvalidResponse(_)))
+            && reprompts < strictness.max_reprompts()
+        {
+            reprompts += 1;
+            truncated_before = provider.truncated_responses();
+            checked = provider
+                .generate_command(&strict_prompt, user_prompt)
+                .await
+                .and_then(|response| strictness.check(&response));
+        }
+        checked?
+    };
+
+    Ok(CommandResult {
+        command,
+        provider: provider.get_provider_name().to_string(),
+        model: provider.get_model_info().name,
+        estimated_prompt_tokens: estimate_tokens(system_prompt) + estimate_tokens(user_prompt),
+        usage: usage_since(provider, usage_before),
+        truncated: provider.truncated_responses() > truncated_before,
+        fell_back_from: provider.fell_back_from(),
+    })
+}
+
+/// Like [`run`], but streams the response, calling `on_token` with each
+/// chunk as it arrives (`--stream`). The chunks are the raw model output;
+/// the returned command is the accumulated text after
+/// [`sanitize_command`]. Chunks cannot be taken back once shown, so there is
+/// no reprompting.
+pub async fn run_streaming(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    on_token: impl FnMut(&str),
+) -> Result<CommandResult, ProviderError> {
+    let usage_before = provider.token_usage();
+    let truncated_before = provider.truncated_responses();
+    let text = stream_command(provider, system_prompt, user_prompt, on_token).await?;
+    let command = sanitize_command(&text);
+    if command.is_empty() {
+        return Err(ProviderError::InvalidResponse(
+            "Empty command response".to_string(),
+        ));
+    }
+
+    Ok(CommandResult {
+        command,
+        provider: provider.get_provider_name().to_string(),
+        model: provider.get_model_info().name,
+        estimated_prompt_tokens: estimate_tokens(system_prompt) + estimate_tokens(user_prompt),
+        usage: usage_since(provider, usage_before),
+        truncated: provider.truncated_responses() > truncated_before,
+        fell_back_from: provider.fell_back_from(),
+    })
+}
+
+/// What `provider` reported using since it reported `before`; `None` when
+/// it reported nothing new.
+fn usage_since(provider: &dyn AIProvider, before: Option<TokenUsage>) -> Option<TokenUsage> {
+    provider
+        .token_usage()
+        .map(|total| total.since(before))
+        .filter(|usage| *usage != TokenUsage::default())
+}
+
+/// Generates a command for `prompt` with the configured provider, calling
+/// `on_token` with each chunk as it arrives. Returns the complete command.
+pub async fn generate_streaming(
+    config: &Config,
+    context: &SystemContext,
+    prompt: &str,
+    on_token: impl FnMut(&str),
+) -> crate::error::Result<String> {
+    let provider = crate::configured_provider(config)?;
+    let template = crate::system_prompt_template(config.system_prompt.as_deref());
+    let system_prompt = render_system_prompt(&template, context);
+    let result = run_streaming(provider.as_ref(), &system_prompt, prompt, on_token).await?;
+    Ok(result.command)
+}
+
+/// Drives `provider`'s stream, forwarding every chunk to `on_token` in order.
+pub async fn stream_command(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    mut on_token: impl FnMut(&str),
+) -> Result<String, ProviderError> {
+    let mut stream = provider
+        .generate_command_stream(system_prompt, user_prompt, &GenerateOptions::default())
+        .await?;
+
+    let mut command = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        on_token(&chunk);
+        command.push_str(&chunk);
+    }
+
+    Ok(command)
+}
+
+/// Generates up to `count` different commands for the request (`--count`).
+/// Fewer come back when the samples repeat each other.
+pub async fn run_alternatives(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    count: u32,
+) -> Result<Vec<CommandResult>, ProviderError> {
+    let usage_before = provider.token_usage();
+    let truncated_before = provider.truncated_responses();
+    let commands = provider
+        .generate_commands(
+            system_prompt,
+            user_prompt,
+            count,
+            &GenerateOptions::default(),
+        )
+        .await?;
+    let usage = usage_since(provider, usage_before);
+    let truncated = provider.truncated_responses() > truncated_before;
+    let fell_back_from = provider.fell_back_from();
+
+    let provider_name = provider.get_provider_name().to_string();
+    let model = provider.get_model_info().name;
+    let estimated_prompt_tokens = estimate_tokens(system_prompt) + estimate_tokens(user_prompt);
+    Ok(commands
+        .into_iter()
+        .map(|command| CommandResult {
+            command,
+            provider: provider_name.clone(),
+            model: model.clone(),
+            estimated_prompt_tokens,
+            usage,
+            truncated,
+            fell_back_from: fell_back_from.clone(),
+        })
+        .collect())
+}
+
+/// Numbers the alternatives from 1, indenting the continuation lines of
+/// multi-line commands under their first line.
+pub fn format_alternatives(commands: &[String]) -> String {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| {
+            let prefix = format!("{}. ", i + 1);
+            let indent = " ".repeat(prefix.len());
+            format!(
+                "{prefix}{}\n",
+                command.replace('\n', &format!("\n{indent}"))
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellVariant {
+    pub shell: String,
+    pub command: String,
+}
+
+/// Generates the command once per target shell. Each request sees the shell
+/// in its context and an explicit instruction to use that shell's syntax.
+pub async fn generate_for_shells(
+    provider: &dyn AIProvider,
+    context: &SystemContext,
+    template: &str,
+    vars: &HashMap<String, String>,
+    user_prompt: &str,
+    shells: &[String],
+    compact_context: bool,
+) -> Result<Vec<ShellVariant>, ProviderError> {
+    let mut variants = Vec::with_capacity(shells.len());
+
+    for shell in shells {
+        let shell_context = SystemContext {
+            shell: shell.clone(),
+            ..context.clone()
+        };
+        let system_prompt = with_target_shell(
+            &build_system_prompt_with(template, &shell_context, vars, compact_context),
+            shell,
+        );
+
+        let command = provider
+            .generate_command(&system_prompt, user_prompt)
+            .await?;
+        variants.push(ShellVariant {
+            shell: shell.clone(),
+            command,
+        });
+    }
+
+    Ok(variants)
+}
+
+/// Renders one labeled block per shell.
+pub fn format_shell_variants(variants: &[ShellVariant]) -> String {
+    variants
+        .iter()
+        .map(|variant| format!("# {}\n{}\n", variant.shell, variant.command))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_utils::sample_context;
+    use crate::prompt::DEFAULT_SYSTEM_PROMPT;
+    use crate::providers::test_utils::MockProvider;
+
+    /// The default: no stripping, one reprompt for prose.
+    const RETRY_ONLY: Strictness = Strictness {
+        level: 0,
+        retries: DEFAULT_STRICT_RETRIES,
+        retry_on_invalid: true,
+    };
+
+    const LENIENT: Strictness = Strictness {
+        level: 1,
+        retries: DEFAULT_STRICT_RETRIES,
+        retry_on_invalid: true,
+    };
+
+    #[tokio::test]
+    async fn test_run_returns_structured_result() {
+        let provider = MockProvider::with_response("du -sh *".to_string());
+
+        let result = run(&provider, "abcd", "efgh", LENIENT).await.unwrap();
+        assert_eq!(
+            result,
+            CommandResult {
+                command: "du -sh *".to_string(),
+                provider: "mock".to_string(),
+                model: "mock-model".to_string(),
+                estimated_prompt_tokens: 2,
+                usage: None,
+                truncated: false,
+                fell_back_from: None,
+            }
+        );
+        assert_eq!(provider.requests(), vec![("abcd".into(), "efgh".into())]);
+    }
+
+    #[test]
+    fn test_check_command_response() {
+        for prose in [
+            "Sure! Use `ls -la` to list files.",
+            "Here's the command you need",
+            "You can use find for that.",
+        ] {
+            assert!(
+                matches!(
+                    check_command_response(prose),
+                    Err(ProviderError::InvalidResponse(_))
+                ),
+                "{prose:?}"
+            );
+        }
+
+        for command in [
+            "ls -la",
+            "surefire-cli run",
+            "touch here.txt",
+            "find . -name '*:*'",
+            "scp notes.txt host:",
+            "rsync -a build/ deploy@host:",
+        ] {
+            assert_eq!(check_command_response(command).unwrap(), command);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prose_response_is_retried_with_strict_prompt() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Ok("Sure! You can list the files with ls -la.".to_string()));
+        provider.add_response(Ok("ls -la".to_string()));
+
+        let result = run(&provider, "system", "list files", RETRY_ONLY)
+            .await
+            .unwrap();
+        assert_eq!(result.command, "ls -la");
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].0, "system");
+        assert!(requests[1].0.starts_with("system\n"));
+        assert!(requests[1].0.contains(STRICT_REPROMPT));
+        assert_eq!(requests[1].1, "list files");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response_without_retry() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Err(ProviderError::InvalidResponse(
+            "Empty command response".to_string(),
+        )));
+        provider.add_response(Ok("ls -la".to_string()));
+
+        let result = run(&provider, "system", "list files", Strictness::OFF).await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+        assert_eq!(provider.requests().len(), 1);
+
+        // Other errors are never retried
+        let provider = MockProvider::with_error(ProviderError::RateLimitError("slow".to_string()));
+        let result = run(&provider, "system", "list files", LENIENT).await;
+        assert!(matches!(result, Err(ProviderError::RateLimitError(_))));
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_strip_prose() {
+        assert_eq!(
+            strip_prose("Here's the command:\n\n```bash\nls -la\n```\n\nThis lists all files."),
+            "ls -la"
+        );
+        assert_eq!(
+            strip_prose("du -sh * | sort -h\n\nThis shows the size of each entry."),
+            "du -sh * | sort -h"
+        );
+        assert_eq!(
+            strip_prose("for f in *.log; do\n  gzip \"$f\"\ndone"),
+            "for f in *.log; do\n  gzip \"$f\"\ndone"
+        );
+        assert_eq!(strip_prose("Sure! You can list files with ls."), "");
+
+        let heredoc = "cat <<EOF > notes.txt\nfirst\n\n    second\nEOF";
+        assert_eq!(
+            strip_prose(&format!(
+                "Here is one way:\n{heredoc}\n\nThis writes two lines."
+            )),
+            heredoc
+        );
+    }
+
+    fn chatty_provider(chatty_responses: usize) -> MockProvider {
+        let mut provider = MockProvider::new();
+        for _ in 0..chatty_responses {
+            provider.add_response(Ok("Sure! I can help with that.".to_string()));
+        }
+        provider.add_response(Ok("ls -la".to_string()));
+        provider
+    }
+
+    #[tokio::test]
+    async fn test_strict_level_0_returns_response_as_is() {
+        let provider = chatty_provider(1);
+        let result = run(&provider, "system", "list", Strictness::OFF)
+            .await
+            .unwrap();
+        assert_eq!(result.command, "Sure! I can help with that.");
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_default_keeps_the_response_unchanged() {
+        let response = "scp notes.txt host:\n\nThis copies the file.";
+        let provider = MockProvider::with_response(response.to_string());
+        let result = run(&provider, "system", "copy", RETRY_ONLY).await.unwrap();
+        assert_eq!(result.command, response);
+        assert_eq!(provider.requests().len(), 1);
+
+        let provider = chatty_provider(2);
+        let result = run(&provider, "system", "list", RETRY_ONLY).await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+        assert_eq!(provider.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_strict_level_1_strips_and_reprompts_once() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Ok(
+            "Here is the command:\n```\nls -la\n```\nIt lists files.".to_string(),
+        ));
+        let result = run(&provider, "system", "list", LENIENT).await.unwrap();
+        assert_eq!(result.command, "ls -la");
+        assert_eq!(provider.requests().len(), 1);
+
+        let provider = chatty_provider(2);
+        let result = run(&provider, "system", "list", LENIENT).await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+        assert_eq!(provider.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_strict_level_2_reprompts_up_to_retries() {
+        let strictness = Strictness {
+            level: 2,
+            retries: 3,
+            retry_on_invalid: false,
+        };
+
+        let provider = chatty_provider(3);
+        let result = run(&provider, "system", "list", strictness).await.unwrap();
+        assert_eq!(result.command, "ls -la");
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(
+            requests[1..]
+                .iter()
+                .all(|(system, _)| system.contains(STRICT_REPROMPT))
+        );
+
+        let provider = chatty_provider(4);
+        let result = run(&provider, "system", "list", strictness).await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+        assert_eq!(provider.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_strictness_from_config() {
+        let config = Config::default();
+        assert_eq!(Strictness::from_config(&config, 0), RETRY_ONLY);
+        assert_eq!(Strictness::from_config(&config, 1).level, 1);
+        assert_eq!(Strictness::from_config(&config, 2).level, 2);
+        assert_eq!(Strictness::from_config(&config, 5).level, 2);
+
+        let config = Config {
+            retry_on_invalid: false,
+            strict_retries: Some(5),
+            ..Config::default()
+        };
+        assert_eq!(
+            Strictness::from_config(&config, 0),
+            Strictness {
+                level: 0,
+                retries: 5,
+                retry_on_invalid: false,
+            }
+        );
+        let config = Config {
+            strict_level: Some(2),
+            ..config
+        };
+        assert_eq!(Strictness::from_config(&config, 0).level, 2);
+    }
+
+    #[tokio::test]
+    async fn test_stream_callback_sees_tokens_in_order() {
+        let provider = MockProvider::with_stream(&["find", " .", " -name", " '*.rs'"]);
+
+        let mut tokens = Vec::new();
+        let command = stream_command(&provider, "system", "find rust files", |token| {
+            tokens.push(token.to_string())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(tokens, vec!["find", " .", " -name", " '*.rs'"]);
+        assert_eq!(command, "find . -name '*.rs'");
+    }
+
+    #[tokio::test]
+    async fn test_streamed_text_is_sanitized_once_complete() {
+        let provider = MockProvider::with_stream(&["```bash\n", "git status", "\n```\n"]);
+
+        let mut tokens = Vec::new();
+        let result = run_streaming(&provider, "system", "status", |token| {
+            tokens.push(token.to_string())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(tokens, vec!["```bash\n", "git status", "\n```\n"]);
+        assert_eq!(result.command, "git status");
+
+        let provider = MockProvider::with_stream(&["```", "\n```"]);
+        let result = run_streaming(&provider, "system", "status", |_| {}).await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stream_falls_back_to_single_chunk() {
+        let provider = MockProvider::with_response("ls -la".to_string());
+
+        let mut tokens = Vec::new();
+        let command = stream_command(&provider, "system", "list", |token| {
+            tokens.push(token.to_string())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(tokens, vec!["ls -la"]);
+        assert_eq!(command, "ls -la");
+    }
+
+    #[tokio::test]
+    async fn test_each_shell_gets_its_own_command() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Ok("ls -a".to_string()));
+        provider.add_response(Ok("Get-ChildItem -Force".to_string()));
+
+        let shells = vec!["bash".to_string(), "powershell".to_string()];
+        let variants = generate_for_shells(
+            &provider,
+            &sample_context(),
+            DEFAULT_SYSTEM_PROMPT,
+            &HashMap::new(),
+            "list all files",
+            &shells,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            variants,
+            vec![
+                ShellVariant {
+                    shell: "bash".to_string(),
+                    command: "ls -a".to_string(),
+                },
+                ShellVariant {
+                    shell: "powershell".to_string(),
+                    command: "Get-ChildItem -Force".to_string(),
+                },
+            ]
+        );
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].0.contains("Shell: bash"));
+        assert!(requests[0].0.contains("for the bash shell"));
+        assert!(requests[1].0.contains("Shell: powershell"));
+        assert!(requests[1].0.contains("for the powershell shell"));
+
+        let output = format_shell_variants(&variants);
+        assert_eq!(
+            output,
+            "# bash\nls -a\n\n# powershell\nGet-ChildItem -Force\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_alternatives() {
+        let mut provider = MockProvider::new();
+        for response in ["ls -t", "ls -lt", "ls -t"] {
+            provider.add_response(Ok(response.to_string()));
+        }
+
+        let results = run_alternatives(&provider, "abcd", "efgh", 3)
+            .await
+            .unwrap();
+        let commands: Vec<&str> = results.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls -t", "ls -lt"]);
+        assert_eq!(results[1].model, "mock-model");
+        assert_eq!(results[1].estimated_prompt_tokens, 2);
+    }
+
+    #[test]
+    fn test_format_alternatives() {
+        let commands = vec![
+            "ls -t".to_string(),
+            "for f in *; do\n  echo \"$f\"\ndone".to_string(),
+        ];
+        assert_eq!(
+            format_alternatives(&commands),
+            "1. ls -t\n2. for f in *; do\n     echo \"$f\"\n   done\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_for_any_shell_is_returned() {
+        let provider = MockProvider::with_error(ProviderError::RateLimitError("slow down".into()));
+
+        let result = generate_for_shells(
+            &provider,
+            &sample_context(),
+            DEFAULT_SYSTEM_PROMPT,
+            &HashMap::new(),
+            "list all files",
+            &["fish".to_string()],
+            false,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ProviderError::RateLimitError(_))));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+
+/// A heredoc waiting for its body: the terminator word and whether it was
+/// opened with `<<-`, which allows the terminator to be indented with tabs.
+#[derive(Debug, Clone, PartialEq)]
+struct Delimiter {
+    word: String,
+    strip_tabs: bool,
+}
+
+/// Follows heredocs (`cat <<EOF ... EOF`) through a command line by line, so
+/// that cleanup passes can leave their bodies alone: blank lines, indentation,
+/// `#` lines and fences inside a body are content.
+#[derive(Debug, Default)]
+pub struct HeredocTracker {
+    pending: VecDeque<Delimiter>,
+}
+
+impl HeredocTracker {
+    /// Feeds the next line (without its newline). Returns true when the line
+    /// is part of a heredoc body, including the terminator line, and must be
+    /// kept verbatim.
+    pub fn in_body(&mut self, line: &str) -> bool {
+        if let Some(delimiter) = self.pending.front() {
+            let line = line.trim_end_matches('\r');
+            let candidate = if delimiter.strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if candidate == delimiter.word {
+                self.pending.pop_front();
+            }
+            return true;
+        }
+
+        self.pending.extend(parse_delimiters(line));
+        false
+    }
+}
+
+/// The heredocs a line opens, in order. `<<<` here-strings, quoted text,
+/// comments and shifts in arithmetic (`$((1<<4))`, `((x<<2))`) are skipped;
+/// quotes and backslashes are removed from the word, as the shell does when
+/// matching the terminator.
+fn parse_delimiters(line: &str) -> Vec<Delimiter> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut delimiters = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut arithmetic = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            } else if c == '\\' && q == '"' {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '\\' => i += 1,
+            '\'' | '"' => quote = Some(c),
+            '#' if i == 0 || chars[i - 1].is_whitespace() => break,
+            '(' if chars.get(i + 1) == Some(&'(') => {
+                arithmetic += 1;
+                i += 2;
+                continue;
+            }
+            ')' if arithmetic > 0 && chars.get(i + 1) == Some(&')') => {
+                arithmetic -= 1;
+                i += 2;
+                continue;
+            }
+            '<' if arithmetic == 0 && chars.get(i + 1) == Some(&'<') => {
+                if chars.get(i + 2) == Some(&'<') {
+                    i += 3;
+                    continue;
+                }
+
+                let mut j = i + 2;
+                let strip_tabs = chars.get(j) == Some(&'-');
+                if strip_tabs {
+                    j += 1;
+                }
+                while chars.get(j).is_some_and(|c| *c == ' ' || *c == '\t') {
+                    j += 1;
+                }
+
+                let mut word = String::new();
+                let mut word_quote: Option<char> = None;
+                while let Some(&c) = chars.get(j) {
+                    match word_quote {
+                        Some(q) if c == q => word_quote = None,
+                        Some(_) => word.push(c),
+                        None => match c {
+                            '\'' | '"' => word_quote = Some(c),
+                            '\\' => {}
+                            c if c.is_whitespace() || ";|&<>()".contains(c) => break,
+                            c => word.push(c),
+                        },
+                    }
+                    j += 1;
+                }
+
+                if is_delimiter_word(&word) {
+                    delimiters.push(Delimiter { word, strip_tabs });
+                }
+                i = j;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    delimiters
+}
+
+/// Terminator words sh-aid recognizes: `EOF`, `END_OF_TEXT`, `my-doc`...
+/// Anything else after `<<` (a number, an expansion) is far more likely a
+/// misread shift than a heredoc whose body would swallow the rest.
+fn is_delimiter_word(word: &str) -> bool {
+    word.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-.".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// For each line of `text`, whether it belongs to a heredoc body.
+    fn heredoc_body_lines(text: &str) -> Vec<bool> {
+        let mut tracker = HeredocTracker::default();
+        text.lines().map(|line| tracker.in_body(line)).collect()
+    }
+
+    fn delimiter(word: &str, strip_tabs: bool) -> Delimiter {
+        Delimiter {
+            word: word.to_string(),
+            strip_tabs,
+        }
+    }
+
+    #[test]
+    fn test_parse_delimiters() {
+        assert_eq!(
+            parse_delimiters("cat <<EOF > out.txt"),
+            vec![delimiter("EOF", false)]
+        );
+        assert_eq!(
+            parse_delimiters("cat <<'EOF'"),
+            vec![delimiter("EOF", false)]
+        );
+        assert_eq!(
+            parse_delimiters("cat <<-\"END\" | sort"),
+            vec![delimiter("END", true)]
+        );
+        assert_eq!(
+            parse_delimiters("cat << \\EOF"),
+            vec![delimiter("EOF", false)]
+        );
+        assert_eq!(
+            parse_delimiters("paste <<A <<B"),
+            vec![delimiter("A", false), delimiter("B", false)]
+        );
+
+        assert!(parse_delimiters("grep x <<< \"$text\"").is_empty());
+        assert!(parse_delimiters("echo '<<EOF'").is_empty());
+        assert!(parse_delimiters("ls # <<EOF").is_empty());
+    }
+
+    #[test]
+    fn test_arithmetic_shifts_are_not_heredocs() {
+        assert!(parse_delimiters("echo $((1<<4))").is_empty());
+        assert!(parse_delimiters("((x<<2)); echo $x").is_empty());
+        assert!(parse_delimiters("echo $(( (1 << 3) | 1 ))").is_empty());
+        assert!(parse_delimiters("cat <<$var").is_empty());
+        assert!(parse_delimiters("cat <<4").is_empty());
+        assert_eq!(
+            parse_delimiters("n=$((1<<4)); cat <<EOF"),
+            vec![delimiter("EOF", false)]
+        );
+
+        let text = "echo $((1<<4))
+ls
+
+EOF";
+        assert_eq!(heredoc_body_lines(text), vec![false, false, false, false]);
+    }
+
+    #[test]
+    fn test_body_lines() {
+        let text = "cat <<EOF > notes.md\n# Title\n\n  indented\nEOF\n# comment";
+        assert_eq!(
+            heredoc_body_lines(text),
+            vec![false, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn test_tab_indented_terminator_needs_dash() {
+        let text = "cat <<-EOF\n\tbody\n\tEOF\nls";
+        assert_eq!(heredoc_body_lines(text), vec![false, true, true, false]);
+
+        // Without `-` an indented terminator is still body
+        let text = "cat <<EOF\n\tEOF\nEOF\nls";
+        assert_eq!(heredoc_body_lines(text), vec![false, true, true, false]);
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::listing::format_utc;
+use crate::rotating_log::{RotatingWriter, RotationPolicy};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub prompt: String,
+    pub command: String,
+    pub provider: String,
+    pub model: String,
+    pub timestamp: u64,
+}
+
+pub fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("sh-aid").join("history.jsonl"))
+}
+
+/// Loads all entries from a JSONL history file. A missing file is an empty
+/// history and malformed lines are skipped rather than failing the whole load.
+pub fn load_entries(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Appends one entry to the JSONL history file, creating the file and its
+/// directory on first use. The file is rotated like a log with the default
+/// [`RotationPolicy`], which is not configurable: past 10 MB it moves to
+/// `history.jsonl.1`, and the three newest rotated files are kept but no
+/// longer searched.
+pub fn append_entry(path: &Path, entry: &HistoryEntry) -> io::Result<()> {
+    append_rotating(path, entry, RotationPolicy::default())
+}
+
+fn append_rotating(path: &Path, entry: &HistoryEntry, policy: RotationPolicy) -> io::Result<()> {
+    let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    let mut writer = RotatingWriter::open(path, policy)?;
+    // One write per entry, so a rotation never splits a line
+    writer.write_all(format!("{line}\n").as_bytes())?;
+    writer.flush()
+}
+
+/// The last `limit` entries, oldest first, each with its 1-based index (as
+/// used by `--rerun`) and UTC time.
+pub fn format_recent(entries: &[HistoryEntry], limit: usize) -> String {
+    let start = entries.len().saturating_sub(limit);
+    entries[start..]
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let time = format_utc(UNIX_EPOCH + Duration::from_secs(entry.timestamp));
+            format!(
+                "{:>4}  {time}  {}\n      {}\n",
+                start + i + 1,
+                entry.prompt,
+                entry.command
+            )
+        })
+        .collect()
+}
+
+/// Returns the entry at a 1-based history index, as printed by `--search`.
+pub fn resolve_index(entries: &[HistoryEntry], index: usize) -> Option<&HistoryEntry> {
+    index.checked_sub(1).and_then(|i| entries.get(i))
+}
+
+/// Fuzzy-searches prompts and commands, best matches first. Each result
+/// carries its 1-based history index; ties favour the most recent entry.
+pub fn search<'a>(entries: &'a [HistoryEntry], query: &str) -> Vec<(usize, &'a HistoryEntry)> {
+    let mut scored: Vec<(u32, usize, &HistoryEntry)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let score =
+                fuzzy_score(query, &entry.prompt).max(fuzzy_score(query, &entry.command))?;
+            Some((score, i + 1, entry))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    scored
+        .into_iter()
+        .map(|(_, index, entry)| (index, entry))
+        .collect()
+}
+
+/// Scores how well `query` matches `text` as a case-insensitive subsequence.
+///
+/// Consecutive characters and matches at word starts score higher, and a
+/// plain substring match gets a large bonus. Returns `None` when not all
+/// query characters can be found in order.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0u32;
+    let mut qi = 0;
+    let mut prev_match: Option<usize> = None;
+
+    for (ti, &c) in text.iter().enumerate() {
+        if qi == query.len() {
+            break;
+        }
+        if c != query[qi] {
+            continue;
+        }
+
+        score += 1;
+        if prev_match.is_some_and(|p| p + 1 == ti) {
+            score += 5;
+        }
+        if ti == 0 || !text[ti - 1].is_alphanumeric() {
+            score += 3;
+        }
+
+        prev_match = Some(ti);
+        qi += 1;
+    }
+
+    if qi < query.len() {
+        return None;
+    }
+
+    let needle: String = query.iter().collect();
+    let haystack: String = text.iter().collect();
+    if haystack.contains(&needle) {
+        score += 20;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(prompt: &str, command: &str) -> HistoryEntry {
+        HistoryEntry {
+            prompt: prompt.to_string(),
+            command: command.to_string(),
+            provider: "OpenAI".to_string(),
+            model: "gpt-4o".to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("lsa", "ls -la").is_some());
+        assert!(fuzzy_score("xyz", "ls -la").is_none());
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+
+        // Substring beats a scattered subsequence
+        assert!(fuzzy_score("disk", "show disk usage") > fuzzy_score("disk", "do i see keys"));
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let entries = vec![
+            entry("list files", "ls"),
+            entry("find large files", "find . -size +100M"),
+            entry("do it safely now", "true"),
+            entry("show disk usage", "du -sh ."),
+        ];
+
+        let results = search(&entries, "disk");
+        let indices: Vec<usize> = results.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices.first(), Some(&4));
+        assert!(!indices.contains(&1));
+
+        // Commands are searched too
+        let results = search(&entries, "-size");
+        assert_eq!(results[0].0, 2);
+    }
+
+    #[test]
+    fn test_search_ties_prefer_recent() {
+        let entries = vec![entry("list files", "ls"), entry("list files", "ls -1")];
+        let results = search(&entries, "list");
+        assert_eq!(results[0].0, 2);
+        assert_eq!(results[1].0, 1);
+    }
+
+    #[test]
+    fn test_resolve_index() {
+        let entries = vec![entry("a", "1"), entry("b", "2")];
+        assert_eq!(resolve_index(&entries, 1).unwrap().prompt, "a");
+        assert_eq!(resolve_index(&entries, 2).unwrap().prompt, "b");
+        assert!(resolve_index(&entries, 0).is_none());
+        assert!(resolve_index(&entries, 3).is_none());
+    }
+
+    #[test]
+    fn test_append_entry_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("nested").join("history.jsonl");
+
+        append_entry(&path, &entry("list files", "ls")).unwrap();
+        append_entry(&path, &entry("disk usage", "du -sh .")).unwrap();
+
+        let entries = load_entries(&path).unwrap();
+        assert_eq!(
+            entries,
+            vec![entry("list files", "ls"), entry("disk usage", "du -sh .")]
+        );
+    }
+
+    #[test]
+    fn test_history_file_is_rotated() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let policy = RotationPolicy {
+            max_bytes: 100,
+            keep: 1,
+        };
+
+        for (prompt, command) in [("list files", "ls"), ("disk usage", "du"), ("uptime", "w")] {
+            append_rotating(&path, &entry(prompt, command), policy).unwrap();
+        }
+
+        assert_eq!(load_entries(&path).unwrap(), vec![entry("uptime", "w")]);
+        let rotated = dir.path().join("history.jsonl.1");
+        assert_eq!(
+            load_entries(&rotated).unwrap(),
+            vec![entry("disk usage", "du")]
+        );
+        assert!(!dir.path().join("history.jsonl.2").exists());
+    }
+
+    #[test]
+    fn test_format_recent_keeps_history_indices() {
+        let mut entries = vec![entry("a", "1"), entry("b", "2"), entry("c", "3")];
+        entries[2].timestamp = 1_700_000_000;
+
+        assert_eq!(
+            format_recent(&entries, 2),
+            "   2  1970-01-01 00:00  b\n      2\n   3  2023-11-14 22:13  c\n      3\n"
+        );
+        assert_eq!(format_recent(&entries, 10).lines().count(), 6);
+        assert_eq!(format_recent(&[], 10), "");
+    }
+
+    #[test]
+    fn test_load_entries_skips_bad_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let good = serde_json::to_string(&entry("a", "1")).unwrap();
+        fs::write(&path, format!("{good}\nnot json\n\n{good}\n")).unwrap();
+
+        assert_eq!(load_entries(&path).unwrap().len(), 2);
+        assert!(
+            load_entries(&dir.path().join("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
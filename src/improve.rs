@@ -0,0 +1,192 @@
+use crate::markdown::{ExplainFormat, render_explanation};
+use crate::providers::{AIProvider, ProviderError};
+
+/// System prompt for `--improve`. The prompt argument is an existing command
+/// rather than a description.
+pub const IMPROVE_SYSTEM_PROMPT: &str = r#"You live in a developer's CLI, helping them improve shell commands they already use.
+You are given an existing command. Rewrite it to be faster, safer or more idiomatic (better flags, safer options, more suitable tooling)
+while preserving exactly what it does. If it is already good, return it unchanged.
+Respond in this format and nothing else:
+<the improved command on a single line>
+---
+- <one short line per change and why>
+
+--- ENVIRONMENT CONTEXT ---
+{context}
+--- END ENVIRONMENT CONTEXT ---
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Improvement {
+    pub command: String,
+    /// Short notes on what changed; empty when the model gave none.
+    pub notes: Vec<String>,
+}
+
+pub fn build_improve_user_prompt(command: &str) -> String {
+    format!("Improve this command:\n{}", command.trim())
+}
+
+pub async fn improve_command(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    command: &str,
+) -> Result<Improvement, ProviderError> {
+    let response = provider
+        .generate_command(system_prompt, &build_improve_user_prompt(command))
+        .await?;
+    parse_improvement(&response)
+}
+
+/// Splits the response into the command (before the `---` line) and the
+/// change notes after it. Markdown fences are ignored.
+pub fn parse_improvement(response: &str) -> Result<Improvement, ProviderError> {
+    let lines: Vec<&str> = response
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("```"))
+        .collect();
+
+    let split = lines
+        .iter()
+        .position(|line| *line == "---")
+        .unwrap_or(lines.len());
+
+    let command = lines[..split]
+        .iter()
+        .filter(|line| !line.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if command.is_empty() {
+        return Err(ProviderError::InvalidResponse(
+            "Improve response does not contain a command".to_string(),
+        ));
+    }
+
+    let notes = lines
+        .get(split + 1..)
+        .unwrap_or_default()
+        .iter()
+        .map(|line| line.trim_start_matches(['-', '*']).trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    Ok(Improvement { command, notes })
+}
+
+/// Diff-style rendering: the original and improved command followed by the
+/// notes, which are markdown rendered according to `notes_format`.
+pub fn format_improvement(
+    original: &str,
+    improvement: &Improvement,
+    notes_format: ExplainFormat,
+    is_tty: bool,
+) -> String {
+    let original = original.trim();
+    let mut output = if original == improvement.command {
+        format!("  {original}\n(no changes suggested)\n")
+    } else {
+        format!("- {original}\n+ {}\n", improvement.command)
+    };
+
+    if !improvement.notes.is_empty() {
+        let notes: String = improvement
+            .notes
+            .iter()
+            .map(|note| format!("* {note}\n"))
+            .collect();
+        output.push('\n');
+        output.push_str(&render_explanation(&notes, notes_format, is_tty));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+
+    #[test]
+    fn test_parse_improvement() {
+        let response =
+            "```\nrg -l TODO src\n---\n- rg is faster than grep -r\n- searches src only once\n```";
+        assert_eq!(
+            parse_improvement(response).unwrap(),
+            Improvement {
+                command: "rg -l TODO src".to_string(),
+                notes: vec![
+                    "rg is faster than grep -r".to_string(),
+                    "searches src only once".to_string(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_improvement_without_notes() {
+        let improvement = parse_improvement("ls -la\n").unwrap();
+        assert_eq!(improvement.command, "ls -la");
+        assert!(improvement.notes.is_empty());
+
+        assert!(matches!(
+            parse_improvement("\n---\n- nothing"),
+            Err(ProviderError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_improvement() {
+        let improvement = Improvement {
+            command: "rg -l TODO src".to_string(),
+            notes: vec!["rg is faster".to_string()],
+        };
+        assert_eq!(
+            format_improvement("grep -rl TODO src ", &improvement, ExplainFormat::Raw, true),
+            "- grep -rl TODO src\n+ rg -l TODO src\n\n* rg is faster\n"
+        );
+
+        let unchanged = Improvement {
+            command: "ls".to_string(),
+            notes: Vec::new(),
+        };
+        assert_eq!(
+            format_improvement("ls", &unchanged, ExplainFormat::Raw, true),
+            "  ls\n(no changes suggested)\n"
+        );
+
+        let pretty = format_improvement(
+            "grep -rl TODO src",
+            &improvement,
+            ExplainFormat::Pretty,
+            true,
+        );
+        assert_eq!(
+            pretty,
+            "- grep -rl TODO src\n+ rg -l TODO src\n\n• rg is faster\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_improve_prompt_construction() {
+        let provider = MockProvider::with_response("find . -name '*.log' -delete".to_string());
+
+        let improvement = improve_command(
+            &provider,
+            IMPROVE_SYSTEM_PROMPT,
+            "  find . -name '*.log' -exec rm {} \\;",
+        )
+        .await
+        .unwrap();
+        assert_eq!(improvement.command, "find . -name '*.log' -delete");
+
+        let requests = provider.requests();
+        assert!(requests[0].0.contains("preserving exactly what it does"));
+        assert_eq!(
+            requests[0].1,
+            "Improve this command:\nfind . -name '*.log' -exec rm {} \\;"
+        );
+    }
+}
@@ -1,4 +1,115 @@
+pub mod aliases;
+pub mod cache;
+pub mod cassette;
+pub mod clipboard;
+pub mod comments;
 pub mod config;
+pub mod confirm;
 pub mod context;
+pub mod early_stop;
+pub mod edit;
 pub mod error;
+pub mod exec;
+pub mod explain;
+pub mod file_context;
+pub mod fix;
+pub mod generate;
+pub mod heredoc;
+pub mod history;
+pub mod improve;
+pub mod listing;
+pub mod live_line;
+pub mod markdown;
+pub mod model_fallback;
+pub mod org_policy;
+pub mod output;
+pub mod plan;
+pub mod policy;
+pub mod probe;
+pub mod prompt;
 pub mod providers;
+pub mod rate_limit;
+pub mod redact;
+pub mod rotating_log;
+pub mod safety;
+pub mod sandbox;
+pub mod shell_env;
+pub mod shell_history;
+pub mod timing;
+pub mod usage;
+
+use crate::config::Config;
+use crate::context::SystemContext;
+use crate::generate::{Strictness, run};
+use crate::prompt::{DEFAULT_SYSTEM_PROMPT, render_system_prompt, with_context_placeholder};
+use crate::providers::{AIProvider, RetryProvider, create_provider};
+
+/// The provider `config` describes, retrying failed requests when
+/// `maxRetries` is set. The CLI adds its own wrappers on top.
+pub fn configured_provider(config: &Config) -> error::Result<Box<dyn AIProvider>> {
+    let mut provider = create_provider(config)?;
+    if let Some(retries) = config.max_retries.filter(|&retries| retries > 0) {
+        provider = Box::new(RetryProvider::new(provider, retries));
+    }
+    Ok(provider)
+}
+
+/// The system prompt template: `custom` (`systemPrompt`, `--prompt-template`)
+/// with a `{context}` placeholder added when it has none, otherwise the
+/// default prompt.
+pub fn system_prompt_template(custom: Option<&str>) -> String {
+    match custom {
+        Some(template) => with_context_placeholder(template),
+        None => DEFAULT_SYSTEM_PROMPT.to_string(),
+    }
+}
+
+/// Turns `prompt` into a shell command for `context`, as `sh-aid "prompt"`
+/// does without the flags: the provider and system prompt come from
+/// `config`, chatty responses are cleaned up according to `strictLevel`, and
+/// commands outside `allowedCommands` are refused.
+///
+/// Every call builds a new provider and HTTP client. To make many requests,
+/// create the provider once with [`configured_provider`] and call
+/// [`generate_with`], which reuses its pooled connections.
+///
+/// ```no_run
+/// # async fn example() -> sh_aid::error::Result<()> {
+/// use sh_aid::config::Config;
+/// use sh_aid::context::SystemContext;
+///
+/// let config = Config::load()?;
+/// let context = SystemContext::gather()?;
+/// let command = sh_aid::generate(&config, &context, "list files by size").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn generate(
+    config: &Config,
+    context: &SystemContext,
+    prompt: &str,
+) -> error::Result<String> {
+    let provider = configured_provider(config)?;
+    generate_with(provider.as_ref(), config, context, prompt).await
+}
+
+/// [`generate`] with a provider of the caller's choosing.
+pub async fn generate_with(
+    provider: &dyn AIProvider,
+    config: &Config,
+    context: &SystemContext,
+    prompt: &str,
+) -> error::Result<String> {
+    let template = system_prompt_template(config.system_prompt.as_deref());
+    let system_prompt = render_system_prompt(&template, context);
+
+    let result = run(
+        provider,
+        &system_prompt,
+        prompt,
+        Strictness::from_config(config, 0),
+    )
+    .await?;
+    policy::check_command(&result.command, &config.allowed_commands)?;
+    Ok(result.command)
+}
@@ -0,0 +1,279 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Renders `dir` like `ls -l`, but deterministically: one line per entry with
+/// type (`d`, `l` or `-`), size in bytes, modification time in UTC and name,
+/// directories first, then by name. Hidden entries are skipped, as `ls -l`
+/// does.
+///
+/// ```text
+/// d      4096 2024-05-01 12:30 src/
+/// -       812 2024-05-01 12:31 Cargo.toml
+/// l        10 2024-05-02 08:00 latest -> build/v2
+/// ```
+pub fn render_listing(dir: &Path) -> io::Result<String> {
+    render_listing_capped(dir, usize::MAX)
+}
+
+/// Entries shown in the context's directory listing unless configured
+/// otherwise (`contextMaxEntries`).
+pub const DEFAULT_CONTEXT_MAX_ENTRIES: usize = 100;
+
+/// Like [`render_listing`], but shows at most `max_shown` entries and ends
+/// with a `... (N more)` line for the rest. Metadata is only read for the
+/// entries shown.
+pub fn render_listing_capped(dir: &Path, max_shown: usize) -> io::Result<String> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = entry.file_type()?.is_dir();
+        entries.push((!is_dir, name, entry.path()));
+    }
+
+    if entries.is_empty() {
+        return Ok("(empty directory)\n".to_string());
+    }
+
+    entries.sort();
+    let hidden = entries.len().saturating_sub(max_shown);
+    entries.truncate(max_shown);
+
+    let mut rows = Vec::with_capacity(entries.len());
+    for (_, name, path) in entries {
+        let meta = path.symlink_metadata()?;
+        let file_type = meta.file_type();
+        let (kind, display) = if file_type.is_symlink() {
+            let target = fs::read_link(&path)
+                .map(|t| t.to_string_lossy().to_string())
+                .unwrap_or_else(|_| "?".to_string());
+            ('l', format!("{name} -> {target}"))
+        } else if file_type.is_dir() {
+            ('d', format!("{name}/"))
+        } else {
+            ('-', name)
+        };
+
+        let mtime = meta
+            .modified()
+            .map(format_utc)
+            .unwrap_or_else(|_| "-".repeat(16));
+        rows.push((kind, meta.len(), mtime, display));
+    }
+
+    let size_width = rows
+        .iter()
+        .map(|row| row.1.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let mut listing: String = rows
+        .iter()
+        .map(|(kind, size, mtime, display)| {
+            format!("{kind} {size:>size_width$} {mtime} {display}\n")
+        })
+        .collect();
+    if hidden > 0 {
+        listing.push_str(&format!("... ({} more)\n", group_thousands(hidden)));
+    }
+    Ok(listing)
+}
+
+/// Listings of directories with more entries than this are omitted unless
+/// configured otherwise (`maxListingEntries`).
+pub const DEFAULT_MAX_LISTING_ENTRIES: usize = 200;
+
+/// Like [`render_listing_capped`], but for directories with more than
+/// `max_entries` visible entries (think `node_modules` or `/usr/bin`) only a
+/// short note is returned. Entries are counted before any metadata is read.
+pub fn render_listing_guarded(
+    dir: &Path,
+    max_entries: usize,
+    max_shown: usize,
+) -> io::Result<String> {
+    let count = count_entries(dir)?;
+    if count > max_entries {
+        return Ok(format!(
+            "directory has {} entries; listing omitted\n",
+            group_thousands(count)
+        ));
+    }
+
+    render_listing_capped(dir, max_shown)
+}
+
+fn count_entries(dir: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        if !entry?.file_name().to_string_lossy().starts_with('.') {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut output = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            output.push(',');
+        }
+        output.push(c);
+    }
+    output
+}
+
+/// Formats a timestamp as `YYYY-MM-DD HH:MM` in UTC.
+pub(crate) fn format_utc(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(86_400);
+    let secs_of_day = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date
+/// (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn set_mtime(path: &Path, secs: u64) {
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_844), (2024, 5, 1));
+    }
+
+    #[test]
+    fn test_render_listing_columns() {
+        let dir = TempDir::new().unwrap();
+        let big = dir.path().join("big.bin");
+        let small = dir.path().join("a.txt");
+        fs::write(&big, vec![b'x'; 1234]).unwrap();
+        fs::write(&small, "hi").unwrap();
+        fs::write(dir.path().join(".hidden"), "secret").unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+
+        // 2024-05-01 12:30:00 UTC
+        set_mtime(&big, 1_714_566_600);
+        set_mtime(&small, 1_714_566_600 + 60);
+
+        let listing = render_listing(dir.path()).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("d "));
+        assert!(lines[0].ends_with(" src/"));
+        assert_eq!(lines[1], "-    2 2024-05-01 12:31 a.txt");
+        assert_eq!(lines[2], "- 1234 2024-05-01 12:30 big.bin");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_listing_symlink() {
+        let dir = TempDir::new().unwrap();
+        std::os::unix::fs::symlink("target/v2", dir.path().join("latest")).unwrap();
+
+        let listing = render_listing(dir.path()).unwrap();
+        assert!(listing.starts_with("l "));
+        assert!(listing.trim_end().ends_with("latest -> target/v2"));
+    }
+
+    #[test]
+    fn test_listing_guard_threshold() {
+        let dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            fs::write(dir.path().join(format!("f{i}")), "").unwrap();
+        }
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+
+        let listing = render_listing_guarded(dir.path(), 5, 100).unwrap();
+        assert_eq!(listing.lines().count(), 5);
+
+        let listing = render_listing_guarded(dir.path(), 4, 100).unwrap();
+        assert_eq!(listing, "directory has 5 entries; listing omitted\n");
+
+        // Under the threshold the cap still applies
+        let listing = render_listing_guarded(dir.path(), 5, 2).unwrap();
+        assert_eq!(listing.lines().count(), 3);
+        assert!(listing.ends_with("... (3 more)\n"));
+    }
+
+    #[test]
+    fn test_listing_cap_keeps_directories_first() {
+        let dir = TempDir::new().unwrap();
+        for name in ["b.txt", "a.txt", "c.txt", "d.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        for name in ["zeta", "alpha"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+        }
+
+        let listing = render_listing_capped(dir.path(), 3).unwrap();
+        let names: Vec<&str> = listing
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect();
+        assert_eq!(names, vec!["alpha/", "zeta/", "a.txt", "more)"]);
+        assert!(listing.ends_with("\n... (3 more)\n"));
+
+        let full = render_listing_capped(dir.path(), 6).unwrap();
+        assert_eq!(full.lines().count(), 6);
+        assert!(!full.contains("more)"));
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(5231), "5,231");
+        assert_eq!(group_thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_render_empty_directory() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(render_listing(dir.path()).unwrap(), "(empty directory)\n");
+    }
+}
@@ -0,0 +1,183 @@
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Width assumed when the terminal size cannot be determined.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// A single status line (spinner text or the tail of streamed output) that is
+/// redrawn in place. Tracks what was last drawn so that after a resize the
+/// old line, which may now wrap over several rows, is fully erased.
+#[derive(Debug)]
+pub struct LiveLine {
+    width: usize,
+    drawn_chars: usize,
+}
+
+impl LiveLine {
+    pub fn new(width: usize) -> Self {
+        LiveLine {
+            width: width.max(1),
+            drawn_chars: 0,
+        }
+    }
+
+    /// Returns the escape sequence that replaces the current line with `text`
+    /// at terminal width `width`. Text that does not fit is shown as `…` plus
+    /// its tail, so the line never wraps.
+    pub fn redraw(&mut self, text: &str, width: usize) -> String {
+        let width = width.max(1);
+        let mut output = self.erase(width);
+
+        let fitted = fit_tail(text, width.saturating_sub(1));
+        self.drawn_chars = fitted.chars().count();
+        self.width = width;
+        output.push_str(&fitted);
+        output
+    }
+
+    /// Returns the escape sequence that removes the line entirely.
+    pub fn clear(&mut self) -> String {
+        let output = self.erase(self.width);
+        self.drawn_chars = 0;
+        output
+    }
+
+    /// Moves to the first row the previous draw occupies at `width` and
+    /// clears to the end of the screen.
+    fn erase(&self, width: usize) -> String {
+        let rows = self.drawn_chars.div_ceil(width).max(1);
+        if rows > 1 {
+            format!("\x1b[{}A\r\x1b[J", rows - 1)
+        } else {
+            "\r\x1b[J".to_string()
+        }
+    }
+}
+
+/// Keeps the last characters of `text` (on one line) that fit in `max`
+/// columns, marking a cut with `…`.
+fn fit_tail(text: &str, max: usize) -> String {
+    let line = text.replace(['\n', '\r'], " ");
+    let count = line.chars().count();
+    if count <= max {
+        return line;
+    }
+    if max == 0 {
+        return String::new();
+    }
+
+    let tail: String = line.chars().skip(count - (max - 1)).collect();
+    format!("…{tail}")
+}
+
+/// The terminal's column count from `COLUMNS`, or `stty size` on Unix.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+    {
+        return Some(columns);
+    }
+
+    if !cfg!(unix) {
+        return None;
+    }
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::from(tty))
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    parse_stty_size(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the `rows columns` output of `stty size`.
+fn parse_stty_size(output: &str) -> Option<usize> {
+    output
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+        .filter(|columns| *columns > 0)
+}
+
+/// Set when the terminal was resized (`SIGWINCH`) since the last check, so
+/// the width is only queried again when it may have changed.
+#[derive(Debug, Clone, Default)]
+pub struct ResizeFlag(Arc<AtomicBool>);
+
+impl ResizeFlag {
+    /// Starts listening for `SIGWINCH` on the current tokio runtime. On other
+    /// platforms the flag is never set.
+    pub fn watch() -> Self {
+        let flag = ResizeFlag::default();
+
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            if let Ok(mut resizes) = signal(SignalKind::window_change()) {
+                let flag = flag.clone();
+                tokio::spawn(async move {
+                    while resizes.recv().await.is_some() {
+                        flag.0.store(true, Ordering::Relaxed);
+                    }
+                });
+            }
+        }
+
+        flag
+    }
+
+    /// Returns true once per resize.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redraw_fits_width() {
+        let mut line = LiveLine::new(20);
+        assert_eq!(line.redraw("Generating...", 20), "\r\x1b[JGenerating...");
+
+        // Streamed output longer than the line keeps its tail
+        assert_eq!(
+            line.redraw("find . -name '*.rs' -newer Cargo.toml", 20),
+            "\r\x1b[J… -newer Cargo.toml"
+        );
+    }
+
+    #[test]
+    fn test_redraw_after_shrink_clears_wrapped_rows() {
+        let mut line = LiveLine::new(40);
+        line.redraw(&"x".repeat(35), 40);
+
+        // At 10 columns the 35 drawn characters now span 4 rows
+        let output = line.redraw("spinner", 10);
+        assert_eq!(output, "\x1b[3A\r\x1b[Jspinner");
+
+        // Growing back never needs to move up
+        assert_eq!(line.redraw("spinner", 80), "\r\x1b[Jspinner");
+    }
+
+    #[test]
+    fn test_clear_and_newlines() {
+        let mut line = LiveLine::new(10);
+        assert_eq!(line.redraw("a\nb", 10), "\r\x1b[Ja b");
+        assert_eq!(line.clear(), "\r\x1b[J");
+    }
+
+    #[test]
+    fn test_parse_stty_size() {
+        assert_eq!(parse_stty_size("24 80\n"), Some(80));
+        assert_eq!(parse_stty_size("0 0\n"), None);
+        assert_eq!(parse_stty_size(""), None);
+    }
+}
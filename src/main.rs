@@ -1,42 +1,1271 @@
-use clap::Parser;
-use sh_aid::config::Config;
-use sh_aid::context::SystemContext;
-use sh_aid::error::Result;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use sh_aid::cache::{ResponseCache, now_unix, response_cache_path, response_key};
+use sh_aid::clipboard::copy_to_clipboard;
+use sh_aid::comments::strip_comments;
+use sh_aid::config::{
+    Config, ConfigIo, ConfigOverrides, ProviderType, find_env_file, get_config_path, load_env_file,
+};
+use sh_aid::confirm::{
+    RunAnswer, RunDecision, ask_choice, ask_run_or_edit, ask_typed_yes, ask_yes_no, decide_run,
+    describe_request, generate_if_confirmed,
+};
+use sh_aid::context::{ContextOptions, SystemContext, tool_search_dirs};
+use sh_aid::early_stop::EarlyStopProvider;
+use sh_aid::edit::edit_command;
+use sh_aid::error::{Result, ShaidError, print_error_chain};
+use sh_aid::exec::{run_capturing_stderr, sandboxed_command, shell_command};
+use sh_aid::explain::{parse_explained_response, with_explain_instruction};
+use sh_aid::fix::{FixOutcome, run_and_fix};
+use sh_aid::generate::{
+    CommandResult, Strictness, format_alternatives, format_shell_variants, generate_for_shells,
+    run_alternatives, run_streaming,
+};
+use sh_aid::history;
+use sh_aid::improve::{IMPROVE_SYSTEM_PROMPT, format_improvement, improve_command};
+use sh_aid::live_line::{DEFAULT_WIDTH, LiveLine, ResizeFlag, terminal_width};
+use sh_aid::markdown::{ExplainFormat, render_explanation};
+use sh_aid::output::{Console, Output, OutputFormat, Verbosity};
+use sh_aid::plan::{PlanOutcome, execute_plan, format_plan, generate_plan};
+use sh_aid::policy::{check_command, check_prompt_budget, find_never_execute};
+use sh_aid::probe::{probe_cache_path, resolve_api_root};
+use sh_aid::prompt::{
+    TokenBreakdown, build_system_prompt_with, compact_context, estimate_tokens,
+    merge_context_files, parse_template_var, render_template, resolve_prompt, with_extra_context,
+    with_target_shell,
+};
+use sh_aid::providers::{
+    DefaultOptionsProvider, GenerateOptions, create_provider, format_provider_list,
+    provider_metadata, tls_backend_report,
+};
+use sh_aid::redact::{omit_directory_listing, redact_context};
+use sh_aid::safety::{RiskLevel, classify_command};
+use sh_aid::sandbox::{detect_sandbox, unavailable_warning};
+use sh_aid::shell_env::{ShellKind, render_env, suggest_alias_name};
+use sh_aid::shell_history::DEFAULT_CONTEXT_HISTORY_LINES;
+use sh_aid::timing::{ApiTimer, TimedProvider, Timings};
+use sh_aid::usage::usage_summary;
+use sh_aid::{configured_provider, out, outln, system_prompt_template};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Instant;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The natural language prompt to convert to a shell command.
-    #[arg(required = true, num_args = 1..)]
+    /// Read from stdin when not given and stdin is piped. A prompt starting
+    /// with a subcommand name (`env`, `config`, `models`) is taken as a
+    /// prompt when it does not parse as that subcommand; put `--` before it
+    /// to be sure.
+    #[arg(num_args = 1..)]
     prompt: Vec<String>,
+
+    /// Fuzzy-search past prompts and commands and print matches with their index.
+    #[arg(long, value_name = "QUERY", conflicts_with = "rerun")]
+    search: Option<String>,
+
+    /// Print the last N generated commands (default 20) instead of generating.
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20")]
+    history: Option<usize>,
+
+    /// Generate again using the prompt of the history entry at this index.
+    #[arg(long, value_name = "INDEX")]
+    rerun: Option<usize>,
+
+    /// List the supported providers with their defaults and status.
+    #[arg(long)]
+    list_providers: bool,
+
+    /// Print the remaining credit of the configured API key, for providers
+    /// that report it (OpenRouter).
+    #[arg(long)]
+    quota: bool,
+
+    /// Print the estimated prompt tokens per section (context, system prompt,
+    /// user prompt) without calling the provider.
+    #[arg(long)]
+    count_tokens: bool,
+
+    /// Define a `{key}` placeholder for the prompt templates (repeatable).
+    #[arg(long = "template-var", value_name = "KEY=VALUE", value_parser = parse_template_var)]
+    template_vars: Vec<(String, String)>,
+
+    /// Run the generated command without asking for confirmation.
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Copy the command to the clipboard (pbcopy, wl-copy, xclip or clip)
+    /// instead of offering to run it; with --yes it is also run.
+    #[arg(short, long, conflicts_with_all = ["plan", "for_shells", "improve"])]
+    copy: bool,
+
+    /// Only print the command, never prompt or run it.
+    #[arg(short = 'n', long, conflicts_with_all = ["yes", "execute"])]
+    dry_run: bool,
+
+    /// Ask for confirmation before sending the request to the provider.
+    #[arg(long)]
+    confirm_request: bool,
+
+    /// Extra directory to search when probing for available tools (repeatable).
+    #[arg(long = "append-path", value_name = "DIR")]
+    append_path: Vec<String>,
+
+    /// Load environment variables (API keys etc.) from a .env file before
+    /// resolving config. Without `=PATH`, uses the nearest `.env` in the
+    /// current directory or its parents.
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true)]
+    env_file: Option<Option<PathBuf>>,
+
+    /// Generate the equivalent command for each of these shells (e.g. bash,powershell).
+    #[arg(long = "for", value_name = "SHELLS", value_delimiter = ',')]
+    for_shells: Vec<String>,
+
+    /// Generate for this shell (e.g. fish, powershell, cmd) instead of the
+    /// detected one.
+    #[arg(long, value_name = "NAME", conflicts_with = "for_shells")]
+    shell: Option<String>,
+
+    /// Ask for an ordered, multi-step plan instead of a single command.
+    #[arg(long, conflicts_with = "for_shells")]
+    plan: bool,
+
+    /// Run the plan's steps in order, confirming each one and stopping at the
+    /// first failure.
+    #[arg(long, requires = "plan")]
+    execute: bool,
+
+    /// Run commands in a sandbox with a read-only filesystem (bwrap or
+    /// firejail on Linux, sandbox-exec on macOS). Paths in `sandboxWritable`
+    /// stay writable. Without a sandbox tool the command runs normally, with
+    /// a warning.
+    #[arg(long, conflicts_with = "dry_run")]
+    sandbox: bool,
+
+    /// Skip the dangerous-command check that asks for a typed `yes` before
+    /// running commands such as `rm -rf /` or `curl ... | sh`.
+    #[arg(long)]
+    no_safety: bool,
+
+    /// Treat the prompt as an existing command and suggest an optimized
+    /// version with the same behavior.
+    #[arg(long, conflicts_with_all = ["plan", "for_shells"])]
+    improve: bool,
+
+    /// Stop reading the response once it holds a complete single-line
+    /// command, cutting off any explanation the model adds after it.
+    #[arg(long, conflicts_with_all = ["plan", "for_shells", "improve", "explain"])]
+    stop_at_command: bool,
+
+    /// When the command fails, send its error output back to the model for
+    /// a corrected command and run that. Each correction is confirmed unless
+    /// --yes is given.
+    #[arg(long, conflicts_with_all = ["dry_run", "plan", "for_shells", "improve"])]
+    run_and_fix: bool,
+
+    /// Corrections --run-and-fix asks for before giving up.
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "run_and_fix")]
+    max_fixes: u32,
+
+    /// Print the response to stderr as it is generated.
+    #[arg(long, conflicts_with_all = ["plan", "for_shells", "improve"])]
+    stream: bool,
+
+    /// Generate up to N different commands and pick the one to run by
+    /// number.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=10),
+        conflicts_with_all = ["plan", "for_shells", "improve", "stream", "explain"]
+    )]
+    count: u32,
+
+    /// Also ask for a short plain-English explanation of the command.
+    #[arg(short, long, conflicts_with_all = ["plan", "for_shells", "improve"])]
+    explain: bool,
+
+    /// How explanations are printed: raw markdown, or pretty (ANSI-styled,
+    /// terminals only).
+    #[arg(long, value_name = "raw|pretty", default_value = "raw")]
+    explain_format: ExplainFormat,
+
+    /// Print the command as is (`text`) or as a JSON object with the
+    /// command, provider, model and explanation (`json`), for scripts.
+    /// `json` also hides the progress messages unless `-v` is given, and
+    /// never runs the command (as with --dry-run).
+    #[arg(
+        long,
+        value_name = "text|json",
+        default_value = "text",
+        conflicts_with_all = ["plan", "for_shells", "improve", "count"]
+    )]
+    format: OutputFormat,
+
+    /// Compact the context (drop blank/repeated lines, shorten paths under the
+    /// home directory to `~/...`) to save prompt tokens.
+    #[arg(long)]
+    compact_context: bool,
+
+    /// Leave the directory listing out of the context, e.g. where file names
+    /// are private (`shareDirectoryListing: false` does this for every run).
+    #[arg(long)]
+    no_listing: bool,
+
+    /// Ask the provider even when the same prompt was answered in the same
+    /// context within `cacheTtlSeconds` (the cache is off unless it is set).
+    #[arg(long)]
+    no_cache: bool,
+
+    /// If the model is not found, retry once with the provider's default model.
+    #[arg(long)]
+    model_fallback: bool,
+
+    /// Probe the base URL's /v1/models and /models endpoints to find the API
+    /// root of an OpenAI-compatible gateway (cached per base URL).
+    #[arg(long)]
+    probe: bool,
+
+    /// Provider to use, overriding the config file and `SHAID_PROVIDER`.
+    #[arg(long)]
+    provider: Option<ProviderType>,
+
+    /// Model to use, overriding the config file and `SHAID_MODEL`.
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Sampling temperature for this run (default 0, the most deterministic).
+    #[arg(long, value_name = "T")]
+    temperature: Option<f32>,
+
+    /// Maximum number of tokens in the response (default 1024).
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<u32>,
+
+    /// Sampling seed, for providers that support reproducible output.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Check whether the machine is online and tell the model (adds up to
+    /// half a second).
+    #[arg(long)]
+    with_connectivity: bool,
+
+    /// Tell the model about your shell aliases so it can use them. Read from
+    /// `SHAID_ALIASES` (e.g. `SHAID_ALIASES="$(alias)"`) or else the shell's
+    /// rc file; secret-looking values are redacted.
+    #[arg(long)]
+    with_aliases: bool,
+
+    /// Tell the model the commands you ran recently, read from the bash or
+    /// zsh history file (`contextHistoryLines`, default 20); secret-looking
+    /// commands are redacted.
+    #[arg(long)]
+    with_history: bool,
+
+    /// After the command, print an alias definition for it (in the detected
+    /// shell's syntax) to stderr.
+    #[arg(long)]
+    suggest_alias: bool,
+
+    /// Remove trailing `# comments` from generated commands so they can be
+    /// run or copied as-is.
+    #[arg(long)]
+    strip_comments: bool,
+
+    /// Fight chatty models harder: once strips prose around the command and
+    /// reprompts once, twice reprompts until a clean command comes back (up
+    /// to `strictRetries` times). Without it, responses are used as they
+    /// come and only retried once when they are prose (`retryOnInvalid`).
+    #[arg(long, visible_alias = "no-trailing-explanation", action = clap::ArgAction::Count)]
+    strict: u8,
+
+    /// Report more on stderr: -v adds where the time went (context
+    /// gathering, provider calls, total), -vv request and response metadata.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Report nothing on stderr but warnings and questions; stdout only
+    /// ever holds the result.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Include the contents of small text files matching this glob in the context (repeatable).
+    #[arg(long, value_name = "GLOB", alias = "context-file-glob")]
+    include_file: Vec<String>,
+
+    /// Use the system prompt template in this file instead of `systemPrompt`
+    /// or the built-in one. `{context}` in it is replaced by the system
+    /// context, which is appended when the placeholder is missing.
+    #[arg(long, value_name = "PATH", conflicts_with = "improve")]
+    prompt_template: Option<PathBuf>,
+
+    /// Add notes from a markdown file to the system prompt (repeatable).
+    /// Later files append to sections of earlier ones with the same heading,
+    /// or replace them when the heading ends in `(override)`.
+    #[arg(long, value_name = "PATH")]
+    context_file: Vec<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print shell statements that set up sh-aid's environment, for
+    /// `eval "$(sh-aid env)"`.
+    Env {
+        /// Shell syntax to emit: bash, zsh, fish or powershell (default: detected from $SHELL).
+        #[arg(long)]
+        shell: Option<ShellKind>,
+    },
+    /// List the models the configured provider offers (`--provider` picks
+    /// another one).
+    Models,
+    /// View or change settings in the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the value of a setting, e.g. `model` or `baseUrl`.
+    Get { key: String },
+    /// Change a setting and save the config file. An empty value unsets
+    /// optional settings.
+    Set { key: String, value: String },
+    /// Print the path of the config file.
+    Path,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-    let user_prompt = args.prompt.join(" ");
+async fn main() {
+    if let Err(error) = run().await {
+        if error.is_broken_pipe() {
+            return;
+        }
+        print_error_chain(&error);
+        std::process::exit(1);
+    }
+}
+
+/// Parses the command line. When it fails to parse because the prompt
+/// starts with a subcommand name ("env vars that are set"), it is parsed
+/// again with `--` before that word, making it part of the prompt.
+fn parse_args() -> Args {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let error = match Args::try_parse_from(&argv) {
+        Ok(args) => return args,
+        Err(error) => error,
+    };
+    if matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp
+            | ErrorKind::DisplayVersion
+            | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+    ) {
+        error.exit();
+    }
+
+    let subcommands: Vec<String> = Args::command()
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    let Some(position) = argv.iter().skip(1).position(|arg| {
+        arg.to_str()
+            .is_some_and(|arg| arg == "--" || subcommands.iter().any(|name| name == arg))
+    }) else {
+        error.exit();
+    };
+    if argv[position + 1] == "--" {
+        error.exit();
+    }
+
+    let mut retry = argv;
+    retry.insert(position + 1, OsString::from("--"));
+    Args::try_parse_from(retry).unwrap_or_else(|_| error.exit())
+}
+
+async fn run() -> Result<()> {
+    let started = Instant::now();
+    let mut args = parse_args();
+    if args.format == OutputFormat::Json {
+        // JSON output is for scripts: the command is never run, so nothing
+        // but the object reaches stdout
+        if args.yes || args.run_and_fix {
+            return Err(ShaidError::Usage(
+                "--format json only prints the command; it cannot be combined with --yes or --run-and-fix"
+                    .to_string(),
+            ));
+        }
+        args.dry_run = true;
+    }
+
+    // Subcommands read the config too, so the env file comes first
+    load_env_flag(&args)?;
+
+    match &args.command {
+        Some(Command::Env { shell }) => return print_env(*shell, &args),
+        Some(Command::Config { action }) => return config_command(action),
+        Some(Command::Models) => return print_models(&args).await,
+        None => {}
+    }
 
-    println!("Loading configuration...");
-    let config = Config::load()?;
+    if args.list_providers {
+        out!("{}", format_provider_list())?;
+        return Ok(());
+    }
+
+    if let Some(query) = &args.search {
+        let entries = load_history()?;
+        for (index, entry) in history::search(&entries, query) {
+            outln!("{index:>4}  {}\n      {}", entry.prompt, entry.command)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(limit) = args.history {
+        out!("{}", history::format_recent(&load_history()?, limit))?;
+        return Ok(());
+    }
+
+    let raw_prompt = match args.rerun {
+        Some(index) => {
+            let entries = load_history()?;
+            history::resolve_index(&entries, index)
+                .map(|entry| entry.prompt.clone())
+                .ok_or_else(|| ShaidError::History(format!("No history entry at index {index}")))?
+        }
+        // `--quota` needs no prompt
+        None if args.quota => String::new(),
+        None => resolve_prompt(&args.prompt, io::stdin().is_terminal(), io::stdin().lock())?,
+    };
+
+    let template_vars: HashMap<String, String> = args.template_vars.iter().cloned().collect();
+    // Prompts like `docker ps --format '{{.Names}}'` are literal unless
+    // `--template-var` asks for rendering
+    let user_prompt = if template_vars.is_empty() {
+        raw_prompt.clone()
+    } else {
+        render_template(&raw_prompt, &template_vars)
+    };
+
+    let verbosity = Verbosity::from_flags(
+        args.verbose,
+        args.quiet || (args.format == OutputFormat::Json && args.verbose == 0),
+    );
+    let mut console = Console::stdio(verbosity);
+
+    console.banner("Loading configuration...")?;
+    // Notices such as a replaced corrupted config file are status lines too
+    let mut notice = Vec::new();
+    let loaded = Config::load_with(&config_flags(&args), &mut terminal_config_io(&mut notice));
+    for line in String::from_utf8_lossy(&notice).lines() {
+        console.banner(line)?;
+    }
+    let mut config = loaded?;
+    if args.confirm_request {
+        config.confirm_before_request = true;
+    }
+    if args.model_fallback {
+        config.model_fallback = true;
+    }
+    if !args.count_tokens {
+        config.validate()?;
+        if let Some(warning) = config.model_warning() {
+            eprintln!("Warning: {warning}");
+        }
+    }
+    if args.probe
+        && config.api_root.is_none()
+        && let Some(base_url) = config.base_url.clone()
+    {
+        let root = resolve_api_root(&config, &base_url, probe_cache_path().as_deref()).await?;
+        config.api_root = Some(root);
+    }
+    console.banner("Configuration loaded successfully.")?;
+    console.banner(&format!("Provider: {:?}", config.provider_type))?;
+    console.banner(&format!("Model: {}", config.model))?;
+    if let Some(origin) = config.api_key_origin {
+        console.info(&format!("API key: from {origin}"))?;
+    }
+    console.banner(&format!("TLS: {}", tls_backend_report(&config)))?;
+    if !config.policy_overrides.is_empty() {
+        console.banner(&format!(
+            "Set by organization policy: {}",
+            config.policy_overrides.join(", ")
+        ))?;
+    }
+
+    if args.quota {
+        let provider = create_provider(&config)?;
+        match provider.remaining_quota().await? {
+            Some(quota) => out!("{quota}")?,
+            None => outln!(
+                "{} does not report remaining quota.",
+                provider_metadata(&config.provider_type).name
+            )?,
+        }
+        return Ok(());
+    }
+
+    console.banner("\nGathering system context...")?;
+    let context_started = Instant::now();
+    let context_options = ContextOptions {
+        extra_path: config
+            .extra_path
+            .iter()
+            .chain(&args.append_path)
+            .cloned()
+            .collect(),
+        include_files: args.include_file.clone(),
+        max_listing_entries: config.max_listing_entries,
+        context_max_entries: config.context_max_entries,
+        check_connectivity: args.with_connectivity,
+        include_aliases: args.with_aliases,
+        history_lines: args.with_history.then(|| {
+            config
+                .context_history_lines
+                .unwrap_or(DEFAULT_CONTEXT_HISTORY_LINES)
+        }),
+        shell: args.shell.clone(),
+    };
+    let mut context = SystemContext::gather_with(&context_options)?
+        .restricted_to(config.allowed_context_fields());
+    redact_context(&mut context);
+    if args.no_listing || !config.share_directory_listing {
+        omit_directory_listing(&mut context);
+    }
+    let context_time = context_started.elapsed();
+    console.banner("System context gathered successfully.")?;
+
+    let context_files = args
+        .context_file
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| ShaidError::Context(format!("Failed to read {}: {e}", path.display())))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let custom_template = match &args.prompt_template {
+        Some(path) => Some(std::fs::read_to_string(path).map_err(|e| {
+            ShaidError::Context(format!(
+                "Failed to read prompt template {}: {e}",
+                path.display()
+            ))
+        })?),
+        None => config.system_prompt.clone(),
+    };
+    let base_template = if args.improve {
+        IMPROVE_SYSTEM_PROMPT.to_string()
+    } else {
+        system_prompt_template(custom_template.as_deref())
+    };
+    let template = with_extra_context(&base_template, &merge_context_files(&context_files));
+    let system_prompt_for = |context: &SystemContext| {
+        let mut prompt =
+            build_system_prompt_with(&template, context, &template_vars, args.compact_context);
+        if let Some(shell) = &args.shell {
+            prompt = with_target_shell(&prompt, shell);
+        }
+        if args.explain {
+            prompt = with_explain_instruction(&prompt);
+        }
+        prompt
+    };
+    let system_prompt = system_prompt_for(&context);
+
+    if args.count_tokens {
+        let mut context_text = context.build_full_context();
+        if args.compact_context {
+            context_text = compact_context(&context_text, &context.home_dir);
+        }
+        out!(
+            "{}",
+            TokenBreakdown::new(&system_prompt, &context_text, &user_prompt)
+        )?;
+        return Ok(());
+    }
+
+    check_prompt_budget(
+        estimate_tokens(&system_prompt) + estimate_tokens(&user_prompt),
+        config.max_prompt_tokens,
+    )?;
+
+    let mut provider = configured_provider(&config)?;
+    if args.stop_at_command {
+        provider = Box::new(EarlyStopProvider::new(provider));
+    }
+    let options = GenerateOptions {
+        temperature: args.temperature,
+        max_tokens: args.max_tokens,
+        seed: args.seed,
+        stop: Vec::new(),
+    };
+    // Sampling options ask for a different answer than the cached one
+    let use_cache = !args.no_cache && options == GenerateOptions::default();
+    if options != GenerateOptions::default() {
+        provider = Box::new(DefaultOptionsProvider::new(provider, options));
+    }
+    let api_timer = ApiTimer::default();
+    if verbosity >= Verbosity::Verbose {
+        provider = Box::new(TimedProvider::new(provider, api_timer.clone()));
+    }
+    console.debug(&format!(
+        "\nRequest: {}, model {}, ~{} prompt tokens (system {}, user {})",
+        provider.get_provider_name(),
+        provider.model_id(),
+        estimate_tokens(&system_prompt) + estimate_tokens(&user_prompt),
+        estimate_tokens(&system_prompt),
+        estimate_tokens(&user_prompt),
+    ))?;
+    if let Some(base_url) = &config.base_url {
+        console.debug(&format!("Endpoint: {base_url}"))?;
+    }
+    let timings = || {
+        format!(
+            "\nTiming: {}",
+            Timings {
+                context: context_time,
+                api: api_timer.total(),
+                total: started.elapsed(),
+            }
+        )
+    };
+
+    let confirm = |summary: &str| {
+        !config.confirm_before_request
+            || args.dry_run
+            || ask_yes_no(summary, &mut io::stdin().lock(), &mut io::stderr()).unwrap_or(false)
+    };
+
+    if !args.for_shells.is_empty() {
+        let summary = format!(
+            "{} ({} requests)",
+            describe_request(provider.as_ref(), &system_prompt, &user_prompt),
+            args.for_shells.len()
+        );
+        if !confirm(&summary) {
+            console.banner("Request cancelled.")?;
+            return Ok(());
+        }
+
+        let mut variants = generate_for_shells(
+            provider.as_ref(),
+            &context,
+            &template,
+            &template_vars,
+            &user_prompt,
+            &args.for_shells,
+            args.compact_context,
+        )
+        .await?;
+        for variant in &mut variants {
+            if args.strip_comments {
+                variant.command = strip_comments(&variant.command);
+            }
+            check_command(&variant.command, &config.allowed_commands)?;
+        }
+        out!("{}", format_shell_variants(&variants))?;
+        console.info(&timings())?;
+        return Ok(());
+    }
+
+    if args.improve {
+        if !confirm(&describe_request(
+            provider.as_ref(),
+            &system_prompt,
+            &user_prompt,
+        )) {
+            console.banner("Request cancelled.")?;
+            return Ok(());
+        }
+
+        let mut improvement =
+            improve_command(provider.as_ref(), &system_prompt, &user_prompt).await?;
+        if args.strip_comments {
+            improvement.command = strip_comments(&improvement.command);
+        }
+        check_command(&improvement.command, &config.allowed_commands)?;
+        out!(
+            "{}",
+            format_improvement(
+                &user_prompt,
+                &improvement,
+                args.explain_format,
+                io::stdout().is_terminal()
+            )
+        )?;
+        console.info(&timings())?;
+        return Ok(());
+    }
+
+    if args.plan {
+        if !confirm(&describe_request(
+            provider.as_ref(),
+            &system_prompt,
+            &user_prompt,
+        )) {
+            console.banner("Request cancelled.")?;
+            return Ok(());
+        }
+
+        let mut plan = generate_plan(provider.as_ref(), &system_prompt, &user_prompt).await?;
+        for step in &mut plan.steps {
+            if args.strip_comments {
+                step.command = strip_comments(&step.command);
+            }
+            check_command(&step.command, &config.allowed_commands)?;
+        }
+        out!("{}", format_plan(&plan))?;
+        console.info(&timings())?;
+
+        if args.execute {
+            let outcome = execute_plan(
+                &plan,
+                |number, step| {
+                    if let Some(pattern) = find_never_execute(&step.command, &config.never_execute)
+                    {
+                        eprintln!(
+                            "\nNot running step {number}: it matches neverExecute '{pattern}'."
+                        );
+                        return false;
+                    }
+                    confirm_run(
+                        &format!("\nRun step {number}: {}?", step.command),
+                        command_risk(&step.command, args.no_safety),
+                    )
+                },
+                |command| run_command(command, args.sandbox, &config),
+            )?;
+            match outcome {
+                PlanOutcome::Completed => console.banner("\nAll steps completed.")?,
+                PlanOutcome::Declined { step } => {
+                    console.banner(&format!("Stopped before step {step}."))?
+                }
+                PlanOutcome::Failed { step, exit_code } => {
+                    let code = exit_code.map_or("a signal".to_string(), |c| format!("code {c}"));
+                    console.banner(&format!("Step {step} failed with {code}; stopping."))?;
+                    std::process::exit(exit_code.unwrap_or(1));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let mut picked = false;
+    let generated = if args.stream {
+        if confirm(&describe_request(
+            provider.as_ref(),
+            &system_prompt,
+            &user_prompt,
+        )) {
+            // On a terminal the response so far is one line redrawn in place,
+            // refitted when the window is resized; elsewhere it is copied out
+            let preview = io::stderr().is_terminal();
+            let resized = if preview {
+                ResizeFlag::watch()
+            } else {
+                eprintln!();
+                ResizeFlag::default()
+            };
+            let mut width = terminal_width().unwrap_or(DEFAULT_WIDTH);
+            let mut line = LiveLine::new(width);
+            let mut text = String::new();
+            let result = run_streaming(provider.as_ref(), &system_prompt, &user_prompt, |chunk| {
+                if !preview {
+                    eprint!("{chunk}");
+                    return;
+                }
+                if resized.take() {
+                    width = terminal_width().unwrap_or(width);
+                }
+                text.push_str(chunk);
+                eprint!("{}", line.redraw(&text, width));
+            })
+            .await;
+            if preview {
+                eprint!("{}", line.clear());
+            } else {
+                eprintln!();
+            }
+            Some(result?)
+        } else {
+            None
+        }
+    } else if args.count > 1 {
+        if !confirm(&describe_request(
+            provider.as_ref(),
+            &system_prompt,
+            &user_prompt,
+        )) {
+            console.banner("Request cancelled.")?;
+            return Ok(());
+        }
+
+        let mut results =
+            run_alternatives(provider.as_ref(), &system_prompt, &user_prompt, args.count).await?;
+        for result in &mut results {
+            if args.strip_comments {
+                result.command = strip_comments(&result.command);
+            }
+            check_command(&result.command, &config.allowed_commands)?;
+        }
+
+        if results.len() == 1 {
+            results.pop()
+        } else {
+            let commands: Vec<String> = results.iter().map(|r| r.command.clone()).collect();
+            console.command(format_alternatives(&commands).trim_end())?;
+            if args.dry_run || !io::stdin().is_terminal() {
+                console.info(&timings())?;
+                return Ok(());
+            }
+
+            let choice = ask_choice(
+                "\nRun which command?",
+                results.len(),
+                &mut io::stdin().lock(),
+                &mut io::stderr(),
+            )
+            .unwrap_or(None);
+            picked = choice.is_some();
+            choice.map(|index| results.swap_remove(index))
+        }
+    } else {
+        // Off unless `cacheTtlSeconds` is set
+        let cache_ttl = config.cache_ttl_seconds.unwrap_or(0);
+        let cache_path = response_cache_path().filter(|_| use_cache && cache_ttl > 0);
+        let strictness = Strictness::from_config(&config, args.strict);
+        // Free memory and listing times would make every key unique
+        let key = response_key(
+            provider.get_provider_name(),
+            provider.model_id(),
+            &system_prompt_for(&context.without_volatile_fields()),
+            &user_prompt,
+            strictness,
+        );
+        let mut cache = cache_path
+            .as_deref()
+            .map(ResponseCache::load)
+            .unwrap_or_default();
+
+        match cache.get(&key, cache_ttl, now_unix()) {
+            Some(command) => {
+                console.info("\nUsing the cached response (--no-cache to ask again).")?;
+                Some(CommandResult {
+                    command: command.to_string(),
+                    provider: provider.get_provider_name().to_string(),
+                    model: provider.get_model_info().name,
+                    estimated_prompt_tokens: estimate_tokens(&system_prompt)
+                        + estimate_tokens(&user_prompt),
+                    usage: None,
+                    truncated: false,
+                    fell_back_from: None,
+                })
+            }
+            None => {
+                let generated = generate_if_confirmed(
+                    provider.as_ref(),
+                    &system_prompt,
+                    &user_prompt,
+                    strictness,
+                    confirm,
+                )
+                .await?;
+                if let (Some(result), Some(path)) = (&generated, &cache_path) {
+                    cache.insert(&key, &result.command, cache_ttl, now_unix());
+                    // The cache only saves a request next time; failing to
+                    // write it is fine
+                    let _ = cache.save(path);
+                }
+                generated
+            }
+        }
+    };
+
+    match generated {
+        Some(mut result) => {
+            let mut explanation = None;
+            if args.explain {
+                let generated = parse_explained_response(&result.command);
+                result.command = generated.command;
+                explanation = generated.explanation;
+            }
+            if args.strip_comments {
+                result.command = strip_comments(&result.command);
+            }
+            check_command(&result.command, &config.allowed_commands)?;
+            if let Some(model) = &result.fell_back_from {
+                console.banner(&format!(
+                    "Warning: model '{model}' is not available; used '{}' instead.",
+                    result.model
+                ))?;
+            }
+            if result.truncated {
+                console.banner(
+                    "Warning: the response reached the token limit and the command may be cut off; \
+                     raise it with --max-tokens.",
+                )?;
+            }
+            console.result(&Output::new(&result, explanation.as_deref()), args.format)?;
+            console.debug(&format!(
+                "\nResponse: {} from {}, model {}, {} lines, {} characters",
+                if explanation.is_some() {
+                    "command and explanation"
+                } else {
+                    "command"
+                },
+                result.provider,
+                result.model,
+                result.command.lines().count(),
+                result.command.chars().count(),
+            ))?;
+            if let Some(usage) = &result.usage {
+                console.info(&format!("\n{}", usage_summary(&result.model, usage)))?;
+            }
+            console.info(&timings())?;
+            record_history(&raw_prompt, &result);
+            if let Some(explanation) = explanation
+                .as_ref()
+                .filter(|_| args.format == OutputFormat::Text)
+            {
+                let rendered = render_explanation(
+                    explanation,
+                    args.explain_format,
+                    io::stderr().is_terminal(),
+                );
+                eprintln!("\n{}", rendered.trim_end());
+            }
+            if args.suggest_alias {
+                let shell = ShellKind::detect(
+                    args.shell
+                        .clone()
+                        .or_else(|| std::env::var("SHELL").ok())
+                        .as_deref(),
+                );
+                let name = suggest_alias_name(&result.command);
+                eprintln!("\n{}", shell.alias(&name, &result.command));
+            }
+
+            if args.copy {
+                match copy_to_clipboard(&result.command, &tool_search_dirs(&config.extra_path)) {
+                    Ok(_) => console.banner("\nCopied to the clipboard.")?,
+                    Err(e) => eprintln!("\nWarning: not copied to the clipboard: {e}"),
+                }
+            }
+
+            if args.dry_run || (args.copy && !args.yes) {
+                return Ok(());
+            }
+
+            // Without a terminal to answer on, only print the command.
+            // Picking an alternative counts as a yes. Dangerous commands
+            // need a typed `yes` even with --yes. An edited command is
+            // checked and asked about again.
+            let decision = loop {
+                let risk = command_risk(&result.command, args.no_safety);
+                let mut wants_edit = false;
+                let decision = decide_run(
+                    &result.command,
+                    &config.never_execute,
+                    (args.yes || picked) && risk < RiskLevel::Dangerous,
+                    || {
+                        io::stdin().is_terminal()
+                            && match confirm_run_or_edit("\nRun this command?", risk) {
+                                RunAnswer::Yes => true,
+                                RunAnswer::Edit => {
+                                    wants_edit = true;
+                                    false
+                                }
+                                RunAnswer::No => false,
+                            }
+                    },
+                );
+                if !wants_edit {
+                    break decision;
+                }
+
+                match edit_command(&result.command) {
+                    Ok(edited) if edited.is_empty() => break RunDecision::Declined,
+                    Ok(edited) => {
+                        check_command(&edited, &config.allowed_commands)?;
+                        eprintln!("\n{edited}");
+                        result.command = edited;
+                    }
+                    Err(e) => eprintln!("\nWarning: could not edit the command: {e}"),
+                }
+            };
+            match decision {
+                RunDecision::Run => {
+                    if !args.run_and_fix {
+                        let exit_code = run_command(&result.command, args.sandbox, &config)?;
+                        if exit_code != Some(0) {
+                            std::process::exit(exit_code.unwrap_or(1));
+                        }
+                        return Ok(());
+                    }
+
+                    let outcome = run_and_fix(
+                        provider.as_ref(),
+                        &system_prompt,
+                        &user_prompt,
+                        &result.command,
+                        args.max_fixes,
+                        Strictness::from_config(&config, args.strict),
+                        |attempt, command| {
+                            eprintln!("\nAttempt {attempt}, corrected command:\n{command}");
+                            if let Err(e) = check_command(command, &config.allowed_commands) {
+                                eprintln!("Not running it: {e}");
+                                return false;
+                            }
+                            let risk = command_risk(command, args.no_safety);
+                            let decision = decide_run(
+                                command,
+                                &config.never_execute,
+                                args.yes && risk < RiskLevel::Dangerous,
+                                || {
+                                    io::stdin().is_terminal()
+                                        && confirm_run("\nRun the corrected command?", risk)
+                                },
+                            );
+                            if let RunDecision::Refused { pattern } = &decision {
+                                eprintln!("Not running it: it matches neverExecute '{pattern}'.");
+                            }
+                            decision == RunDecision::Run
+                        },
+                        |command| {
+                            run_capturing_stderr(command_process(command, args.sandbox, &config))
+                        },
+                    )
+                    .await?;
+                    match outcome {
+                        FixOutcome::Succeeded { attempt, command } => {
+                            if attempt > 1 {
+                                console.banner(&format!("\nSucceeded on attempt {attempt}."))?;
+                                record_history(&raw_prompt, &CommandResult { command, ..result });
+                            }
+                        }
+                        FixOutcome::Declined { .. } => {}
+                        FixOutcome::GaveUp {
+                            attempts,
+                            exit_code,
+                        } => {
+                            console.banner(&format!(
+                                "\nStill failing after {attempts} attempts; giving up."
+                            ))?;
+                            std::process::exit(exit_code.unwrap_or(1));
+                        }
+                    }
+                }
+                RunDecision::Refused { pattern } => {
+                    eprintln!("\nNot running this command: it matches neverExecute '{pattern}'.");
+                }
+                RunDecision::Declined => {}
+            }
+        }
+        None => console.banner("Request cancelled.")?,
+    }
+
+    Ok(())
+}
+
+fn command_risk(command: &str, no_safety: bool) -> RiskLevel {
+    if no_safety {
+        RiskLevel::Safe
+    } else {
+        classify_command(command)
+    }
+}
+
+/// Asks whether to run a command, warning about risky ones first.
+/// Dangerous commands take a typed `yes`.
+fn confirm_run(question: &str, risk: RiskLevel) -> bool {
+    if let Some(warning) = risk.warning() {
+        eprintln!("\n{warning}");
+    }
+    let (mut input, mut output) = (io::stdin().lock(), io::stderr());
+    if risk == RiskLevel::Dangerous {
+        ask_typed_yes(question, &mut input, &mut output).unwrap_or(false)
+    } else {
+        ask_yes_no(question, &mut input, &mut output).unwrap_or(false)
+    }
+}
+
+/// [`confirm_run`] with the option to edit the command first.
+fn confirm_run_or_edit(question: &str, risk: RiskLevel) -> RunAnswer {
+    if let Some(warning) = risk.warning() {
+        eprintln!("\n{warning}");
+    }
+    let (mut input, mut output) = (io::stdin().lock(), io::stderr());
+    ask_run_or_edit(
+        question,
+        risk == RiskLevel::Dangerous,
+        &mut input,
+        &mut output,
+    )
+    .unwrap_or(RunAnswer::No)
+}
+
+/// Runs an approved command.
+fn run_command(command: &str, sandbox: bool, config: &Config) -> io::Result<Option<i32>> {
+    Ok(command_process(command, sandbox, config).status()?.code())
+}
+
+/// The process for an approved command, inside a sandbox with `--sandbox`
+/// when one is available.
+fn command_process(command: &str, sandbox: bool, config: &Config) -> std::process::Command {
+    if !sandbox {
+        return shell_command(command);
+    }
+
+    let os = std::env::consts::OS;
+    match detect_sandbox(os, &tool_search_dirs(&config.extra_path)) {
+        Some(tool) => {
+            let writable: Vec<PathBuf> =
+                config.sandbox_writable.iter().map(PathBuf::from).collect();
+            sandboxed_command(tool, command, &writable)
+        }
+        None => {
+            eprintln!("{}", unavailable_warning(os));
+            shell_command(command)
+        }
+    }
+}
+
+/// `sh-aid config get|set|path`. Works on the file as written, so
+/// environment overrides are neither shown nor saved.
+fn config_command(action: &ConfigAction) -> Result<()> {
+    let path = get_config_path()?;
+    match action {
+        ConfigAction::Path => outln!("{}", path.display())?,
+        ConfigAction::Get { key } => {
+            match Config::read_file(&path, &mut terminal_config_io(io::stderr()))?.get_key(key)? {
+                Some(value) => outln!("{value}")?,
+                None => eprintln!("{key} is not set"),
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            let mut config = Config::read_file(&path, &mut terminal_config_io(io::stderr()))?;
+            config.set_key(key, value)?;
+            config.save_to(&path)?;
+            eprintln!("Updated {key} in {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Asks on the terminal before sh-aid writes the config file on its own
+/// (`configInit: prompt`), and writes what it did to `notice`.
+fn terminal_config_io<'a>(notice: impl Write + 'a) -> ConfigIo<'a> {
+    let interactive = io::stdin().is_terminal();
+    ConfigIo::new(
+        move |question| {
+            interactive
+                && ask_yes_no(question, &mut io::stdin().lock(), &mut io::stderr()).unwrap_or(false)
+        },
+        notice,
+    )
+}
+
+/// Loads the `.env` file asked for with `--env-file`, if any.
+fn load_env_flag(args: &Args) -> Result<()> {
+    let path = match &args.env_file {
+        None => return Ok(()),
+        Some(Some(path)) => path.clone(),
+        Some(None) => {
+            let dir = std::env::current_dir()?;
+            find_env_file(&dir).ok_or_else(|| {
+                ShaidError::Usage(format!(
+                    "--env-file: no .env file in {} or its parents",
+                    dir.display()
+                ))
+            })?
+        }
+    };
+    load_env_file(&path)?;
+    Ok(())
+}
+
+/// The config overrides given on the command line.
+fn config_flags(args: &Args) -> ConfigOverrides {
+    ConfigOverrides {
+        provider: args.provider.clone(),
+        model: args.model.clone(),
+        ..ConfigOverrides::default()
+    }
+}
+
+/// `sh-aid models`: the model ids of the configured provider, one per line.
+async fn print_models(args: &Args) -> Result<()> {
+    let config = Config::load_with(&config_flags(args), &mut terminal_config_io(io::stderr()))?;
     config.validate()?;
-    println!("Configuration loaded successfully.");
-    println!("Provider: {:?}", config.provider_type);
-    println!("Model: {}", config.model);
 
-    println!("\nGathering system context...");
-    let context = SystemContext::gather()?;
-    println!("System context gathered successfully.");
+    for model in create_provider(&config)?.list_models().await? {
+        outln!("{}", model.name)?;
+    }
+    Ok(())
+}
 
-    println!("\n--- System Context ---");
-    println!("{}", context.build_environment_context());
-    println!("----------------------");
+/// `sh-aid env`: the current provider/model as `SHAID_*` exports, plus a PATH
+/// entry for this binary's directory when it is not on `PATH` yet.
+fn print_env(shell: Option<ShellKind>, args: &Args) -> Result<()> {
+    let shell = shell.unwrap_or_else(|| ShellKind::detect(std::env::var("SHELL").ok().as_deref()));
 
-    println!("\nUser Prompt: {user_prompt}");
+    let config = Config::load_with(&config_flags(args), &mut terminal_config_io(io::stderr()))?;
 
-    // In a future step, this would be sent to the AI provider.
-    // For now, we just print the information we've gathered.
+    let mut vars = vec![
+        (
+            "SHAID_PROVIDER",
+            provider_metadata(&config.provider_type).name.to_string(),
+        ),
+        ("SHAID_MODEL", config.model.clone()),
+    ];
+    if let Some(base_url) = &config.base_url {
+        vars.push(("SHAID_BASE_URL", base_url.clone()));
+    }
 
-    println!("\nPhase 2 Complete: Core infrastructure is in place.");
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()));
+    let on_path = |dir: &std::path::Path| {
+        std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|p| p == dir))
+    };
+    let path_dir = exe_dir
+        .filter(|dir| !on_path(dir))
+        .map(|dir| dir.to_string_lossy().to_string());
 
+    out!("{}", render_env(shell, path_dir.as_deref(), &vars))?;
     Ok(())
 }
+
+/// Appends the generation to the history file. Failures only print a
+/// warning so that a broken history never blocks command generation.
+fn record_history(prompt: &str, result: &CommandResult) {
+    let Some(path) = history::history_path() else {
+        eprintln!("Warning: not saving history: failed to determine data directory");
+        return;
+    };
+    let entry = history::HistoryEntry {
+        prompt: prompt.to_string(),
+        command: result.command.clone(),
+        provider: result.provider.clone(),
+        model: result.model.clone(),
+        timestamp: now_unix().unwrap_or_default(),
+    };
+    if let Err(e) = history::append_entry(&path, &entry) {
+        eprintln!("Warning: failed to save history to {}: {e}", path.display());
+    }
+}
+
+fn load_history() -> Result<Vec<history::HistoryEntry>> {
+    let path = history::history_path()
+        .ok_or_else(|| ShaidError::History("Failed to determine data directory".to_string()))?;
+    Ok(history::load_entries(&path)?)
+}
@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+const BOLD: &str = "\x1b[1m";
+const UNDERLINE: &str = "\x1b[4m";
+const DIM: &str = "\x1b[2m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// How explanations are printed (`--explain-format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExplainFormat {
+    /// The model's markdown as is; safe for pipes.
+    #[default]
+    Raw,
+    /// Markdown rendered with ANSI styles, when stdout is a terminal.
+    Pretty,
+}
+
+impl FromStr for ExplainFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(ExplainFormat::Raw),
+            "pretty" => Ok(ExplainFormat::Pretty),
+            _ => Err(format!("unknown format '{s}' (expected raw or pretty)")),
+        }
+    }
+}
+
+/// Renders `text` for display. Pretty rendering only applies when the
+/// output is a terminal; otherwise the markdown is returned untouched.
+pub fn render_explanation(text: &str, format: ExplainFormat, is_tty: bool) -> String {
+    match format {
+        ExplainFormat::Pretty if is_tty => markdown_to_ansi(text),
+        _ => text.to_string(),
+    }
+}
+
+/// A small markdown-to-ANSI renderer covering what explanations use:
+/// headings, bullet lists, fenced code blocks, `**bold**` and `` `code` ``.
+pub fn markdown_to_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+
+        if in_code_block {
+            output.push_str(&format!("    {CYAN}{line}{RESET}\n"));
+        } else if let Some(heading) = strip_heading(trimmed) {
+            output.push_str(&format!(
+                "{BOLD}{UNDERLINE}{}{RESET}\n",
+                render_inline(heading)
+            ));
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            let indent = &line[..line.len() - trimmed.len()];
+            output.push_str(&format!("{indent}• {}\n", render_inline(item)));
+        } else if let Some(quote) = trimmed.strip_prefix("> ") {
+            output.push_str(&format!("{DIM}│ {}{RESET}\n", render_inline(quote)));
+        } else {
+            output.push_str(&render_inline(line));
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+fn strip_heading(line: &str) -> Option<&str> {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) {
+        line[hashes..].strip_prefix(' ').map(str::trim)
+    } else {
+        None
+    }
+}
+
+/// Styles `**bold**` and `` `code` `` spans; unmatched markers are kept.
+fn render_inline(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    loop {
+        let bold = rest.find("**");
+        let code = rest.find('`');
+
+        let (start, marker, style) = match (bold, code) {
+            (Some(b), Some(c)) if c < b => (c, "`", CYAN),
+            (Some(b), _) => (b, "**", BOLD),
+            (None, Some(c)) => (c, "`", CYAN),
+            (None, None) => break,
+        };
+
+        let after = &rest[start + marker.len()..];
+        match after.find(marker) {
+            Some(end) => {
+                output.push_str(&rest[..start]);
+                output.push_str(&format!("{style}{}{RESET}", &after[..end]));
+                rest = &after[end + marker.len()..];
+            }
+            None => break,
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str =
+        "## Breakdown\n- `ls` lists files\n- **-a** includes hidden ones\n```\nls -a\n```\n";
+
+    #[test]
+    fn test_raw_preserves_markdown() {
+        assert_eq!(render_explanation(SAMPLE, ExplainFormat::Raw, true), SAMPLE);
+    }
+
+    #[test]
+    fn test_pretty_is_disabled_for_non_tty() {
+        assert_eq!(
+            render_explanation(SAMPLE, ExplainFormat::Pretty, false),
+            SAMPLE
+        );
+    }
+
+    #[test]
+    fn test_pretty_produces_ansi() {
+        let rendered = render_explanation(SAMPLE, ExplainFormat::Pretty, true);
+        assert_eq!(
+            rendered,
+            "\x1b[1m\x1b[4mBreakdown\x1b[0m\n\
+             • \x1b[36mls\x1b[0m lists files\n\
+             • \x1b[1m-a\x1b[0m includes hidden ones\n    \
+             \x1b[36mls -a\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_markers_are_kept() {
+        assert_eq!(render_inline("a ** b ` c"), "a ** b ` c");
+        assert_eq!(render_inline("5 * 3"), "5 * 3");
+    }
+
+    #[test]
+    fn test_parse_explain_format() {
+        assert_eq!("raw".parse(), Ok(ExplainFormat::Raw));
+        assert_eq!("Pretty".parse(), Ok(ExplainFormat::Pretty));
+        assert!("html".parse::<ExplainFormat>().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::Config;
+use crate::providers::{
+    AIProvider, CommandStream, GenerateOptions, ModelInfo, ProviderError, Quota,
+};
+use crate::usage::{TokenUsage, sum_usage};
+
+/// Retries once with the provider's default model when the configured model
+/// is reported as unavailable (`modelFallback` / `--model-fallback`).
+/// Once it has fallen back, it reports the default model as its own and
+/// [`AIProvider::fell_back_from`] names the configured one, for the caller
+/// to tell the user.
+pub struct ModelFallbackProvider {
+    primary: Box<dyn AIProvider>,
+    fallback: Box<dyn AIProvider>,
+    /// Set once a request fell back, shared by clones.
+    fell_back: Arc<AtomicBool>,
+}
+
+impl ModelFallbackProvider {
+    pub fn new(primary: Box<dyn AIProvider>, fallback: Box<dyn AIProvider>) -> Self {
+        Self {
+            primary,
+            fallback,
+            fell_back: Arc::default(),
+        }
+    }
+
+    /// The provider answering for the configured model now.
+    fn current(&self) -> &dyn AIProvider {
+        if self.fell_back.load(Ordering::Relaxed) {
+            self.fallback.as_ref()
+        } else {
+            self.primary.as_ref()
+        }
+    }
+}
+
+/// True for errors meaning the requested model does not exist or was retired,
+/// as opposed to any other failure worth surfacing as is.
+pub fn is_model_not_found(error: &ProviderError) -> bool {
+    let message = match error {
+        ProviderError::ApiError {
+            status_code: 404, ..
+        } => return true,
+        ProviderError::ApiError { message, .. } | ProviderError::InvalidResponse(message) => {
+            message.to_lowercase()
+        }
+        _ => return false,
+    };
+
+    message.contains("model_not_found")
+        || (message.contains("model")
+            && (message.contains("not found") || message.contains("does not exist")))
+}
+
+#[async_trait]
+impl AIProvider for ModelFallbackProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        match self
+            .primary
+            .generate_command_with(system_prompt, user_prompt, options)
+            .await
+        {
+            Err(error) if is_model_not_found(&error) => {
+                self.fell_back.store(true, Ordering::Relaxed);
+                self.fallback
+                    .generate_command_with(system_prompt, user_prompt, options)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> Result<Vec<String>, ProviderError> {
+        match self
+            .primary
+            .generate_commands(system_prompt, user_prompt, n, options)
+            .await
+        {
+            Err(error) if is_model_not_found(&error) => {
+                self.fell_back.store(true, Ordering::Relaxed);
+                self.fallback
+                    .generate_commands(system_prompt, user_prompt, n, options)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<CommandStream, ProviderError> {
+        match self
+            .primary
+            .generate_command_stream(system_prompt, user_prompt, options)
+            .await
+        {
+            Err(error) if is_model_not_found(&error) => {
+                self.fell_back.store(true, Ordering::Relaxed);
+                self.fallback
+                    .generate_command_stream(system_prompt, user_prompt, options)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        self.primary.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.primary.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        sum_usage(self.primary.token_usage(), self.fallback.token_usage())
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.primary.truncated_responses() + self.fallback.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.fell_back
+            .load(Ordering::Relaxed)
+            .then(|| self.primary.model_id().to_string())
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        self.primary.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.current().get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.current().model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(ModelFallbackProvider {
+            primary: self.primary.clone_box(),
+            fallback: self.fallback.clone_box(),
+            fell_back: self.fell_back.clone(),
+        })
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.primary.get_provider_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderType;
+    use crate::providers::create_provider;
+    use mockito::Matcher;
+
+    fn success_body(command: &str) -> String {
+        format!(
+            r#"{{"choices":[{{"message":{{"role":"assistant","content":"{command}"}},"finish_reason":"stop"}}]}}"#
+        )
+    }
+
+    const MODEL_NOT_FOUND_BODY: &str = r#"{"error":{"message":"The model `gpt-5-typo` does not exist or you do not have access to it.","type":"invalid_request_error","param":null,"code":"model_not_found"}}"#;
+
+    fn config(base_url: String, model_fallback: bool) -> Config {
+        Config {
+            provider_type: ProviderType::OpenAI,
+            api_key: Some("test-key".to_string()),
+            model: "gpt-5-typo".to_string(),
+            base_url: Some(base_url),
+            model_fallback,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_is_model_not_found() {
+        assert!(is_model_not_found(&ProviderError::ApiError {
+            status_code: 404,
+            message: String::new(),
+        }));
+        assert!(is_model_not_found(&ProviderError::ApiError {
+            status_code: 400,
+            message: MODEL_NOT_FOUND_BODY.to_string(),
+        }));
+        assert!(!is_model_not_found(&ProviderError::ApiError {
+            status_code: 500,
+            message: "internal error".to_string(),
+        }));
+        assert!(!is_model_not_found(&ProviderError::RateLimitError(
+            "model not found".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_default_model() {
+        let mut server = mockito::Server::new_async().await;
+        let missing = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"model":"gpt-5-typo"}"#.to_string(),
+            ))
+            .with_status(404)
+            .with_body(MODEL_NOT_FOUND_BODY)
+            .create_async()
+            .await;
+        let fallback = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"model":"gpt-4o"}"#.to_string(),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(success_body("ls -la"))
+            .create_async()
+            .await;
+
+        let provider = create_provider(&config(server.url(), true)).unwrap();
+        assert_eq!(provider.fell_back_from(), None);
+        let result = provider.generate_command("system", "user").await;
+
+        missing.assert_async().await;
+        fallback.assert_async().await;
+        assert_eq!(result.unwrap(), "ls -la");
+        assert_eq!(provider.fell_back_from().as_deref(), Some("gpt-5-typo"));
+        assert_eq!(provider.model_id(), "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn test_without_flag_the_error_is_returned() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(404)
+            .with_body(MODEL_NOT_FOUND_BODY)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let provider = create_provider(&config(server.url(), false)).unwrap();
+        let result = provider.generate_command("system", "user").await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ApiError {
+                status_code: 404,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(500)
+            .with_body("boom")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let provider = create_provider(&config(server.url(), true)).unwrap();
+        let result = provider.generate_command("system", "user").await;
+
+        mock.assert_async().await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ApiError {
+                status_code: 500,
+                ..
+            })
+        ));
+    }
+}
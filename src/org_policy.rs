@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, ProviderType};
+
+/// Path of the organization policy file in managed deployments.
+pub const POLICY_ENV: &str = "SHAID_POLICY";
+
+/// Settings an administrator forces on every user. The file is only ever
+/// read, and it is applied after the config file, the `SHAID_*` variables
+/// and the command-line flags, so nothing a user sets can override it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct OrgPolicy {
+    /// Base URL every request must go to, e.g. an internal gateway.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Providers users may not select.
+    #[serde(default)]
+    pub disallowed_providers: Vec<ProviderType>,
+    /// Patterns added to every user's `neverExecute` list.
+    #[serde(default)]
+    pub never_execute: Vec<String>,
+    /// Upper bound for `maxPromptTokens`.
+    #[serde(default)]
+    pub max_prompt_tokens: Option<usize>,
+}
+
+impl OrgPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file: {path:?}"))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse policy file: {path:?}"))
+    }
+
+    /// Forces the policy onto `config`, recording the config keys it changed
+    /// in `config.policy_overrides`. Fails when the selected provider is
+    /// disallowed.
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        if self.disallowed_providers.contains(&config.provider_type) {
+            anyhow::bail!(
+                "Provider {:?} is disallowed by the organization policy",
+                config.provider_type
+            );
+        }
+
+        if let Some(base_url) = &self.base_url {
+            if config.base_url.as_ref() != Some(base_url) {
+                config.base_url = Some(base_url.clone());
+                config.policy_overrides.push("baseUrl");
+            }
+            // Providers send requests to an API root in preference to the
+            // base URL, so a user's one would bypass the forced gateway
+            if config.api_root.take().is_some() {
+                config.policy_overrides.push("apiRoot");
+            }
+        }
+
+        let missing: Vec<String> = self
+            .never_execute
+            .iter()
+            .filter(|pattern| !config.never_execute.contains(pattern))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            config.never_execute.extend(missing);
+            config.policy_overrides.push("neverExecute");
+        }
+
+        // A user may set a smaller budget, never a larger one
+        if let Some(max) = self.max_prompt_tokens
+            && config
+                .max_prompt_tokens
+                .is_none_or(|user_max| user_max > max)
+        {
+            config.max_prompt_tokens = Some(max);
+            config.policy_overrides.push("maxPromptTokens");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn policy() -> OrgPolicy {
+        OrgPolicy {
+            base_url: Some("https://llm-gateway.corp.example/v1".to_string()),
+            disallowed_providers: vec![ProviderType::Gemini],
+            never_execute: vec!["rm -rf /".to_string(), "mkfs".to_string()],
+            max_prompt_tokens: Some(4000),
+        }
+    }
+
+    #[test]
+    fn test_policy_overrides_user_config() {
+        let mut config = Config {
+            base_url: Some("https://api.openai.com/v1".to_string()),
+            never_execute: vec!["mkfs".to_string()],
+            max_prompt_tokens: Some(8000),
+            ..Config::default()
+        };
+        policy().apply(&mut config).unwrap();
+
+        assert_eq!(
+            config.base_url.as_deref(),
+            Some("https://llm-gateway.corp.example/v1")
+        );
+        assert_eq!(config.never_execute, vec!["mkfs", "rm -rf /"]);
+        assert_eq!(config.max_prompt_tokens, Some(4000));
+        assert_eq!(
+            config.policy_overrides,
+            vec!["baseUrl", "neverExecute", "maxPromptTokens"]
+        );
+    }
+
+    #[test]
+    fn test_policy_base_url_clears_user_api_root() {
+        let mut config = Config {
+            base_url: Some("https://llm-gateway.corp.example/v1".to_string()),
+            api_root: Some("https://api.openai.com/v1".to_string()),
+            ..Config::default()
+        };
+        policy().apply(&mut config).unwrap();
+
+        assert_eq!(config.api_root, None);
+        assert_eq!(
+            config.base_url.as_deref(),
+            Some("https://llm-gateway.corp.example/v1")
+        );
+        assert!(config.policy_overrides.contains(&"apiRoot"));
+    }
+
+    #[test]
+    fn test_stricter_user_settings_are_kept() {
+        let mut config = Config {
+            base_url: Some("https://llm-gateway.corp.example/v1".to_string()),
+            never_execute: vec!["rm -rf /".to_string(), "mkfs".to_string()],
+            max_prompt_tokens: Some(1000),
+            ..Config::default()
+        };
+        policy().apply(&mut config).unwrap();
+
+        assert_eq!(config.max_prompt_tokens, Some(1000));
+        assert!(config.policy_overrides.is_empty());
+    }
+
+    #[test]
+    fn test_disallowed_provider_is_rejected() {
+        let mut config = Config {
+            provider_type: ProviderType::Gemini,
+            ..Config::default()
+        };
+        let err = policy().apply(&mut config).unwrap_err();
+        assert!(err.to_string().contains("Gemini"));
+    }
+
+    #[test]
+    fn test_load_policy_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("policy.json");
+        fs::write(
+            &path,
+            r#"{"baseUrl": "https://llm-gateway.corp.example/v1",
+                "disallowedProviders": ["Gemini"],
+                "neverExecute": ["rm -rf /", "mkfs"],
+                "maxPromptTokens": 4000}"#,
+        )
+        .unwrap();
+        assert_eq!(OrgPolicy::load(&path).unwrap(), policy());
+
+        fs::write(&path, r#"{"baseURL": "typo"}"#).unwrap();
+        assert!(OrgPolicy::load(&path).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::generate::CommandResult;
+
+/// Writes `text` to stdout, returning the error instead of panicking like
+/// `print!` does when the reader has gone away (e.g. `sh-aid ... | head -1`).
+pub fn write_stdout(text: &str) -> io::Result<()> {
+    write_text(&mut io::stdout().lock(), text)
+}
+
+pub fn write_text<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    out.write_all(text.as_bytes())?;
+    out.flush()
+}
+
+/// How much is reported besides the result (`-q`, `-v`, `-vv`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the result, warnings and questions.
+    Quiet,
+    /// Progress banners as well.
+    #[default]
+    Normal,
+    /// Timings as well.
+    Verbose,
+    /// Request and response metadata as well.
+    Debug,
+}
+
+impl Verbosity {
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+}
+
+/// How the result is written to stdout (`--format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The command alone.
+    #[default]
+    Text,
+    /// An [`Output`] object, for tools wrapping sh-aid.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown format '{s}' (expected text or json)")),
+        }
+    }
+}
+
+/// The result as written with `--format json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Output {
+    pub command: String,
+    pub provider: String,
+    pub model: String,
+    pub explanation: Option<String>,
+}
+
+impl Output {
+    pub fn new(result: &CommandResult, explanation: Option<&str>) -> Self {
+        Output {
+            command: result.command.clone(),
+            provider: result.provider.clone(),
+            model: result.model.clone(),
+            explanation: explanation.map(str::to_string),
+        }
+    }
+}
+
+/// Keeps stdout for the generated command, so that it can be piped into
+/// other tools; status messages go to stderr, as far as `verbosity` allows.
+pub struct Console<O: Write, E: Write> {
+    out: O,
+    err: E,
+    verbosity: Verbosity,
+}
+
+impl Console<io::Stdout, io::Stderr> {
+    pub fn stdio(verbosity: Verbosity) -> Self {
+        Console::new(io::stdout(), io::stderr(), verbosity)
+    }
+}
+
+impl<O: Write, E: Write> Console<O, E> {
+    pub fn new(out: O, err: E, verbosity: Verbosity) -> Self {
+        Console {
+            out,
+            err,
+            verbosity,
+        }
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Writes a status line such as "Loading configuration...".
+    pub fn banner(&mut self, text: &str) -> io::Result<()> {
+        self.status(Verbosity::Normal, text)
+    }
+
+    /// Writes a line shown with `-v`, such as timings.
+    pub fn info(&mut self, text: &str) -> io::Result<()> {
+        self.status(Verbosity::Verbose, text)
+    }
+
+    /// Writes a line shown with `-vv`, such as request metadata.
+    pub fn debug(&mut self, text: &str) -> io::Result<()> {
+        self.status(Verbosity::Debug, text)
+    }
+
+    /// Writes the generated command.
+    pub fn command(&mut self, command: &str) -> io::Result<()> {
+        write_text(&mut self.out, &format!("{command}\n"))
+    }
+
+    /// Writes the result in `format`: the command alone, or the whole
+    /// [`Output`] as one line of JSON.
+    pub fn result(&mut self, output: &Output, format: OutputFormat) -> io::Result<()> {
+        match format {
+            OutputFormat::Text => self.command(&output.command),
+            OutputFormat::Json => {
+                let json = serde_json::to_string(output).map_err(io::Error::other)?;
+                write_text(&mut self.out, &format!("{json}\n"))
+            }
+        }
+    }
+
+    fn status(&mut self, level: Verbosity, text: &str) -> io::Result<()> {
+        if self.verbosity >= level {
+            write_text(&mut self.err, &format!("{text}\n"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// `print!` to stdout that returns write errors instead of panicking.
+#[macro_export]
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(&format!($($arg)*))
+    };
+}
+
+/// `println!` to stdout that returns write errors instead of panicking.
+#[macro_export]
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(&format!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ShaidError;
+    use crate::generate;
+    use crate::providers::test_utils::MockProvider;
+
+    /// Behaves like stdout after the reading end of the pipe was closed.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_closed_pipe_is_reported_as_broken_pipe() {
+        let result = write_text(&mut ClosedPipe, "ls -la\n");
+        let error: ShaidError = result.unwrap_err().into();
+        assert!(error.is_broken_pipe());
+    }
+
+    #[test]
+    fn test_other_errors_are_not_broken_pipe() {
+        let error: ShaidError = io::Error::from(io::ErrorKind::PermissionDenied).into();
+        assert!(!error.is_broken_pipe());
+        assert!(!ShaidError::History("missing".to_string()).is_broken_pipe());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_stdout_is_only_the_command() {
+        let provider = MockProvider::with_response("ls -la".to_string());
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut console = Console::new(&mut out, &mut err, Verbosity::default());
+
+        console.banner("Loading configuration...").unwrap();
+        console.banner("\nGathering system context...").unwrap();
+        let result = generate::run(&provider, "system", "list files", generate::Strictness::OFF)
+            .await
+            .unwrap();
+        console.command(&result.command).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "ls -la\n");
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            "Loading configuration...\n\nGathering system context...\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_dry_run_stdout_is_one_json_object() {
+        let provider = MockProvider::with_response("du -sh *".to_string());
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut console = Console::new(&mut out, &mut err, Verbosity::Quiet);
+
+        let result = generate::run(&provider, "system", "disk usage", generate::Strictness::OFF)
+            .await
+            .unwrap();
+        let format: OutputFormat = "json".parse().unwrap();
+        console.result(&Output::new(&result, None), format).unwrap();
+
+        let stdout = String::from_utf8(out).unwrap();
+        assert_eq!(stdout.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "command": "du -sh *",
+                "provider": "mock",
+                "model": result.model,
+                "explanation": null,
+            })
+        );
+        assert!(err.is_empty());
+
+        let mut out = Vec::new();
+        Console::new(&mut out, Vec::new(), Verbosity::Quiet)
+            .result(&Output::new(&result, Some("Sizes")), OutputFormat::Text)
+            .unwrap();
+        assert_eq!(out, b"du -sh *\n");
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_verbosity_levels() {
+        assert_eq!(Verbosity::from_flags(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(3, false), Verbosity::Debug);
+        assert_eq!(Verbosity::from_flags(0, true), Verbosity::Quiet);
+
+        let write_all = |verbosity| {
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            let mut console = Console::new(&mut out, &mut err, verbosity);
+            console.banner("Model: gpt-4o").unwrap();
+            console.info("Timing: total: 1.2s").unwrap();
+            console.debug("Request: ~120 prompt tokens").unwrap();
+            console.command("ls -la").unwrap();
+            (
+                String::from_utf8(out).unwrap(),
+                String::from_utf8(err).unwrap(),
+            )
+        };
+
+        assert_eq!(write_all(Verbosity::Quiet), ("ls -la\n".into(), "".into()));
+        assert_eq!(
+            write_all(Verbosity::Normal),
+            ("ls -la\n".into(), "Model: gpt-4o\n".into())
+        );
+        assert_eq!(
+            write_all(Verbosity::Debug).1,
+            "Model: gpt-4o\nTiming: total: 1.2s\nRequest: ~120 prompt tokens\n"
+        );
+    }
+
+    #[test]
+    fn test_write_text() {
+        let mut buffer = Vec::new();
+        write_text(&mut buffer, "ls -la\n").unwrap();
+        assert_eq!(buffer, b"ls -la\n");
+    }
+}
@@ -0,0 +1,349 @@
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io;
+use std::sync::LazyLock;
+
+use crate::providers::{AIProvider, ProviderError};
+
+/// Appended to the system prompt in `--plan` mode, replacing the usual
+/// single-command answer with a JSON plan.
+pub const PLAN_INSTRUCTIONS: &str = r#"
+Instead of a single command, break the task into an ordered list of steps.
+Respond with only a JSON object of this shape and nothing else:
+{"steps": [{"description": "what the step does", "command": "the shell command"}]}
+"#;
+
+/// JSON schema every plan response must satisfy before it is used.
+pub const PLAN_SCHEMA: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "type": "object",
+  "required": ["steps"],
+  "properties": {
+    "steps": {
+      "type": "array",
+      "minItems": 1,
+      "items": {
+        "type": "object",
+        "required": ["description", "command"],
+        "properties": {
+          "description": { "type": "string" },
+          "command": { "type": "string", "pattern": "\\S" }
+        }
+      }
+    }
+  }
+}"#;
+
+static PLAN_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| {
+    let schema: Value = serde_json::from_str(PLAN_SCHEMA).expect("plan schema is valid JSON");
+    jsonschema::validator_for(&schema).expect("plan schema is a valid JSON schema")
+});
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub description: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanOutcome {
+    Completed,
+    /// The user declined the step at this 1-based index; nothing after it ran.
+    Declined {
+        step: usize,
+    },
+    /// The step at this 1-based index exited unsuccessfully.
+    Failed {
+        step: usize,
+        exit_code: Option<i32>,
+    },
+}
+
+/// Asks for a plan. A response that is not valid JSON or does not match
+/// [`PLAN_SCHEMA`] is retried once, with the error and the schema added to
+/// the system prompt.
+pub async fn generate_plan(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+) -> Result<Plan, ProviderError> {
+    let system_prompt = format!("{system_prompt}{PLAN_INSTRUCTIONS}");
+    let response = provider
+        .generate_command(&system_prompt, user_prompt)
+        .await?;
+
+    match parse_plan(&response) {
+        Err(ProviderError::InvalidResponse(error)) => {
+            let strict_prompt = format!(
+                "{system_prompt}\nYour previous response was rejected ({error}). \
+                 Respond with only a JSON object matching this schema:\n{PLAN_SCHEMA}\n"
+            );
+            let response = provider
+                .generate_command(&strict_prompt, user_prompt)
+                .await?;
+            parse_plan(&response)
+        }
+        result => result,
+    }
+}
+
+/// Parses the model's plan, tolerating Markdown fences or chatter around the
+/// JSON object.
+pub fn parse_plan(response: &str) -> Result<Plan, ProviderError> {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => {
+            return Err(ProviderError::InvalidResponse(
+                "Plan response does not contain a JSON object".to_string(),
+            ));
+        }
+    };
+
+    let value: Value = serde_json::from_str(json)
+        .map_err(|e| ProviderError::InvalidResponse(format!("Failed to parse plan: {e}")))?;
+    validate_plan(&value)?;
+
+    serde_json::from_value(value)
+        .map_err(|e| ProviderError::InvalidResponse(format!("Failed to parse plan: {e}")))
+}
+
+/// Checks `value` against [`PLAN_SCHEMA`], listing every violation with its
+/// location in the response.
+pub fn validate_plan(value: &Value) -> Result<(), ProviderError> {
+    let violations: Vec<String> = PLAN_VALIDATOR
+        .iter_errors(value)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{path}: {error}")
+            }
+        })
+        .collect();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ProviderError::InvalidResponse(format!(
+            "Plan does not match its schema: {}",
+            violations.join("; ")
+        )))
+    }
+}
+
+/// Renders the plan as a numbered list.
+pub fn format_plan(plan: &Plan) -> String {
+    plan.steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{}. {}\n   $ {}\n", i + 1, step.description, step.command))
+        .collect()
+}
+
+/// Runs the steps in order. Each step must be approved by `confirm` first;
+/// execution stops at the first declined or failing step.
+pub fn execute_plan<C, R>(plan: &Plan, mut confirm: C, mut run: R) -> io::Result<PlanOutcome>
+where
+    C: FnMut(usize, &PlanStep) -> bool,
+    R: FnMut(&str) -> io::Result<Option<i32>>,
+{
+    for (i, step) in plan.steps.iter().enumerate() {
+        let number = i + 1;
+        if !confirm(number, step) {
+            return Ok(PlanOutcome::Declined { step: number });
+        }
+
+        let exit_code = run(&step.command)?;
+        if exit_code != Some(0) {
+            return Ok(PlanOutcome::Failed {
+                step: number,
+                exit_code,
+            });
+        }
+    }
+
+    Ok(PlanOutcome::Completed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+
+    fn sample_plan() -> Plan {
+        Plan {
+            steps: vec![
+                PlanStep {
+                    description: "Create a virtualenv".to_string(),
+                    command: "python3 -m venv .venv".to_string(),
+                },
+                PlanStep {
+                    description: "Install dependencies".to_string(),
+                    command: ".venv/bin/pip install -r requirements.txt".to_string(),
+                },
+                PlanStep {
+                    description: "Run the tests".to_string(),
+                    command: ".venv/bin/pytest".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_plan() {
+        let response = r#"```json
+{"steps": [
+  {"description": "Create a virtualenv", "command": "python3 -m venv .venv"},
+  {"description": "Install dependencies", "command": ".venv/bin/pip install -r requirements.txt"},
+  {"description": "Run the tests", "command": ".venv/bin/pytest"}
+]}
+```"#;
+        assert_eq!(parse_plan(response).unwrap(), sample_plan());
+    }
+
+    #[test]
+    fn test_parse_plan_rejects_bad_responses() {
+        for response in [
+            "python3 -m venv .venv",
+            r#"{"steps": []}"#,
+            r#"{"steps": [{"description": "oops"}]}"#,
+            r#"{"steps": [{"description": "blank", "command": "  "}]}"#,
+        ] {
+            assert!(
+                matches!(parse_plan(response), Err(ProviderError::InvalidResponse(_))),
+                "accepted: {response}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_violations_are_described() {
+        let value = serde_json::json!({"steps": [{"description": "oops", "command": 1}]});
+        let Err(ProviderError::InvalidResponse(message)) = validate_plan(&value) else {
+            panic!("invalid plan accepted");
+        };
+        assert!(message.starts_with("Plan does not match its schema: /steps/0/command:"));
+
+        let valid = serde_json::json!({"steps": [{"description": "List", "command": "ls"}]});
+        assert!(validate_plan(&valid).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_generate_plan_retries_schema_violation() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Ok(r#"{"steps": [{"command": "ls"}]}"#.to_string()));
+        provider.add_response(Ok(
+            r#"{"steps": [{"description": "List files", "command": "ls"}]}"#.to_string(),
+        ));
+
+        let plan = generate_plan(&provider, "system", "list files")
+            .await
+            .unwrap();
+        assert_eq!(plan.steps[0].description, "List files");
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].0.contains("\"description\""));
+        assert!(requests[1].0.contains("was rejected"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_plan_fails_after_second_violation() {
+        let mut provider = MockProvider::new();
+        provider.add_response(Ok(r#"{"steps": []}"#.to_string()));
+        provider.add_response(Ok("ls -la".to_string()));
+
+        let result = generate_plan(&provider, "system", "list files").await;
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+        assert_eq!(provider.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_format_plan() {
+        let output = format_plan(&sample_plan());
+        assert!(output.starts_with("1. Create a virtualenv\n   $ python3 -m venv .venv\n"));
+        assert!(output.contains("3. Run the tests\n   $ .venv/bin/pytest\n"));
+    }
+
+    #[test]
+    fn test_execute_plan_runs_all_steps_in_order() {
+        let mut ran = Vec::new();
+        let outcome = execute_plan(
+            &sample_plan(),
+            |_, _| true,
+            |command| {
+                ran.push(command.to_string());
+                Ok(Some(0))
+            },
+        )
+        .unwrap();
+
+        assert_eq!(outcome, PlanOutcome::Completed);
+        assert_eq!(ran.len(), 3);
+        assert_eq!(ran[0], "python3 -m venv .venv");
+        assert_eq!(ran[2], ".venv/bin/pytest");
+    }
+
+    #[test]
+    fn test_execute_plan_stops_on_failure() {
+        let mut ran = Vec::new();
+        let outcome = execute_plan(
+            &sample_plan(),
+            |_, _| true,
+            |command| {
+                ran.push(command.to_string());
+                Ok(Some(if ran.len() == 2 { 1 } else { 0 }))
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            outcome,
+            PlanOutcome::Failed {
+                step: 2,
+                exit_code: Some(1)
+            }
+        );
+        assert_eq!(ran.len(), 2);
+    }
+
+    #[test]
+    fn test_execute_plan_stops_when_declined() {
+        let mut ran = Vec::new();
+        let outcome = execute_plan(
+            &sample_plan(),
+            |number, _| number != 2,
+            |command| {
+                ran.push(command.to_string());
+                Ok(Some(0))
+            },
+        )
+        .unwrap();
+
+        assert_eq!(outcome, PlanOutcome::Declined { step: 2 });
+        assert_eq!(ran, vec!["python3 -m venv .venv".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_plan_adds_instructions() {
+        let provider = MockProvider::with_response(
+            r#"{"steps": [{"description": "List files", "command": "ls"}]}"#.to_string(),
+        );
+
+        let plan = generate_plan(&provider, "system", "list files")
+            .await
+            .unwrap();
+        assert_eq!(plan.steps[0].command, "ls");
+
+        let requests = provider.requests();
+        assert!(requests[0].0.starts_with("system"));
+        assert!(requests[0].0.contains(r#"{"steps": ["#));
+    }
+}
@@ -0,0 +1,556 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::error::{Result, ShaidError};
+use crate::safety::SHELLS;
+
+/// Refuses `command` with [`ShaidError::Policy`] when it runs a binary that
+/// is not in `allowed`.
+pub fn check_command(command: &str, allowed: &[String]) -> Result<()> {
+    match find_disallowed(command, allowed) {
+        Some(binary) => Err(ShaidError::Policy(describe_violation(&binary, allowed))),
+        None => Ok(()),
+    }
+}
+
+/// Checks every stage of `command` (split on `|`, `&&`, `||`, `;`, `&` and
+/// newlines outside quotes), including those inside command and process
+/// substitutions, against `allowed` binary names. An empty allowlist allows
+/// everything. Returns the first disallowed binary.
+pub fn find_disallowed(command: &str, allowed: &[String]) -> Option<String> {
+    if allowed.is_empty() {
+        return None;
+    }
+
+    command_stages(command)
+        .iter()
+        .filter_map(|stage| leading_binary(stage))
+        .find(|binary| !allowed.iter().any(|a| a == binary))
+}
+
+/// The policy error message shown instead of a disallowed command.
+pub fn describe_violation(binary: &str, allowed: &[String]) -> String {
+    format!(
+        "the generated command uses '{binary}', which is not in allowedCommands ({})",
+        allowed.join(", ")
+    )
+}
+
+/// Refuses a prompt whose estimated size is over `maxPromptTokens`.
+pub fn check_prompt_budget(
+    estimated_tokens: usize,
+    max_prompt_tokens: Option<usize>,
+) -> Result<()> {
+    match max_prompt_tokens {
+        Some(max) if estimated_tokens > max => Err(ShaidError::Policy(format!(
+            "the prompt is about {estimated_tokens} tokens, over maxPromptTokens ({max})"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the first `never_execute` pattern that a stage of `command` runs,
+/// looking inside substitutions and `sh -c` scripts. The program is compared
+/// by file name after skipping `VAR=value` assignments and wrappers like
+/// `sudo -n` or `env`; the pattern's flags must all be present, in any order
+/// or grouping, and its other words must start the stage's. `rm -rf /`
+/// matches `sudo /bin/rm -f -r /*` but not `rm -rf /tmp/build`.
+pub fn find_never_execute<'a>(command: &str, never_execute: &'a [String]) -> Option<&'a str> {
+    let stages: Vec<Invocation> = executed_stages(command)
+        .iter()
+        .map(|words| Invocation::parse(words))
+        .collect();
+
+    never_execute
+        .iter()
+        .find(|pattern| {
+            let pattern = Invocation::parse(&stage_words(pattern));
+            !pattern.program.is_empty() && stages.iter().any(|stage| stage.covers(&pattern))
+        })
+        .map(String::as_str)
+}
+
+/// The words of every stage `command` runs, including those of scripts
+/// handed to a shell with `-c` or to `eval`.
+fn executed_stages(command: &str) -> Vec<Vec<String>> {
+    let mut all = Vec::new();
+    for stage in command_stages(command) {
+        let words = stage_words(&stage);
+        if let Some(script) = inline_script(&words) {
+            all.extend(executed_stages(&script));
+        }
+        all.push(words);
+    }
+    all
+}
+
+/// The script of `sh -c 'script'` or `eval script`.
+fn inline_script(words: &[String]) -> Option<String> {
+    let program = words.first()?;
+    if program == "eval" {
+        return Some(words[1..].join(" "));
+    }
+    if !SHELLS.contains(&program.as_str()) {
+        return None;
+    }
+    let flag = words[1..]
+        .iter()
+        .position(|w| w.starts_with('-') && !w.starts_with("--") && w.contains('c'))?;
+    words.get(flag + 2).cloned()
+}
+
+/// A stage reduced to what `never_execute` matching compares.
+struct Invocation {
+    program: String,
+    flags: BTreeSet<String>,
+    operands: Vec<String>,
+}
+
+impl Invocation {
+    fn parse(words: &[String]) -> Self {
+        let mut flags = BTreeSet::new();
+        let mut operands = Vec::new();
+        let mut end_of_flags = false;
+
+        for word in words.iter().skip(1) {
+            if end_of_flags || word == "-" || !word.starts_with('-') {
+                operands.push(normalize_path(word));
+            } else if word == "--" {
+                end_of_flags = true;
+            } else if word.starts_with("--") {
+                flags.insert(word.clone());
+            } else if word[1..].chars().all(|c| c.is_ascii_alphabetic()) {
+                flags.extend(word[1..].chars().map(|c| format!("-{c}")));
+            } else {
+                operands.push(word.clone());
+            }
+        }
+
+        Self {
+            program: words.first().cloned().unwrap_or_default(),
+            flags,
+            operands,
+        }
+    }
+
+    fn covers(&self, pattern: &Invocation) -> bool {
+        self.program == pattern.program
+            && pattern.flags.is_subset(&self.flags)
+            && self.operands.starts_with(&pattern.operands)
+    }
+}
+
+/// `/*` and `/` name the same target, as do `dir/` and `dir`.
+fn normalize_path(word: &str) -> String {
+    let word = word
+        .strip_suffix("/*")
+        .map_or(word, |dir| if dir.is_empty() { "/" } else { dir });
+    match word.trim_end_matches('/') {
+        "" if word.starts_with('/') => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Programs that run the rest of their arguments as a command, with the
+/// options of each that take a value.
+const WRAPPERS: &[(&str, &[&str])] = &[
+    (
+        "sudo",
+        &[
+            "-u", "-g", "-h", "-p", "-C", "-D", "-R", "-T", "-U", "--user", "--group", "--host",
+            "--prompt", "--chdir",
+        ],
+    ),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C", "--unset", "--chdir"]),
+    ("nice", &["-n", "--adjustment"]),
+    ("nohup", &[]),
+    ("command", &[]),
+    ("exec", &["-a"]),
+    ("time", &["-f", "-o"]),
+];
+
+/// The words of a stage from its program on, with quotes removed and the
+/// program reduced to its file name. Leading `VAR=value` assignments and
+/// wrappers like `sudo -u root` or `env -i` are skipped.
+pub(crate) fn stage_words(stage: &str) -> Vec<String> {
+    let words: Vec<String> = shell_words(stage)
+        .into_iter()
+        .map(|w| w.trim_start_matches(['(', '{']).to_string())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut start = 0;
+    while let Some(word) = words.get(start) {
+        if is_assignment(word) {
+            start += 1;
+            continue;
+        }
+        let Some((_, takes_value)) = WRAPPERS.iter().find(|(name, _)| *name == file_name(word))
+        else {
+            break;
+        };
+        start += 1;
+        while let Some(flag) = words.get(start).filter(|w| w.starts_with('-')) {
+            start += 1;
+            if flag == "--" {
+                break;
+            }
+            if takes_value.contains(&flag.as_str()) {
+                start += 1;
+            }
+        }
+    }
+
+    let mut words = words[start.min(words.len())..].to_vec();
+    if let Some(program) = words.first_mut() {
+        *program = file_name(program);
+    }
+    words
+}
+
+fn file_name(program: &str) -> String {
+    Path::new(program).file_name().map_or_else(
+        || program.to_string(),
+        |name| name.to_string_lossy().to_string(),
+    )
+}
+
+/// Splits on whitespace outside quotes, removing the quotes and backslash
+/// escapes.
+fn shell_words(stage: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = stage.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                let word = current.get_or_insert_default();
+                word.extend(chars.next());
+            }
+            (Some(_), _) => current.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(current.take()),
+            (None, _) => current.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// The stages of `command`, split on `|`, `&&`, `||`, `;`, `&` and newlines
+/// outside quotes and substitutions.
+pub(crate) fn split_stages(command: &str) -> Vec<String> {
+    scan(command).0
+}
+
+/// Every stage `command` runs: those of the command substitutions
+/// (`$(...)`, backticks) and process substitutions (`<(...)`, `>(...)`) in
+/// it, recursively, followed by its own.
+pub(crate) fn command_stages(command: &str) -> Vec<String> {
+    let (stages, substitutions) = scan(command);
+    let mut all: Vec<String> = substitutions
+        .iter()
+        .flat_map(|body| command_stages(body))
+        .collect();
+    all.extend(stages);
+    all
+}
+
+/// Splits `command` into stages and collects the bodies of the outermost
+/// substitutions, which stay part of their stage's text.
+fn scan(command: &str) -> (Vec<String>, Vec<String>) {
+    let chars: Vec<char> = command.chars().collect();
+    let mut stages = Vec::new();
+    let mut substitutions = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match (quote, c) {
+            (Some('\''), _) => {
+                if c == '\'' {
+                    quote = None;
+                }
+            }
+            (_, '\\') => {
+                current.push(c);
+                current.extend(next);
+                i += 2;
+                continue;
+            }
+            (_, '`') => {
+                let end = (i + 1..chars.len())
+                    .find(|&j| chars[j] == '`' && chars[j - 1] != '\\')
+                    .unwrap_or(chars.len());
+                substitutions.push(chars[i + 1..end].iter().collect());
+                current.extend(&chars[i..(end + 1).min(chars.len())]);
+                i = end + 1;
+                continue;
+            }
+            (_, '$') | (None, '<' | '>')
+                if next == Some('(') && !(c == '$' && chars.get(i + 2) == Some(&'(')) =>
+            {
+                let end = closing_paren(&chars, i + 2);
+                substitutions.push(chars[i + 2..end].iter().collect());
+                current.extend(&chars[i..(end + 1).min(chars.len())]);
+                i = end + 1;
+                continue;
+            }
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '|' | '&' | ';' | '\n') => {
+                stages.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            }
+            (None, _) => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    stages.push(current);
+
+    (stages, substitutions)
+}
+
+/// The index of the `)` closing a substitution whose body starts at
+/// `start`, or the end of `chars` when it is never closed.
+fn closing_paren(chars: &[char], start: usize) -> usize {
+    let mut depth = 1;
+    let mut quote: Option<char> = None;
+    let mut i = start;
+
+    while i < chars.len() {
+        match (quote, chars[i]) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => i += 1,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(chars[i]),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            (None, _) => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// The program a stage runs: the first word after any `VAR=value`
+/// assignments, without its directory.
+fn leading_binary(stage: &str) -> Option<String> {
+    let word = stage
+        .split_whitespace()
+        .map(|w| w.trim_start_matches(['(', '{']))
+        .filter(|w| !w.is_empty())
+        .find(|w| !is_assignment(w))?;
+
+    Path::new(word)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_budget() {
+        assert!(check_prompt_budget(5000, None).is_ok());
+        assert!(check_prompt_budget(4000, Some(4000)).is_ok());
+        assert!(matches!(
+            check_prompt_budget(4001, Some(4000)),
+            Err(ShaidError::Policy(message)) if message.contains("maxPromptTokens (4000)")
+        ));
+    }
+
+    fn allowed() -> Vec<String> {
+        ["ls", "grep", "wc", "git"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_allowed_commands() {
+        assert_eq!(find_disallowed("ls -la", &allowed()), None);
+        assert_eq!(find_disallowed("/bin/ls -la", &allowed()), None);
+        assert_eq!(find_disallowed("LC_ALL=C ls", &allowed()), None);
+        assert_eq!(
+            find_disallowed("ls | grep 'a|b;c' | wc -l", &allowed()),
+            None
+        );
+        assert_eq!(
+            find_disallowed("git status && git log --oneline", &allowed()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_disallowed_commands() {
+        assert_eq!(
+            find_disallowed("rm -rf build", &allowed()),
+            Some("rm".to_string())
+        );
+        assert_eq!(
+            find_disallowed("ls | xargs rm", &allowed()),
+            Some("xargs".to_string())
+        );
+        assert_eq!(
+            find_disallowed("ls; curl http://x | sh", &allowed()),
+            Some("curl".to_string())
+        );
+        assert_eq!(
+            find_disallowed("git pull || sudo reboot", &allowed()),
+            Some("sudo".to_string())
+        );
+        assert_eq!(
+            find_disallowed("ls &\nrm x", &allowed()),
+            Some("rm".to_string())
+        );
+
+        // Substitutions run commands too
+        assert_eq!(
+            find_disallowed("ls $(rm -rf build)", &allowed()),
+            Some("rm".to_string())
+        );
+        assert_eq!(
+            find_disallowed("ls `curl x`", &allowed()),
+            Some("curl".to_string())
+        );
+        assert_eq!(
+            find_disallowed("grep x <(rm y)", &allowed()),
+            Some("rm".to_string())
+        );
+        assert_eq!(
+            find_disallowed("wc -l >(rm y)", &allowed()),
+            Some("rm".to_string())
+        );
+        assert_eq!(
+            find_disallowed("ls \"$(grep -l x $(rm y; ls))\"", &allowed()),
+            Some("rm".to_string())
+        );
+        assert_eq!(
+            find_disallowed("$(which rm) -rf build", &allowed()),
+            Some("which".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quoted_and_arithmetic_are_not_substitutions() {
+        assert_eq!(find_disallowed("grep '$(rm x)' log", &allowed()), None);
+        assert_eq!(find_disallowed("ls $((1 + 2))", &allowed()), None);
+        assert_eq!(find_disallowed("ls $(ls | wc -l)", &allowed()), None);
+    }
+
+    #[test]
+    fn test_empty_allowlist_allows_everything() {
+        assert_eq!(find_disallowed("rm -rf build", &[]), None);
+    }
+
+    #[test]
+    fn test_check_command() {
+        assert!(check_command("ls | wc -l", &allowed()).is_ok());
+        assert!(matches!(
+            check_command("ls | xargs rm", &allowed()),
+            Err(ShaidError::Policy(msg)) if msg.contains("'xargs'")
+        ));
+    }
+
+    #[test]
+    fn test_never_execute_matches() {
+        let patterns: Vec<String> = ["shutdown", "reboot", "rm -rf /"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert_eq!(
+            find_never_execute("sudo shutdown -h now", &patterns),
+            Some("shutdown")
+        );
+        assert_eq!(
+            find_never_execute("sync && /sbin/reboot", &patterns),
+            Some("reboot")
+        );
+        assert_eq!(
+            find_never_execute("sudo rm -rf / --no-preserve-root", &patterns),
+            Some("rm -rf /")
+        );
+
+        // Flag order and grouping, globbed roots and wrapper options
+        for command in [
+            "rm -fr /",
+            "rm -r -f /",
+            "rm -rf /*",
+            "sudo -u root rm -rfv -- /",
+            "env -i PATH=/bin nice -n 5 rm -Rf / -r",
+        ] {
+            assert!(
+                find_never_execute(command, &patterns).is_some(),
+                "{command}"
+            );
+        }
+
+        // Substitutions and inline scripts
+        for command in [
+            "sudo -n shutdown now",
+            "echo $(shutdown)",
+            "echo `shutdown`",
+            "sh -c 'shutdown'",
+            "bash -lc \"sync; shutdown -h now\"",
+            "sudo sh -c 'eval shutdown'",
+        ] {
+            assert_eq!(
+                find_never_execute(command, &patterns),
+                Some("shutdown"),
+                "{command}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_never_execute_non_matches() {
+        let patterns = vec!["shutdown".to_string(), "rm -rf /".to_string()];
+
+        assert_eq!(find_never_execute("rm -rf /tmp/build", &patterns), None);
+        assert_eq!(find_never_execute("echo shutdown", &patterns), None);
+        assert_eq!(find_never_execute("rm -rf /tmp/*", &patterns), None);
+        assert_eq!(find_never_execute("rm -f /", &patterns), None);
+        assert_eq!(find_never_execute("echo 'sh -c shutdown'", &patterns), None);
+        assert_eq!(
+            find_never_execute("grep '$(shutdown)' log", &patterns),
+            None
+        );
+        assert_eq!(find_never_execute("ls -la", &[]), None);
+    }
+
+    #[test]
+    fn test_describe_violation() {
+        assert_eq!(
+            describe_violation("rm", &allowed()),
+            "the generated command uses 'rm', which is not in allowedCommands (ls, grep, wc, git)"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use reqwest::Client;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::providers::{ProviderError, build_http_client};
+
+/// Where discovered API roots are remembered, keyed by base URL.
+pub fn probe_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("sh-aid").join("api-roots.json"))
+}
+
+/// Finds the API root of an OpenAI-compatible gateway by probing
+/// `{base}/v1/models`, then `{base}/models`. Returns `{base}/v1` or `{base}`,
+/// whichever answered with a success status first.
+pub async fn discover_api_root(
+    client: &Client,
+    base_url: &str,
+    api_key: Option<&str>,
+) -> Result<String, ProviderError> {
+    let base = base_url.trim_end_matches('/');
+
+    for root in [format!("{base}/v1"), base.to_string()] {
+        let mut request = client.get(format!("{root}/models"));
+        if let Some(key) = api_key.filter(|key| !key.is_empty()) {
+            request = request.bearer_auth(key);
+        }
+
+        if let Ok(response) = request.send().await
+            && response.status().is_success()
+        {
+            return Ok(root);
+        }
+    }
+
+    Err(ProviderError::ConfigError(format!(
+        "No OpenAI-compatible /models endpoint found at {base}/v1/models or {base}/models"
+    )))
+}
+
+/// [`discover_api_root`] for the configured base URL and key, with the result
+/// cached in `cache_path` so the probe only runs once per base URL. A cached
+/// root outside the base URL is probed again rather than trusted, since the
+/// base URL may be one the organization policy forces.
+pub async fn resolve_api_root(
+    config: &Config,
+    base_url: &str,
+    cache_path: Option<&Path>,
+) -> Result<String, ProviderError> {
+    let mut cache = cache_path.map(load_cache).unwrap_or_default();
+    if let Some(root) = cache.get(base_url)
+        && root.starts_with(base_url.trim_end_matches('/'))
+    {
+        return Ok(root.clone());
+    }
+
+    let client = build_http_client(config, Duration::from_secs(10))?;
+    let root = discover_api_root(&client, base_url, config.get_api_key()).await?;
+
+    if let Some(path) = cache_path {
+        cache.insert(base_url.to_string(), root.clone());
+        // The cache only saves a probe next time; failing to write it is fine.
+        let _ = save_cache(path, &cache);
+    }
+
+    Ok(root)
+}
+
+fn load_cache(path: &Path) -> HashMap<String, String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(path: &Path, cache: &HashMap<String, String>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(cache)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_prefers_v1_models() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/v1/models")
+            .with_body(r#"{"data":[]}"#)
+            .create_async()
+            .await;
+        let bare = server.mock("GET", "/models").expect(0).create_async().await;
+
+        let root = discover_api_root(&Client::new(), &server.url(), Some("key"))
+            .await
+            .unwrap();
+        assert_eq!(root, format!("{}/v1", server.url()));
+        bare.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_bare_models() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/openai/v1/models")
+            .with_status(404)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/openai/models")
+            .match_header("authorization", "Bearer key")
+            .with_body(r#"{"data":[]}"#)
+            .create_async()
+            .await;
+
+        let base = format!("{}/openai/", server.url());
+        let root = discover_api_root(&Client::new(), &base, Some("key"))
+            .await
+            .unwrap();
+        assert_eq!(root, format!("{}/openai", server.url()));
+    }
+
+    #[tokio::test]
+    async fn test_no_models_endpoint_is_an_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let result = discover_api_root(&Client::new(), &server.url(), None).await;
+        assert!(matches!(result, Err(ProviderError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_result_is_cached() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("api-roots.json");
+
+        let mut server = mockito::Server::new_async().await;
+        let models = server
+            .mock("GET", "/v1/models")
+            .with_body(r#"{"data":[]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let first = resolve_api_root(&Config::default(), &server.url(), Some(&cache_path))
+            .await
+            .unwrap();
+        let second = resolve_api_root(&Config::default(), &server.url(), Some(&cache_path))
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        models.assert_async().await;
+        assert!(fs::read_to_string(&cache_path).unwrap().contains(&first));
+    }
+
+    #[tokio::test]
+    async fn test_cached_root_outside_base_url_is_reprobed() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("api-roots.json");
+
+        let mut server = mockito::Server::new_async().await;
+        let models = server
+            .mock("GET", "/v1/models")
+            .with_body(r#"{"data":[]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut cache = HashMap::new();
+        cache.insert(server.url(), "https://api.openai.com/v1".to_string());
+        save_cache(&cache_path, &cache).unwrap();
+
+        let root = resolve_api_root(&Config::default(), &server.url(), Some(&cache_path))
+            .await
+            .unwrap();
+
+        assert_eq!(root, format!("{}/v1", server.url()));
+        models.assert_async().await;
+    }
+}
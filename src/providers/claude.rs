@@ -1,45 +1,491 @@
 use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
 
-use super::{AIProvider, ModelInfo, ProviderError};
+use super::sanitize::sanitize_command;
+use super::{
+    AIProvider, DEFAULT_REQUEST_TIMEOUT, GenerateOptions, ModelInfo, ProviderError,
+    build_http_client, read_response_body,
+};
 use crate::config::Config;
 
-pub struct ClaudeProvider;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Serialize)]
+struct ClaudeMessage {
+    role: String,
+    content: String,
+}
+
+/// Messages API request. Claude takes the system prompt as a top-level field
+/// rather than as a message.
+#[derive(Debug, Serialize)]
+struct ClaudeRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<ClaudeMessage>,
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeResponse {
+    #[serde(default)]
+    content: Vec<ClaudeContentBlock>,
+    stop_reason: Option<String>,
+}
+
+/// `GET /v1/models`.
+#[derive(Debug, Deserialize)]
+struct ClaudeModelList {
+    data: Vec<ClaudeModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeModelEntry {
+    id: String,
+}
+
+#[derive(Clone)]
+pub struct ClaudeProvider {
+    client: Client,
+    api_key: String,
+    model: String,
+    base_url: String,
+}
 
 impl ClaudeProvider {
-    pub fn new(_config: &Config) -> Result<Self, ProviderError> {
-        // This is a placeholder implementation.
-        Err(ProviderError::ConfigError(
-            "Claude provider is not yet implemented.".to_string(),
-        ))
+    pub fn new(config: &Config) -> Result<Self, ProviderError> {
+        let api_key = config
+            .get_api_key()
+            .ok_or_else(|| ProviderError::ConfigError("API key is required".to_string()))?
+            .to_string();
+
+        if api_key.is_empty() {
+            return Err(ProviderError::ConfigError(
+                "API key cannot be empty".to_string(),
+            ));
+        }
+
+        let base_url = config
+            .get_base_url()
+            .unwrap_or("https://api.anthropic.com")
+            .trim_end_matches('/')
+            .to_string();
+
+        let client = build_http_client(config, DEFAULT_REQUEST_TIMEOUT)?;
+
+        Ok(Self {
+            client,
+            api_key,
+            model: config.model.clone(),
+            base_url,
+        })
+    }
+
+    fn build_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> ClaudeRequest {
+        ClaudeRequest {
+            model: self.model.clone(),
+            max_tokens: options.max_tokens.unwrap_or(1024),
+            system: system_prompt.to_string(),
+            messages: vec![ClaudeMessage {
+                role: "user".to_string(),
+                content: user_prompt.to_string(),
+            }],
+            // Use deterministic responses for command generation
+            temperature: Some(options.temperature.unwrap_or(0.0)),
+            stop_sequences: options.stop.clone(),
+        }
+    }
+}
+
+fn parse_model_list(body: &[u8]) -> Result<Vec<ModelInfo>, ProviderError> {
+    let list: ClaudeModelList = serde_json::from_slice(body).map_err(|e| {
+        ProviderError::InvalidResponse(format!("Failed to parse models response: {e}"))
+    })?;
+
+    Ok(list
+        .data
+        .into_iter()
+        .map(|entry| ModelInfo {
+            name: entry.id,
+            provider: "Claude".to_string(),
+            max_tokens: None,
+            supports_system_prompt: true,
+        })
+        .collect())
+}
+
+fn parse_response(response: ClaudeResponse) -> Result<String, ProviderError> {
+    let stop_reason = response.stop_reason.as_deref();
+
+    if stop_reason == Some("refusal") {
+        return Err(ProviderError::ContentBlocked(
+            "Claude declined to answer (stop_reason: refusal)".to_string(),
+        ));
     }
+
+    let text = response
+        .content
+        .iter()
+        .find(|block| block.block_type == "text")
+        .and_then(|block| block.text.as_deref())
+        .map(sanitize_command)
+        .unwrap_or_default();
+
+    if text.is_empty() {
+        return Err(ProviderError::InvalidResponse(match stop_reason {
+            Some("max_tokens") => "Empty response: output hit the max_tokens limit".to_string(),
+            Some(reason) => format!("Empty command response (stop_reason: {reason})"),
+            None => "Empty command response".to_string(),
+        }));
+    }
+
+    Ok(text)
 }
 
 #[async_trait]
 impl AIProvider for ClaudeProvider {
-    async fn generate_command(
+    async fn generate_command_with(
         &self,
-        _system_prompt: &str,
-        _user_prompt: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
     ) -> Result<String, ProviderError> {
-        Err(ProviderError::Unknown(
-            "Claude provider is not yet implemented.".to_string(),
-        ))
+        let request = self.build_request(system_prompt, user_prompt, options);
+        let url = format!("{}/v1/messages", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ProviderError::AuthenticationError(
+                "Invalid API key or authentication failed".to_string(),
+            ));
+        }
+
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(ProviderError::RateLimitError(
+                "Rate limit exceeded. Please try again later.".to_string(),
+            ));
+        }
+
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError {
+                status_code: status.as_u16(),
+                message: error_text,
+            }
+            .redact(&self.api_key));
+        }
+
+        let body = read_response_body(response).await?;
+        let claude_response: ClaudeResponse = serde_json::from_slice(&body).map_err(|e| {
+            ProviderError::InvalidResponse(format!("Failed to parse JSON response: {e}"))
+        })?;
+
+        parse_response(claude_response)
     }
 
-    fn validate_config(&self, _config: &Config) -> Result<(), ProviderError> {
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/v1/models?limit=1000", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ProviderError::AuthenticationError(
+                "Invalid API key or authentication failed".to_string(),
+            ));
+        }
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError {
+                status_code: status.as_u16(),
+                message: error_text,
+            }
+            .redact(&self.api_key));
+        }
+
+        let body = read_response_body(response).await?;
+        parse_model_list(&body)
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        if config.get_api_key().is_none_or(|key| key.is_empty()) {
+            return Err(ProviderError::ConfigError(
+                "API key is required".to_string(),
+            ));
+        }
+
+        if config.model.is_empty() {
+            return Err(ProviderError::ConfigError(
+                "Model name is required".to_string(),
+            ));
+        }
+
+        if let Some(base_url) = config.get_base_url()
+            && !base_url.starts_with("http://")
+            && !base_url.starts_with("https://")
+        {
+            return Err(ProviderError::ConfigError(
+                "Base URL must start with http:// or https://".to_string(),
+            ));
+        }
+
         Ok(())
     }
 
     fn get_model_info(&self) -> ModelInfo {
         ModelInfo {
-            name: "claude-3-5-sonnet-20241022".to_string(),
+            name: self.model.clone(),
             provider: "Claude".to_string(),
-            max_tokens: Some(4096),
+            max_tokens: Some(1024),
             supports_system_prompt: true,
         }
     }
 
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(self.clone())
+    }
+
     fn get_provider_name(&self) -> &'static str {
         "Claude"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderType;
+
+    fn create_test_config() -> Config {
+        Config {
+            provider_type: ProviderType::Claude,
+            api_key: Some("test-key".to_string()),
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            base_url: None,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_claude_provider_creation() {
+        let provider = ClaudeProvider::new(&create_test_config()).unwrap();
+        assert_eq!(provider.api_key, "test-key");
+        assert_eq!(provider.base_url, "https://api.anthropic.com");
+
+        let config = Config {
+            base_url: Some("https://proxy.example/".to_string()),
+            ..create_test_config()
+        };
+        assert_eq!(
+            ClaudeProvider::new(&config).unwrap().base_url,
+            "https://proxy.example"
+        );
+
+        let config = Config {
+            api_key: None,
+            ..create_test_config()
+        };
+        assert!(matches!(
+            ClaudeProvider::new(&config),
+            Err(ProviderError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_model_info_reflects_configured_model() {
+        let config = Config {
+            model: "custom-model".to_string(),
+            ..create_test_config()
+        };
+        let provider = ClaudeProvider::new(&config).unwrap();
+        assert_eq!(provider.model_id(), "custom-model");
+        assert_eq!(provider.get_model_info().name, "custom-model");
+    }
+
+    #[test]
+    fn test_parse_model_list() {
+        let body = br#"{
+            "data": [
+                {"type": "model", "id": "claude-sonnet-4-20250514", "display_name": "Claude Sonnet 4"},
+                {"type": "model", "id": "claude-3-5-haiku-20241022", "display_name": "Claude Haiku 3.5"}
+            ],
+            "has_more": false
+        }"#;
+
+        let names: Vec<String> = parse_model_list(body)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["claude-sonnet-4-20250514", "claude-3-5-haiku-20241022"]
+        );
+    }
+
+    #[test]
+    fn test_build_request() {
+        let provider = ClaudeProvider::new(&create_test_config()).unwrap();
+        let json = serde_json::to_value(provider.build_request(
+            "system prompt",
+            "user prompt",
+            &GenerateOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "model": "claude-3-5-sonnet-20241022",
+                "max_tokens": 1024,
+                "system": "system prompt",
+                "messages": [{"role": "user", "content": "user prompt"}],
+                "temperature": 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn test_options_override_defaults() {
+        let provider = ClaudeProvider::new(&create_test_config()).unwrap();
+        let options = GenerateOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(64),
+            seed: Some(7),
+            stop: vec!["\n\n".to_string()],
+        };
+        let json = serde_json::to_value(provider.build_request("s", "u", &options)).unwrap();
+
+        assert_eq!(json["temperature"], 0.5);
+        assert_eq!(json["max_tokens"], 64);
+        assert_eq!(json["stop_sequences"], serde_json::json!(["\n\n"]));
+        // The Messages API has no seed
+        assert!(json.get("seed").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_command_sends_messages_request() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_header("x-api-key", "test-key")
+            .match_header("anthropic-version", ANTHROPIC_VERSION)
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"system": "sys", "messages": [{"role": "user", "content": "list"}]}),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"content":[{"type":"text","text":"ls -la"}],"stop_reason":"end_turn"}"#)
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = ClaudeProvider::new(&config).unwrap();
+
+        let result = provider.generate_command("sys", "list").await;
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "ls -la");
+    }
+
+    #[tokio::test]
+    async fn test_http_status_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = ClaudeProvider::new(&config).unwrap();
+
+        for (status, check) in [
+            (
+                401,
+                (|e| matches!(e, ProviderError::AuthenticationError(_)))
+                    as fn(&ProviderError) -> bool,
+            ),
+            (429, |e| matches!(e, ProviderError::RateLimitError(_))),
+            (
+                529,
+                |e| matches!(e, ProviderError::ApiError { status_code: 529, message } if message.contains("overloaded")),
+            ),
+        ] {
+            let mock = server
+                .mock("POST", "/v1/messages")
+                .with_status(status)
+                .with_body(r#"{"type":"error","error":{"type":"overloaded_error","message":"overloaded"}}"#)
+                .create_async()
+                .await;
+
+            let error = provider.generate_command("s", "u").await.unwrap_err();
+            assert!(check(&error), "status {status}: {error:?}");
+            mock.remove_async().await;
+        }
+    }
+
+    fn parse(json: &str) -> Result<String, ProviderError> {
+        parse_response(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn test_parse_end_turn() {
+        let result =
+            parse(r#"{"content":[{"type":"text","text":" ls -la \n"}],"stop_reason":"end_turn"}"#);
+        assert_eq!(result.unwrap(), "ls -la");
+    }
+
+    #[test]
+    fn test_parse_refusal_is_blocked() {
+        let result = parse(r#"{"content":[],"stop_reason":"refusal"}"#);
+        assert!(matches!(result, Err(ProviderError::ContentBlocked(_))));
+    }
+
+    #[test]
+    fn test_parse_empty_max_tokens() {
+        let result = parse(r#"{"content":[],"stop_reason":"max_tokens"}"#);
+        match result {
+            Err(ProviderError::InvalidResponse(msg)) => assert!(msg.contains("max_tokens")),
+            other => panic!("Expected InvalidResponse, got {other:?}"),
+        }
+    }
+}
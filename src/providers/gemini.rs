@@ -1,45 +1,568 @@
 use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
 
-use super::{AIProvider, ModelInfo, ProviderError};
+use super::sanitize::sanitize_command;
+use super::{
+    AIProvider, DEFAULT_REQUEST_TIMEOUT, GenerateOptions, ModelInfo, ProviderError,
+    build_http_client, read_response_body,
+};
 use crate::config::Config;
 
-pub struct GeminiProvider;
+#[derive(Debug, Serialize, Deserialize)]
+struct GeminiPart {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    temperature: f32,
+    max_output_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// `generateContent` request. The system prompt goes in `systemInstruction`;
+/// `contents` only carries the user turn.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    system_instruction: GeminiContent,
+    contents: Vec<GeminiContent>,
+    generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    content: Option<GeminiContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    prompt_feedback: Option<GeminiPromptFeedback>,
+}
+
+/// Finish reasons that mean the candidate was withheld by Gemini's filters.
+const BLOCKED_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+/// `GET /v1beta/models`.
+#[derive(Debug, Deserialize)]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelEntry {
+    /// `models/gemini-2.0-flash`
+    name: String,
+    output_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiErrorBody {
+    error: GeminiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiError {
+    message: String,
+    status: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct GeminiProvider {
+    client: Client,
+    api_key: String,
+    model: String,
+    base_url: String,
+}
 
 impl GeminiProvider {
-    pub fn new(_config: &Config) -> Result<Self, ProviderError> {
-        // This is a placeholder implementation.
-        Err(ProviderError::ConfigError(
-            "Gemini provider is not yet implemented.".to_string(),
-        ))
+    pub fn new(config: &Config) -> Result<Self, ProviderError> {
+        let api_key = config
+            .get_api_key()
+            .ok_or_else(|| ProviderError::ConfigError("API key is required".to_string()))?
+            .to_string();
+
+        if api_key.is_empty() {
+            return Err(ProviderError::ConfigError(
+                "API key cannot be empty".to_string(),
+            ));
+        }
+
+        let base_url = config
+            .get_base_url()
+            .unwrap_or("https://generativelanguage.googleapis.com")
+            .trim_end_matches('/')
+            .to_string();
+
+        let client = build_http_client(config, DEFAULT_REQUEST_TIMEOUT)?;
+
+        Ok(Self {
+            client,
+            api_key,
+            model: config.model.clone(),
+            base_url,
+        })
+    }
+
+    fn build_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> GeminiRequest {
+        GeminiRequest {
+            system_instruction: GeminiContent {
+                role: None,
+                parts: vec![GeminiPart {
+                    text: Some(system_prompt.to_string()),
+                }],
+            },
+            contents: vec![GeminiContent {
+                role: Some("user".to_string()),
+                parts: vec![GeminiPart {
+                    text: Some(user_prompt.to_string()),
+                }],
+            }],
+            generation_config: GeminiGenerationConfig {
+                // Use deterministic responses for command generation
+                temperature: options.temperature.unwrap_or(0.0),
+                max_output_tokens: options.max_tokens.unwrap_or(1024),
+                seed: options.seed,
+                stop_sequences: options.stop.clone(),
+            },
+        }
+    }
+}
+
+/// Maps a failed `generateContent` call onto `ProviderError`, using the
+/// message from Gemini's error body when there is one.
+fn map_error_status(status: reqwest::StatusCode, body: &str) -> ProviderError {
+    let error = serde_json::from_str::<GeminiErrorBody>(body)
+        .ok()
+        .map(|body| body.error);
+    let message = error
+        .as_ref()
+        .map_or_else(|| body.to_string(), |e| e.message.clone());
+
+    match status.as_u16() {
+        // Gemini reports a bad key as 400 INVALID_ARGUMENT
+        400 if message.contains("API key") => ProviderError::AuthenticationError(message),
+        401 | 403 => ProviderError::AuthenticationError(message),
+        429 => ProviderError::RateLimitError(message),
+        code => ProviderError::ApiError {
+            status_code: code,
+            message: match error.and_then(|e| e.status) {
+                Some(status) => format!("{status}: {message}"),
+                None => message,
+            },
+        },
+    }
+}
+
+/// The models of a listing that can generate content; embedding models and
+/// the like are left out.
+fn parse_model_list(body: &[u8]) -> Result<Vec<ModelInfo>, ProviderError> {
+    let list: GeminiModelList = serde_json::from_slice(body).map_err(|e| {
+        ProviderError::InvalidResponse(format!("Failed to parse models response: {e}"))
+    })?;
+
+    Ok(list
+        .models
+        .into_iter()
+        .filter(|model| {
+            model
+                .supported_generation_methods
+                .iter()
+                .any(|method| method == "generateContent")
+        })
+        .map(|model| ModelInfo {
+            name: model
+                .name
+                .strip_prefix("models/")
+                .unwrap_or(&model.name)
+                .to_string(),
+            provider: "Gemini".to_string(),
+            max_tokens: model.output_token_limit,
+            supports_system_prompt: true,
+        })
+        .collect())
+}
+
+fn parse_response(response: GeminiResponse) -> Result<String, ProviderError> {
+    if let Some(reason) = response
+        .prompt_feedback
+        .and_then(|feedback| feedback.block_reason)
+    {
+        return Err(ProviderError::ContentBlocked(format!(
+            "Gemini blocked the prompt (blockReason: {reason})"
+        )));
+    }
+
+    let candidate =
+        response.candidates.into_iter().next().ok_or_else(|| {
+            ProviderError::InvalidResponse("No candidates in response".to_string())
+        })?;
+
+    let finish_reason = candidate.finish_reason.as_deref();
+    if let Some(reason) = finish_reason
+        && BLOCKED_FINISH_REASONS.contains(&reason)
+    {
+        return Err(ProviderError::ContentBlocked(format!(
+            "Gemini withheld the response (finishReason: {reason})"
+        )));
+    }
+
+    let text = candidate
+        .content
+        .and_then(|content| content.parts.into_iter().find_map(|part| part.text))
+        .unwrap_or_default();
+    let text = sanitize_command(&text);
+
+    if text.is_empty() {
+        return Err(ProviderError::InvalidResponse(match finish_reason {
+            Some("MAX_TOKENS") => "Empty response: output hit the MAX_TOKENS limit".to_string(),
+            Some(reason) => format!("Empty command response (finishReason: {reason})"),
+            None => "Empty command response".to_string(),
+        }));
     }
+
+    Ok(text)
 }
 
 #[async_trait]
 impl AIProvider for GeminiProvider {
-    async fn generate_command(
+    async fn generate_command_with(
         &self,
-        _system_prompt: &str,
-        _user_prompt: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
     ) -> Result<String, ProviderError> {
-        Err(ProviderError::Unknown(
-            "Gemini provider is not yet implemented.".to_string(),
-        ))
+        let request = self.build_request(system_prompt, user_prompt, options);
+        let url = format!(
+            "{}/v1beta/models/{}:generateContent",
+            self.base_url, self.model
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(map_error_status(status, &error_text).redact(&self.api_key));
+        }
+
+        let body = read_response_body(response).await?;
+        let gemini_response: GeminiResponse = serde_json::from_slice(&body).map_err(|e| {
+            ProviderError::InvalidResponse(format!("Failed to parse JSON response: {e}"))
+        })?;
+
+        parse_response(gemini_response)
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/v1beta/models?pageSize=1000", self.base_url))
+            .header("x-goog-api-key", &self.api_key)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(map_error_status(status, &error_text).redact(&self.api_key));
+        }
+
+        let body = read_response_body(response).await?;
+        parse_model_list(&body)
     }
 
-    fn validate_config(&self, _config: &Config) -> Result<(), ProviderError> {
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        if config.get_api_key().is_none_or(|key| key.is_empty()) {
+            return Err(ProviderError::ConfigError(
+                "API key is required".to_string(),
+            ));
+        }
+
+        if config.model.is_empty() {
+            return Err(ProviderError::ConfigError(
+                "Model name is required".to_string(),
+            ));
+        }
+
+        if let Some(base_url) = config.get_base_url()
+            && !base_url.starts_with("http://")
+            && !base_url.starts_with("https://")
+        {
+            return Err(ProviderError::ConfigError(
+                "Base URL must start with http:// or https://".to_string(),
+            ));
+        }
+
         Ok(())
     }
 
     fn get_model_info(&self) -> ModelInfo {
         ModelInfo {
-            name: "gemini-1.5-pro".to_string(),
+            name: self.model.clone(),
             provider: "Gemini".to_string(),
-            max_tokens: Some(8192),
+            max_tokens: Some(1024),
             supports_system_prompt: true,
         }
     }
 
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(self.clone())
+    }
+
     fn get_provider_name(&self) -> &'static str {
         "Gemini"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderType;
+
+    fn create_test_config() -> Config {
+        Config {
+            provider_type: ProviderType::Gemini,
+            api_key: Some("test-key".to_string()),
+            model: "gemini-1.5-pro".to_string(),
+            base_url: None,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_gemini_provider_creation() {
+        let provider = GeminiProvider::new(&create_test_config()).unwrap();
+        assert_eq!(provider.api_key, "test-key");
+        assert_eq!(
+            provider.base_url,
+            "https://generativelanguage.googleapis.com"
+        );
+
+        let config = Config {
+            api_key: Some(String::new()),
+            ..create_test_config()
+        };
+        assert!(matches!(
+            GeminiProvider::new(&config),
+            Err(ProviderError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_model_list() {
+        let body = br#"{
+            "models": [
+                {
+                    "name": "models/gemini-2.0-flash",
+                    "outputTokenLimit": 8192,
+                    "supportedGenerationMethods": ["generateContent", "countTokens"]
+                },
+                {
+                    "name": "models/text-embedding-004",
+                    "supportedGenerationMethods": ["embedContent"]
+                }
+            ]
+        }"#;
+
+        let models = parse_model_list(body).unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "gemini-2.0-flash");
+        assert_eq!(models[0].max_tokens, Some(8192));
+    }
+
+    #[test]
+    fn test_model_info_reflects_configured_model() {
+        let config = Config {
+            model: "custom-model".to_string(),
+            ..create_test_config()
+        };
+        let provider = GeminiProvider::new(&config).unwrap();
+        assert_eq!(provider.model_id(), "custom-model");
+        assert_eq!(provider.get_model_info().name, "custom-model");
+    }
+
+    #[test]
+    fn test_build_request() {
+        let provider = GeminiProvider::new(&create_test_config()).unwrap();
+        let json = serde_json::to_value(provider.build_request(
+            "system prompt",
+            "user prompt",
+            &GenerateOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "systemInstruction": {"parts": [{"text": "system prompt"}]},
+                "contents": [{"role": "user", "parts": [{"text": "user prompt"}]}],
+                "generationConfig": {"temperature": 0.0, "maxOutputTokens": 1024}
+            })
+        );
+    }
+
+    #[test]
+    fn test_options_override_defaults() {
+        let provider = GeminiProvider::new(&create_test_config()).unwrap();
+        let options = GenerateOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(64),
+            seed: Some(7),
+            stop: vec!["\n\n".to_string()],
+        };
+        let json = serde_json::to_value(provider.build_request("s", "u", &options)).unwrap();
+
+        assert_eq!(
+            json["generationConfig"],
+            serde_json::json!({
+                "temperature": 0.5,
+                "maxOutputTokens": 64,
+                "seed": 7,
+                "stopSequences": ["\n\n"]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_command_calls_generate_content() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1beta/models/gemini-1.5-pro:generateContent")
+            .match_header("x-goog-api-key", "test-key")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"candidates":[{"content":{"role":"model","parts":[{"text":" df -h \n"}]},"finishReason":"STOP"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = GeminiProvider::new(&config).unwrap();
+
+        let result = provider.generate_command("sys", "disk space").await;
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "df -h");
+    }
+
+    #[test]
+    fn test_map_error_status() {
+        let bad_key = r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#;
+        assert!(matches!(
+            map_error_status(reqwest::StatusCode::BAD_REQUEST, bad_key),
+            ProviderError::AuthenticationError(msg) if msg.starts_with("API key not valid")
+        ));
+
+        let bad_request = r#"{"error":{"code":400,"message":"Invalid JSON payload","status":"INVALID_ARGUMENT"}}"#;
+        assert!(matches!(
+            map_error_status(reqwest::StatusCode::BAD_REQUEST, bad_request),
+            ProviderError::ApiError { status_code: 400, message }
+                if message == "INVALID_ARGUMENT: Invalid JSON payload"
+        ));
+
+        let denied =
+            r#"{"error":{"code":403,"message":"Permission denied","status":"PERMISSION_DENIED"}}"#;
+        assert!(matches!(
+            map_error_status(reqwest::StatusCode::FORBIDDEN, denied),
+            ProviderError::AuthenticationError(_)
+        ));
+
+        assert!(matches!(
+            map_error_status(reqwest::StatusCode::TOO_MANY_REQUESTS, "quota"),
+            ProviderError::RateLimitError(msg) if msg == "quota"
+        ));
+    }
+
+    fn parse(json: &str) -> Result<String, ProviderError> {
+        parse_response(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn test_parse_stop() {
+        let result = parse(
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"ls -la\n"}]},"finishReason":"STOP"}]}"#,
+        );
+        assert_eq!(result.unwrap(), "ls -la");
+    }
+
+    #[test]
+    fn test_parse_prompt_block() {
+        let result = parse(r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#);
+        match result {
+            Err(ProviderError::ContentBlocked(msg)) => assert!(msg.contains("SAFETY")),
+            other => panic!("Expected ContentBlocked, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_safety_finish_reason() {
+        let result = parse(r#"{"candidates":[{"finishReason":"SAFETY"}]}"#);
+        assert!(matches!(result, Err(ProviderError::ContentBlocked(_))));
+    }
+
+    #[test]
+    fn test_parse_no_candidates() {
+        let result = parse(r#"{"candidates":[]}"#);
+        assert!(matches!(result, Err(ProviderError::InvalidResponse(_))));
+    }
+}
@@ -1,18 +1,30 @@
 use async_trait::async_trait;
+use futures::Stream;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
 use thiserror::Error;
 
 pub mod claude;
 pub mod gemini;
+pub mod ollama;
 pub mod openai;
+pub mod sanitize;
+pub mod sse;
 
 pub use self::claude::ClaudeProvider;
 pub use self::gemini::GeminiProvider;
+pub use self::ollama::OllamaProvider;
 pub use self::openai::OpenAIProvider;
 
-use crate::config::{Config, ProviderType};
+use crate::cassette::CassetteProvider;
+use crate::config::{Config, HttpVersion, ProviderType, REDACTED, TlsBackend};
+use crate::model_fallback::ModelFallbackProvider;
+use crate::rate_limit::RateLimitedProvider;
+use crate::usage::TokenUsage;
 
-use reqwest;
+use reqwest::{self, Client};
+use std::time::Duration;
 
 #[derive(Debug, Error)]
 pub enum ProviderError {
@@ -31,6 +43,9 @@ pub enum ProviderError {
     #[error("Invalid response format: {0}")]
     InvalidResponse(String),
 
+    #[error("Response blocked by provider: {0}")]
+    ContentBlocked(String),
+
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
@@ -41,6 +56,51 @@ pub enum ProviderError {
     Unknown(String),
 }
 
+impl ProviderError {
+    /// Failures that may succeed on a later attempt: rate limits, timeouts
+    /// and network errors.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::RateLimitError(_)
+                | ProviderError::TimeoutError(_)
+                | ProviderError::HttpError(_)
+        )
+    }
+
+    /// Masks `secret` (the API key) wherever the error would print it. Some
+    /// servers echo the credentials they received in their error bodies.
+    pub fn redact(self, secret: &str) -> Self {
+        if secret.is_empty() {
+            return self;
+        }
+        let mask = |text: String| text.replace(secret, REDACTED);
+
+        match self {
+            ProviderError::HttpError(error)
+                if error.url().is_some_and(|url| url.as_str().contains(secret)) =>
+            {
+                ProviderError::HttpError(error.without_url())
+            }
+            ProviderError::HttpError(error) => ProviderError::HttpError(error),
+            ProviderError::ApiError {
+                status_code,
+                message,
+            } => ProviderError::ApiError {
+                status_code,
+                message: mask(message),
+            },
+            ProviderError::AuthenticationError(m) => ProviderError::AuthenticationError(mask(m)),
+            ProviderError::RateLimitError(m) => ProviderError::RateLimitError(mask(m)),
+            ProviderError::InvalidResponse(m) => ProviderError::InvalidResponse(mask(m)),
+            ProviderError::ContentBlocked(m) => ProviderError::ContentBlocked(mask(m)),
+            ProviderError::ConfigError(m) => ProviderError::ConfigError(mask(m)),
+            ProviderError::TimeoutError(m) => ProviderError::TimeoutError(mask(m)),
+            ProviderError::Unknown(m) => ProviderError::Unknown(mask(m)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub name: String,
@@ -49,61 +109,820 @@ pub struct ModelInfo {
     pub supports_system_prompt: bool,
 }
 
+/// Account credit reported by a provider, in US dollars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quota {
+    pub used: f64,
+    /// `None` when the key has no spending limit.
+    pub limit: Option<f64>,
+    pub remaining: Option<f64>,
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) => {
+                writeln!(f, "Remaining: ${remaining:.2} of ${limit:.2}")?
+            }
+            (Some(remaining), None) => writeln!(f, "Remaining: ${remaining:.2}")?,
+            (None, _) => writeln!(f, "Remaining: no limit")?,
+        }
+        writeln!(f, "Used:      ${:.2}", self.used)
+    }
+}
+
+/// Command text arriving incrementally; each item is one chunk (typically a
+/// token) in order.
+pub type CommandStream =
+    Pin<Box<dyn Stream<Item = std::result::Result<String, ProviderError>> + Send>>;
+
+/// Per-call overrides of the request parameters. `None` (or an empty `stop`)
+/// keeps the provider's default; parameters a provider does not support are
+/// ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerateOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Sampling seed, for providers that support reproducible output.
+    pub seed: Option<u64>,
+    /// Sequences at which generation stops.
+    pub stop: Vec<String>,
+}
+
+impl GenerateOptions {
+    /// These options, with `defaults` filling in the ones not set.
+    pub fn or(&self, defaults: &GenerateOptions) -> GenerateOptions {
+        GenerateOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            seed: self.seed.or(defaults.seed),
+            stop: if self.stop.is_empty() {
+                defaults.stop.clone()
+            } else {
+                self.stop.clone()
+            },
+        }
+    }
+}
+
+/// Sampling temperature for `--count`, so that repeated samples differ.
+pub const ALTERNATIVES_TEMPERATURE: f32 = 0.7;
+
+/// `options` for sampling alternatives: [`ALTERNATIVES_TEMPERATURE`] unless
+/// a temperature was given.
+pub fn alternatives_options(options: &GenerateOptions) -> GenerateOptions {
+    options.or(&GenerateOptions {
+        temperature: Some(ALTERNATIVES_TEMPERATURE),
+        ..GenerateOptions::default()
+    })
+}
+
 #[async_trait]
 pub trait AIProvider: Send + Sync {
     async fn generate_command(
         &self,
         system_prompt: &str,
         user_prompt: &str,
+    ) -> std::result::Result<String, ProviderError> {
+        self.generate_command_with(system_prompt, user_prompt, &GenerateOptions::default())
+            .await
+    }
+
+    /// Generates with per-call `options` overriding the provider defaults.
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
     ) -> std::result::Result<String, ProviderError>;
 
+    /// Up to `n` distinct commands for the same request (`--count`), with
+    /// `options` applied to each. The default makes `n` calls with
+    /// [`alternatives_options`] and drops duplicates; providers that can
+    /// sample several completions in one request override it.
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> std::result::Result<Vec<String>, ProviderError> {
+        if n <= 1 {
+            return Ok(vec![
+                self.generate_command_with(system_prompt, user_prompt, options)
+                    .await?,
+            ]);
+        }
+
+        let options = alternatives_options(options);
+        let mut commands = Vec::new();
+        for _ in 0..n {
+            let command = self
+                .generate_command_with(system_prompt, user_prompt, &options)
+                .await?;
+            if !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+        Ok(commands)
+    }
+
+    /// Streams the command as it is generated, with `options` applied.
+    /// Providers without native streaming yield the complete response as a
+    /// single chunk.
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> std::result::Result<CommandStream, ProviderError> {
+        let command = self
+            .generate_command_with(system_prompt, user_prompt, options)
+            .await?;
+        Ok(Box::pin(futures::stream::once(async move { Ok(command) })))
+    }
+
+    /// Remaining credit for the configured key, for providers that expose
+    /// it (`--quota`); `None` for the others.
+    async fn remaining_quota(&self) -> std::result::Result<Option<Quota>, ProviderError> {
+        Ok(None)
+    }
+
+    /// The models the provider offers (`sh-aid models`). Providers without
+    /// a listing endpoint report the configured model only.
+    async fn list_models(&self) -> std::result::Result<Vec<ModelInfo>, ProviderError> {
+        Ok(vec![self.get_model_info()])
+    }
+
+    /// Tokens used by all requests made so far through this provider and
+    /// its clones; `None` when the provider does not report usage.
+    fn token_usage(&self) -> Option<TokenUsage> {
+        None
+    }
+
+    /// Responses so far, through this provider and its clones, that stopped
+    /// at the token limit and may hold a cut-off command.
+    fn truncated_responses(&self) -> u32 {
+        0
+    }
+
+    /// The configured model, when it was unavailable and a request fell
+    /// back to the provider's default model (`modelFallback`).
+    fn fell_back_from(&self) -> Option<String> {
+        None
+    }
+
     fn validate_config(&self, config: &Config) -> std::result::Result<(), ProviderError>;
 
     fn get_model_info(&self) -> ModelInfo;
 
+    /// The model this provider sends requests for, exactly as configured.
+    fn model_id(&self) -> &str;
+
+    /// Returns an owned copy of this provider. Providers hold an `Arc`-backed
+    /// HTTP client, so this is cheap.
+    fn clone_box(&self) -> Box<dyn AIProvider>;
+
     fn get_provider_name(&self) -> &'static str;
 }
 
+impl Clone for Box<dyn AIProvider> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
 pub fn create_provider(config: &Config) -> std::result::Result<Box<dyn AIProvider>, ProviderError> {
-    match config.provider_type {
-        ProviderType::OpenAI | ProviderType::Custom => {
-            let provider = OpenAIProvider::new(config)?;
-            Ok(Box::new(provider))
+    let mut provider = create_base_provider(config)?;
+
+    let default_model = get_default_model_for_provider(&config.provider_type);
+    if config.model_fallback && config.model != default_model {
+        let fallback_config = Config {
+            model: default_model.to_string(),
+            ..config.clone()
+        };
+        provider = Box::new(ModelFallbackProvider::new(
+            provider,
+            create_base_provider(&fallback_config)?,
+        ));
+    }
+
+    provider = CassetteProvider::wrap_from_env(provider)?;
+
+    Ok(match config.requests_per_minute {
+        Some(rpm) => Box::new(RateLimitedProvider::new(provider, rpm)),
+        None => provider,
+    })
+}
+
+fn create_base_provider(
+    config: &Config,
+) -> std::result::Result<Box<dyn AIProvider>, ProviderError> {
+    Ok(match config.provider_type {
+        ProviderType::OpenAI | ProviderType::Custom => Box::new(OpenAIProvider::new(config)?),
+        ProviderType::Claude => Box::new(ClaudeProvider::new(config)?),
+        ProviderType::Gemini => Box::new(GeminiProvider::new(config)?),
+        ProviderType::Ollama => Box::new(OllamaProvider::new(config)?),
+    })
+}
+
+/// Wait before the first retry of a transient failure; doubled per retry.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Calls `provider`, retrying [transient](ProviderError::is_transient)
+/// failures up to `retries` times with exponential backoff (500ms, 1s, 2s,
+/// ...). Other errors are returned at once.
+pub async fn generate_command_with_retry(
+    provider: &dyn AIProvider,
+    system_prompt: &str,
+    user_prompt: &str,
+    retries: u32,
+) -> std::result::Result<String, ProviderError> {
+    with_backoff(retries, RETRY_BASE_DELAY, || {
+        provider.generate_command(system_prompt, user_prompt)
+    })
+    .await
+}
+
+async fn with_backoff<T, F, Fut>(
+    retries: u32,
+    base_delay: Duration,
+    mut attempt: F,
+) -> std::result::Result<T, ProviderError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = std::result::Result<T, ProviderError>>,
+{
+    let mut delay = base_delay;
+    for _ in 0..retries {
+        match attempt().await {
+            Err(error) if error.is_transient() => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
         }
-        ProviderType::Claude => {
-            let provider = ClaudeProvider::new(config)?;
-            Ok(Box::new(provider))
+    }
+    attempt().await
+}
+
+/// Retries transient failures of the inner provider (`maxRetries`).
+pub struct RetryProvider {
+    inner: Box<dyn AIProvider>,
+    retries: u32,
+    base_delay: Duration,
+}
+
+impl RetryProvider {
+    pub fn new(inner: Box<dyn AIProvider>, retries: u32) -> Self {
+        Self {
+            inner,
+            retries,
+            base_delay: RETRY_BASE_DELAY,
         }
-        ProviderType::Gemini => {
-            let provider = GeminiProvider::new(config)?;
-            Ok(Box::new(provider))
+    }
+}
+
+#[async_trait]
+impl AIProvider for RetryProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> std::result::Result<String, ProviderError> {
+        with_backoff(self.retries, self.base_delay, || {
+            self.inner
+                .generate_command_with(system_prompt, user_prompt, options)
+        })
+        .await
+    }
+
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> std::result::Result<Vec<String>, ProviderError> {
+        with_backoff(self.retries, self.base_delay, || {
+            self.inner
+                .generate_commands(system_prompt, user_prompt, n, options)
+        })
+        .await
+    }
+
+    /// Retries opening the stream; errors after the first chunk are not
+    /// retried.
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> std::result::Result<CommandStream, ProviderError> {
+        with_backoff(self.retries, self.base_delay, || {
+            self.inner
+                .generate_command_stream(system_prompt, user_prompt, options)
+        })
+        .await
+    }
+
+    async fn remaining_quota(&self) -> std::result::Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> std::result::Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> std::result::Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(RetryProvider {
+            inner: self.inner.clone_box(),
+            retries: self.retries,
+            base_delay: self.base_delay,
+        })
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+/// Applies `defaults` (e.g. from `--temperature`) to every call, under the
+/// options the call sets itself, including `--count` samples and streams.
+pub struct DefaultOptionsProvider {
+    inner: Box<dyn AIProvider>,
+    defaults: GenerateOptions,
+}
+
+impl DefaultOptionsProvider {
+    pub fn new(inner: Box<dyn AIProvider>, defaults: GenerateOptions) -> Self {
+        Self { inner, defaults }
+    }
+}
+
+#[async_trait]
+impl AIProvider for DefaultOptionsProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> std::result::Result<String, ProviderError> {
+        self.inner
+            .generate_command_with(system_prompt, user_prompt, &options.or(&self.defaults))
+            .await
+    }
+
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> std::result::Result<Vec<String>, ProviderError> {
+        self.inner
+            .generate_commands(system_prompt, user_prompt, n, &options.or(&self.defaults))
+            .await
+    }
+
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> std::result::Result<CommandStream, ProviderError> {
+        self.inner
+            .generate_command_stream(system_prompt, user_prompt, &options.or(&self.defaults))
+            .await
+    }
+
+    async fn remaining_quota(&self) -> std::result::Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> std::result::Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> std::result::Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(DefaultOptionsProvider::new(
+            self.inner.clone_box(),
+            self.defaults.clone(),
+        ))
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+/// Request timeout for hosted providers when `timeoutSeconds` is not set.
+pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The HTTP client the providers share: the configured `timeoutSeconds` (or
+/// `default_timeout`), compressed responses, the configured `proxy`, and the
+/// `httpVersion` and `tlsBackend` preferences.
+pub(crate) fn build_http_client(
+    config: &Config,
+    default_timeout: Duration,
+) -> std::result::Result<Client, ProviderError> {
+    http_client_builder(config, default_timeout)?
+        .build()
+        .map_err(|e| ProviderError::ConfigError(format!("Failed to create HTTP client: {e}")))
+}
+
+/// The builder behind [`build_http_client`], so tests can add a root
+/// certificate.
+pub(crate) fn http_client_builder(
+    config: &Config,
+    default_timeout: Duration,
+) -> std::result::Result<reqwest::ClientBuilder, ProviderError> {
+    let builder = Client::builder()
+        .timeout(config.request_timeout(default_timeout))
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+
+    let builder = match config.pool_max_idle_per_host {
+        Some(max) => builder.pool_max_idle_per_host(max),
+        None => builder,
+    };
+    let builder = match config.pool_idle_timeout_seconds {
+        Some(secs) => builder.pool_idle_timeout(Duration::from_secs(secs)),
+        None => builder,
+    };
+
+    let builder = match config.http_version.unwrap_or_default() {
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        HttpVersion::Auto => builder,
+    };
+
+    let builder = match &config.extra_headers {
+        Some(headers) => builder.default_headers(header_map(headers)?),
+        None => builder,
+    };
+
+    // Without a configured proxy, reqwest follows HTTPS_PROXY, HTTP_PROXY and
+    // NO_PROXY on its own
+    let builder = match &config.proxy {
+        Some(proxy) => builder.proxy(
+            reqwest::Proxy::all(proxy)
+                .map_err(|e| ProviderError::ConfigError(format!("Invalid proxy: {e}")))?,
+        ),
+        None => builder,
+    };
+
+    match config.tls_backend {
+        Some(TlsBackend::Rustls) => use_rustls(builder),
+        Some(TlsBackend::Native) => use_native_tls(builder),
+        None => Ok(builder),
+    }
+}
+
+/// `extraHeaders` as sent with every request.
+fn header_map(
+    headers: &HashMap<String, String>,
+) -> std::result::Result<reqwest::header::HeaderMap, ProviderError> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        let invalid = || ProviderError::ConfigError(format!("Invalid extraHeaders entry '{name}'"));
+        let name =
+            reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+        let mut value = reqwest::header::HeaderValue::from_str(value).map_err(|_| invalid())?;
+        value.set_sensitive(true);
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+#[cfg(any(feature = "rustls-webpki-roots", feature = "rustls-native-roots"))]
+fn use_rustls(
+    builder: reqwest::ClientBuilder,
+) -> std::result::Result<reqwest::ClientBuilder, ProviderError> {
+    Ok(builder.use_rustls_tls())
+}
+
+#[cfg(not(any(feature = "rustls-webpki-roots", feature = "rustls-native-roots")))]
+fn use_rustls(
+    _builder: reqwest::ClientBuilder,
+) -> std::result::Result<reqwest::ClientBuilder, ProviderError> {
+    Err(ProviderError::ConfigError(
+        "tlsBackend is \"rustls\" but sh-aid was built without a rustls feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "native-tls")]
+fn use_native_tls(
+    builder: reqwest::ClientBuilder,
+) -> std::result::Result<reqwest::ClientBuilder, ProviderError> {
+    Ok(builder.use_native_tls())
+}
+
+#[cfg(not(feature = "native-tls"))]
+fn use_native_tls(
+    _builder: reqwest::ClientBuilder,
+) -> std::result::Result<reqwest::ClientBuilder, ProviderError> {
+    Err(ProviderError::ConfigError(
+        "tlsBackend is \"native\" but sh-aid was built without the native-tls feature".to_string(),
+    ))
+}
+
+/// The TLS backend requests will use, e.g. `rustls (webpki roots)`, for the
+/// startup report.
+pub fn tls_backend_report(config: &Config) -> String {
+    let native = cfg!(feature = "native-tls");
+    let backend = match config.tls_backend {
+        Some(backend) => backend,
+        // reqwest prefers native-tls when it is compiled in
+        None if native => TlsBackend::Native,
+        None => TlsBackend::Rustls,
+    };
+
+    let roots: Vec<&str> = [
+        (cfg!(feature = "rustls-webpki-roots"), "webpki roots"),
+        (cfg!(feature = "rustls-native-roots"), "native roots"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, name)| enabled.then_some(name))
+    .collect();
+
+    match backend {
+        TlsBackend::Native if native => "native-tls".to_string(),
+        TlsBackend::Rustls if !roots.is_empty() => format!("rustls ({})", roots.join(", ")),
+        _ => "none (the configured tlsBackend is not compiled in)".to_string(),
+    }
+}
+
+/// Reads the full response body, keeping decompression failures distinct from
+/// JSON parse errors so a misdeclared `Content-Encoding` is easy to spot.
+pub(crate) async fn read_response_body(
+    response: reqwest::Response,
+) -> std::result::Result<Vec<u8>, ProviderError> {
+    let body = response.bytes().await.map_err(|e| {
+        if e.is_decode() {
+            let cause = std::error::Error::source(&e)
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| e.to_string());
+            ProviderError::InvalidResponse(format!(
+                "Failed to decompress response body ({cause}); check the server's Content-Encoding"
+            ))
+        } else {
+            ProviderError::HttpError(e)
         }
+    })?;
+
+    Ok(body.to_vec())
+}
+
+/// Static facts about a provider type, shared by config resolution and
+/// `--list-providers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderMetadata {
+    pub name: &'static str,
+    pub implemented: bool,
+    /// False for local servers that accept unauthenticated requests.
+    pub requires_api_key: bool,
+    pub default_model: &'static str,
+    pub api_key_env: &'static str,
+    pub default_base_url: &'static str,
+}
+
+pub fn provider_metadata(provider_type: &ProviderType) -> ProviderMetadata {
+    match provider_type {
+        ProviderType::OpenAI => ProviderMetadata {
+            name: "OpenAI",
+            implemented: true,
+            requires_api_key: true,
+            default_model: "gpt-4o",
+            api_key_env: "OPENAI_API_KEY",
+            default_base_url: "https://api.openai.com",
+        },
+        ProviderType::Custom => ProviderMetadata {
+            name: "Custom",
+            implemented: true,
+            requires_api_key: true,
+            default_model: "gpt-4o",
+            api_key_env: "OPENAI_API_KEY",
+            default_base_url: "https://api.openai.com",
+        },
+        ProviderType::Claude => ProviderMetadata {
+            name: "Claude",
+            implemented: true,
+            requires_api_key: true,
+            default_model: "claude-3-5-sonnet-20241022",
+            api_key_env: "ANTHROPIC_API_KEY",
+            default_base_url: "https://api.anthropic.com",
+        },
+        ProviderType::Gemini => ProviderMetadata {
+            name: "Gemini",
+            implemented: true,
+            requires_api_key: true,
+            default_model: "gemini-1.5-pro",
+            api_key_env: "GOOGLE_API_KEY",
+            default_base_url: "https://generativelanguage.googleapis.com",
+        },
+        ProviderType::Ollama => ProviderMetadata {
+            name: "Ollama",
+            implemented: true,
+            requires_api_key: false,
+            default_model: "llama3.1",
+            // Optional; sent as a bearer token when set (see `OllamaProvider`)
+            api_key_env: "OLLAMA_API_KEY",
+            default_base_url: "http://localhost:11434",
+        },
     }
 }
 
 pub fn get_default_model_for_provider(provider_type: &ProviderType) -> &'static str {
+    provider_metadata(provider_type).default_model
+}
+
+/// Well-known model ids of a provider's hosted API, used to catch typos in
+/// `model`. Not exhaustive: new models appear all the time, so an unknown
+/// model is only worth a warning. Empty for providers that serve arbitrary
+/// models (Ollama, custom OpenAI-compatible servers).
+pub fn known_models_for(provider_type: &ProviderType) -> &'static [&'static str] {
     match provider_type {
-        ProviderType::OpenAI | ProviderType::Custom => "gpt-4o",
-        ProviderType::Claude => "claude-3-5-sonnet-20241022",
-        ProviderType::Gemini => "gemini-1.5-pro",
+        ProviderType::OpenAI => &[
+            "gpt-5",
+            "gpt-5-mini",
+            "gpt-5-nano",
+            "gpt-4.1",
+            "gpt-4.1-mini",
+            "gpt-4.1-nano",
+            "gpt-4o",
+            "gpt-4o-mini",
+            "gpt-4-turbo",
+            "gpt-4",
+            "gpt-3.5-turbo",
+            "o1",
+            "o1-mini",
+            "o3",
+            "o3-mini",
+            "o4-mini",
+        ],
+        ProviderType::Claude => &[
+            "claude-opus-4-1-20250805",
+            "claude-opus-4-20250514",
+            "claude-sonnet-4-20250514",
+            "claude-3-7-sonnet-20250219",
+            "claude-3-7-sonnet-latest",
+            "claude-3-5-sonnet-20241022",
+            "claude-3-5-sonnet-20240620",
+            "claude-3-5-sonnet-latest",
+            "claude-3-5-haiku-20241022",
+            "claude-3-5-haiku-latest",
+            "claude-3-opus-20240229",
+            "claude-3-haiku-20240307",
+        ],
+        ProviderType::Gemini => &[
+            "gemini-2.5-pro",
+            "gemini-2.5-flash",
+            "gemini-2.5-flash-lite",
+            "gemini-2.0-flash",
+            "gemini-2.0-flash-lite",
+            "gemini-1.5-pro",
+            "gemini-1.5-flash",
+            "gemini-1.5-flash-8b",
+        ],
+        ProviderType::Custom | ProviderType::Ollama => &[],
+    }
+}
+
+/// The entry of `known` closest to `model` by edit distance, if it is close
+/// enough to be a likely typo (at most 3 edits, and fewer than half of the
+/// characters).
+pub fn closest_model<'a>(model: &str, known: &[&'a str]) -> Option<&'a str> {
+    let max_distance = 3.min(model.chars().count().div_ceil(2).saturating_sub(1));
+    known
+        .iter()
+        .map(|candidate| (edit_distance(model, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, case-insensitive.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Renders the `--list-providers` table.
+pub fn format_provider_list() -> String {
+    let mut output = format!(
+        "{:<8} {:<16} {:<28} {:<18} {}\n",
+        "PROVIDER", "STATUS", "DEFAULT MODEL", "API KEY ENV", "DEFAULT BASE URL"
+    );
+
+    for provider_type in &ProviderType::ALL {
+        let meta = provider_metadata(provider_type);
+        let status = if meta.implemented {
+            "available"
+        } else {
+            "not implemented"
+        };
+        output.push_str(&format!(
+            "{:<8} {:<16} {:<28} {:<18} {}\n",
+            meta.name, status, meta.default_model, meta.api_key_env, meta.default_base_url
+        ));
     }
+
+    output
 }
 
 #[cfg(test)]
 pub mod test_utils {
     use super::*;
     use std::collections::VecDeque;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
+
+    type MockResponses = VecDeque<std::result::Result<String, ProviderError>>;
 
+    /// Clones share the response queue and request log.
+    #[derive(Clone)]
     pub struct MockProvider {
-        responses: Mutex<VecDeque<std::result::Result<String, ProviderError>>>,
+        responses: Arc<Mutex<MockResponses>>,
+        requests: Arc<Mutex<Vec<(String, String)>>>,
+        options: Arc<Mutex<Vec<GenerateOptions>>>,
+        stream_chunks: Option<Vec<String>>,
         model_info: ModelInfo,
     }
 
+    impl Default for MockProvider {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl MockProvider {
         pub fn new() -> Self {
             Self {
-                responses: Mutex::new(VecDeque::new()),
+                responses: Arc::new(Mutex::new(VecDeque::new())),
+                requests: Arc::new(Mutex::new(Vec::new())),
+                options: Arc::new(Mutex::new(Vec::new())),
+                stream_chunks: None,
                 model_info: ModelInfo {
                     name: "mock-model".to_string(),
                     provider: "mock".to_string(),
@@ -125,18 +944,42 @@ pub mod test_utils {
             provider
         }
 
+        /// A provider whose streaming calls yield these chunks in order.
+        pub fn with_stream(chunks: &[&str]) -> Self {
+            Self {
+                stream_chunks: Some(chunks.iter().map(|c| c.to_string()).collect()),
+                ..Self::new()
+            }
+        }
+
         pub fn add_response(&mut self, response: std::result::Result<String, ProviderError>) {
             self.responses.lock().unwrap().push_back(response);
         }
+
+        /// The (system, user) prompt pairs received so far, in call order.
+        pub fn requests(&self) -> Vec<(String, String)> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        /// The options of the non-streaming calls so far, in call order.
+        pub fn options(&self) -> Vec<GenerateOptions> {
+            self.options.lock().unwrap().clone()
+        }
     }
 
     #[async_trait]
     impl AIProvider for MockProvider {
-        async fn generate_command(
+        async fn generate_command_with(
             &self,
-            _system_prompt: &str,
-            _user_prompt: &str,
+            system_prompt: &str,
+            user_prompt: &str,
+            options: &GenerateOptions,
         ) -> std::result::Result<String, ProviderError> {
+            self.options.lock().unwrap().push(options.clone());
+            self.requests
+                .lock()
+                .unwrap()
+                .push((system_prompt.to_string(), user_prompt.to_string()));
             self.responses
                 .lock()
                 .unwrap()
@@ -144,6 +987,26 @@ pub mod test_utils {
                 .unwrap_or_else(|| Ok("ls -la".to_string()))
         }
 
+        async fn generate_command_stream(
+            &self,
+            system_prompt: &str,
+            user_prompt: &str,
+            options: &GenerateOptions,
+        ) -> std::result::Result<CommandStream, ProviderError> {
+            let Some(chunks) = self.stream_chunks.clone() else {
+                let command = self
+                    .generate_command_with(system_prompt, user_prompt, options)
+                    .await?;
+                return Ok(Box::pin(futures::stream::once(async move { Ok(command) })));
+            };
+
+            self.requests
+                .lock()
+                .unwrap()
+                .push((system_prompt.to_string(), user_prompt.to_string()));
+            Ok(Box::pin(futures::stream::iter(chunks.into_iter().map(Ok))))
+        }
+
         fn validate_config(&self, _config: &Config) -> std::result::Result<(), ProviderError> {
             Ok(())
         }
@@ -152,6 +1015,14 @@ pub mod test_utils {
             self.model_info.clone()
         }
 
+        fn model_id(&self) -> &str {
+            &self.model_info.name
+        }
+
+        fn clone_box(&self) -> Box<dyn AIProvider> {
+            Box::new(self.clone())
+        }
+
         fn get_provider_name(&self) -> &'static str {
             "mock"
         }
@@ -181,6 +1052,448 @@ mod tests {
             get_default_model_for_provider(&ProviderType::Custom),
             "gpt-4o"
         );
+        assert_eq!(
+            get_default_model_for_provider(&ProviderType::Ollama),
+            "llama3.1"
+        );
+    }
+
+    #[test]
+    fn test_closest_model_suggests_typo_fixes() {
+        let openai = known_models_for(&ProviderType::OpenAI);
+        assert_eq!(closest_model("gpt-4-o", openai), Some("gpt-4o"));
+        assert_eq!(closest_model("GPT-4o-mni", openai), Some("gpt-4o-mini"));
+        assert_eq!(
+            closest_model(
+                "claude-3-5-sonet-20241022",
+                known_models_for(&ProviderType::Claude)
+            ),
+            Some("claude-3-5-sonnet-20241022")
+        );
+
+        // Unrelated names get no suggestion, nor do short ones that only
+        // share a few characters
+        assert_eq!(closest_model("mistral-large", openai), None);
+        assert_eq!(closest_model("o2", openai), None);
+        assert_eq!(closest_model("gpt-4o", &[]), None);
+    }
+
+    #[test]
+    fn test_default_models_are_known() {
+        for provider_type in &ProviderType::ALL {
+            let known = known_models_for(provider_type);
+            assert!(
+                known.is_empty() || known.contains(&get_default_model_for_provider(provider_type)),
+                "{provider_type:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_provider_list() {
+        let output = format_provider_list();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), ProviderType::ALL.len() + 1);
+
+        for provider_type in &ProviderType::ALL {
+            let meta = provider_metadata(provider_type);
+            let line = lines
+                .iter()
+                .find(|line| line.starts_with(meta.name))
+                .unwrap_or_else(|| panic!("{} missing from list", meta.name));
+            assert!(line.contains(meta.default_model));
+            assert!(line.contains(meta.api_key_env));
+            assert!(line.contains(meta.default_base_url));
+        }
+
+        assert!(!output.contains("not implemented"));
+    }
+
+    #[tokio::test]
+    async fn test_http_client_sends_extra_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("helicone-auth", "Bearer sk-helicone")
+            .match_header("x-team", "platform")
+            .with_body(r#"{"choices":[{"message":{"content":"ls -la"}}]}"#)
+            .create_async()
+            .await;
+        let config = Config {
+            api_key: Some("test-key".to_string()),
+            base_url: Some(server.url()),
+            extra_headers: Some(HashMap::from([
+                (
+                    "Helicone-Auth".to_string(),
+                    "Bearer sk-helicone".to_string(),
+                ),
+                ("X-Team".to_string(), "platform".to_string()),
+            ])),
+            ..Config::default()
+        };
+
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(provider.generate_command("s", "u").await.unwrap(), "ls -la");
+        mock.assert_async().await;
+
+        let bad = HashMap::from([("Bad Name".to_string(), "x".to_string())]);
+        assert!(matches!(
+            header_map(&bad),
+            Err(ProviderError::ConfigError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http_client_uses_configured_proxy() {
+        let mut proxy = mockito::Server::new_async().await;
+        let mock = proxy
+            .mock("GET", "/ping")
+            .with_body("pong")
+            .create_async()
+            .await;
+        let config = Config {
+            proxy: Some(proxy.url()),
+            ..Config::default()
+        };
+
+        let client = build_http_client(&config, Duration::from_secs(5)).unwrap();
+        let body = client
+            .get("http://api.invalid/ping")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "pong");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_client_applies_timeout() {
+        // A server that accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ping", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+
+        let config = Config {
+            timeout_seconds: Some(1),
+            pool_max_idle_per_host: Some(0),
+            pool_idle_timeout_seconds: Some(5),
+            ..Config::default()
+        };
+        let client = build_http_client(&config, Duration::from_secs(30)).unwrap();
+
+        let started = std::time::Instant::now();
+        let err = client.get(&url).send().await.unwrap_err();
+        assert!(err.is_timeout(), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_http_client_applies_version_preference() {
+        // Over plain HTTP, auto negotiation stays on HTTP/1.1
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/ping")
+            .with_body("pong")
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        let url = format!("{}/ping", server.url());
+
+        let get = |http_version| {
+            let config = Config {
+                http_version,
+                ..Config::default()
+            };
+            let client = build_http_client(&config, Duration::from_secs(5)).unwrap();
+            let url = url.clone();
+            async move { client.get(&url).send().await }
+        };
+
+        let response = get(Some(HttpVersion::Http1)).await.unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_11);
+        assert_eq!(
+            get(None).await.unwrap().version(),
+            reqwest::Version::HTTP_11
+        );
+        assert_eq!(
+            get(Some(HttpVersion::Http2)).await.unwrap().version(),
+            reqwest::Version::HTTP_2
+        );
+    }
+
+    /// A one-shot HTTPS server on localhost with a self-signed certificate,
+    /// answering with an OpenAI-style completion. Returns its URL and the
+    /// certificate to trust.
+    async fn tls_mock_server() -> (String, reqwest::Certificate) {
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_rustls::rustls;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let key = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+        let tls_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(socket).await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    return;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            let body = r#"{"choices":[{"message":{"role":"assistant","content":"ls -la"}}]}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+
+        let pem = reqwest::Certificate::from_pem(cert.cert.pem().as_bytes()).unwrap();
+        (format!("https://localhost:{port}"), pem)
+    }
+
+    #[tokio::test]
+    async fn test_request_succeeds_over_selected_tls_backend() {
+        let (url, cert) = tls_mock_server().await;
+        let backend = if cfg!(feature = "native-tls") {
+            TlsBackend::Native
+        } else {
+            TlsBackend::Rustls
+        };
+        let config = Config {
+            tls_backend: Some(backend),
+            http_version: Some(HttpVersion::Http1),
+            ..Config::default()
+        };
+
+        let client = http_client_builder(&config, Duration::from_secs(5))
+            .unwrap()
+            .add_root_certificate(cert)
+            .build()
+            .unwrap();
+        let response = client
+            .post(format!("{url}/v1/chat/completions"))
+            .send()
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "ls -la");
+    }
+
+    #[test]
+    fn test_tls_backend_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"type": "OpenAI", "model": "gpt-4o", "tlsBackend": "rustls"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.tls_backend, Some(TlsBackend::Rustls));
+        assert!(
+            serde_json::from_str::<Config>(
+                r#"{"type": "OpenAI", "model": "gpt-4o", "tlsBackend": "boringssl"}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    #[test]
+    fn test_missing_tls_backend_is_reported() {
+        let config = Config {
+            tls_backend: Some(TlsBackend::Native),
+            ..Config::default()
+        };
+        assert!(matches!(
+            build_http_client(&config, Duration::from_secs(5)),
+            Err(ProviderError::ConfigError(message)) if message.contains("native-tls")
+        ));
+        assert_eq!(
+            tls_backend_report(&config),
+            "none (the configured tlsBackend is not compiled in)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_commands_samples_and_dedups() {
+        let mut provider = test_utils::MockProvider::new();
+        for response in ["ls", "ls -a", "ls"] {
+            provider.add_response(Ok(response.to_string()));
+        }
+
+        let commands = provider
+            .generate_commands("system", "list", 3, &GenerateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(commands, vec!["ls", "ls -a"]);
+        assert!(
+            provider
+                .options()
+                .iter()
+                .all(|options| options.temperature == Some(ALTERNATIVES_TEMPERATURE))
+        );
+
+        // A single command is generated the usual way
+        let commands = provider
+            .generate_commands("system", "list", 1, &GenerateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(commands, vec!["ls -la"]);
+        assert_eq!(provider.options().last(), Some(&GenerateOptions::default()));
+    }
+
+    fn rate_limited_then(response: &str) -> test_utils::MockProvider {
+        let mut provider = test_utils::MockProvider::new();
+        provider.add_response(Err(ProviderError::RateLimitError("slow down".to_string())));
+        provider.add_response(Err(ProviderError::TimeoutError("timed out".to_string())));
+        provider.add_response(Ok(response.to_string()));
+        provider
+    }
+
+    #[tokio::test]
+    async fn test_retry_succeeds_after_transient_errors() {
+        let provider = rate_limited_then("ls -la");
+        let result = with_backoff(3, Duration::ZERO, || {
+            provider.generate_command("system", "list")
+        })
+        .await;
+        assert_eq!(result.unwrap(), "ls -la");
+        assert_eq!(provider.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_retries() {
+        let provider = rate_limited_then("ls -la");
+        let result = with_backoff(1, Duration::ZERO, || {
+            provider.generate_command("system", "list")
+        })
+        .await;
+        assert!(matches!(result, Err(ProviderError::TimeoutError(_))));
+        assert_eq!(provider.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_config_errors_are_not_retried() {
+        for error in [
+            ProviderError::AuthenticationError("bad key".to_string()),
+            ProviderError::ConfigError("no model".to_string()),
+        ] {
+            let provider = test_utils::MockProvider::with_error(error);
+            let result = generate_command_with_retry(&provider, "system", "list", 3).await;
+            assert!(result.is_err());
+            assert_eq!(provider.requests().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_provider() {
+        let provider = RetryProvider {
+            base_delay: Duration::ZERO,
+            ..RetryProvider::new(Box::new(rate_limited_then("df -h")), 2)
+        };
+        assert_eq!(
+            provider.generate_command("system", "disk").await.unwrap(),
+            "df -h"
+        );
+    }
+
+    #[test]
+    fn test_generate_options_or() {
+        let call = GenerateOptions {
+            temperature: Some(0.7),
+            ..GenerateOptions::default()
+        };
+        let defaults = GenerateOptions {
+            temperature: Some(0.2),
+            max_tokens: Some(128),
+            seed: None,
+            stop: vec!["END".to_string()],
+        };
+        assert_eq!(
+            call.or(&defaults),
+            GenerateOptions {
+                temperature: Some(0.7),
+                max_tokens: Some(128),
+                seed: None,
+                stop: vec!["END".to_string()],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_options_provider() {
+        let mock = test_utils::MockProvider::new();
+        let defaults = GenerateOptions {
+            temperature: Some(0.3),
+            seed: Some(42),
+            ..GenerateOptions::default()
+        };
+        let provider = DefaultOptionsProvider::new(Box::new(mock.clone()), defaults.clone());
+
+        provider.generate_command("system", "user").await.unwrap();
+        let per_call = GenerateOptions {
+            seed: Some(1),
+            ..GenerateOptions::default()
+        };
+        provider
+            .generate_command_with("system", "user", &per_call)
+            .await
+            .unwrap();
+
+        let received = mock.options();
+        assert_eq!(received[0], defaults);
+        assert_eq!(received[1].seed, Some(1));
+        assert_eq!(received[1].temperature, Some(0.3));
+
+        // `--count` samples get the defaults too, including the temperature
+        provider
+            .generate_commands("system", "user", 2, &GenerateOptions::default())
+            .await
+            .unwrap();
+        let received = mock.options();
+        assert_eq!(received[2..], [defaults.clone(), defaults]);
+    }
+
+    #[test]
+    fn test_http_version_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"type": "OpenAI", "model": "gpt-4o", "httpVersion": "http1"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.http_version, Some(HttpVersion::Http1));
+        assert!(
+            serde_json::from_str::<Config>(
+                r#"{"type": "OpenAI", "model": "gpt-4o", "httpVersion": "spdy"}"#
+            )
+            .is_err()
+        );
     }
 
     #[test]
@@ -195,6 +1508,57 @@ mod tests {
         assert_eq!(error.to_string(), "Authentication failed: Invalid API key");
     }
 
+    #[tokio::test]
+    async fn test_clone_box_copies_are_usable() {
+        use test_utils::MockProvider;
+
+        let mut mock = MockProvider::new();
+        mock.add_response(Ok("first".to_string()));
+        mock.add_response(Ok("second".to_string()));
+
+        let original: Box<dyn AIProvider> = Box::new(mock);
+        let copy = original.clone();
+
+        let (a, b) = tokio::join!(
+            original.generate_command("system", "a"),
+            copy.generate_command("system", "b")
+        );
+        let mut results = vec![a.unwrap(), b.unwrap()];
+        results.sort();
+        assert_eq!(results, vec!["first".to_string(), "second".to_string()]);
+        assert_eq!(copy.get_provider_name(), "mock");
+    }
+
+    #[test]
+    fn test_clone_box_real_provider() {
+        let config = Config {
+            api_key: Some("test-key".to_string()),
+            model: "gpt-4o-mini".to_string(),
+            requests_per_minute: Some(10),
+            ..Config::default()
+        };
+        let provider = create_provider(&config).unwrap();
+        let copy = provider.clone_box();
+
+        assert_eq!(copy.get_provider_name(), "OpenAI");
+        assert_eq!(copy.get_model_info().name, "gpt-4o-mini");
+    }
+
+    #[test]
+    fn test_configured_model_flows_to_model_id() {
+        let config = Config {
+            api_key: Some("test-key".to_string()),
+            model: "my-finetune:v3".to_string(),
+            requests_per_minute: Some(10),
+            model_fallback: true,
+            ..Config::default()
+        };
+        let provider = create_provider(&config).unwrap();
+
+        assert_eq!(provider.model_id(), "my-finetune:v3");
+        assert_eq!(provider.get_model_info().name, "my-finetune:v3");
+    }
+
     #[tokio::test]
     async fn test_mock_provider() {
         use test_utils::MockProvider;
@@ -0,0 +1,351 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::sanitize::sanitize_command;
+use super::{
+    AIProvider, GenerateOptions, ModelInfo, ProviderError, build_http_client, read_response_body,
+};
+use crate::config::Config;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: Option<OllamaMessage>,
+    error: Option<String>,
+}
+
+/// A local Ollama server. No API key is needed; when one is configured (for
+/// an authenticating proxy in front of Ollama) it is sent as a bearer token.
+#[derive(Clone)]
+pub struct OllamaProvider {
+    client: Client,
+    api_key: Option<String>,
+    model: String,
+    base_url: String,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &Config) -> Result<Self, ProviderError> {
+        let base_url = config
+            .get_base_url()
+            .unwrap_or("http://localhost:11434")
+            .trim_end_matches('/')
+            .to_string();
+
+        // Local models can take a while to load on the first request
+        let client = build_http_client(config, Duration::from_secs(120))?;
+
+        Ok(Self {
+            client,
+            api_key: config
+                .get_api_key()
+                .filter(|key| !key.is_empty())
+                .map(str::to_string),
+            model: config.model.clone(),
+            base_url,
+        })
+    }
+
+    fn build_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> OllamaRequest {
+        OllamaRequest {
+            model: self.model.clone(),
+            messages: vec![
+                OllamaMessage {
+                    role: "system".to_string(),
+                    content: system_prompt.to_string(),
+                },
+                OllamaMessage {
+                    role: "user".to_string(),
+                    content: user_prompt.to_string(),
+                },
+            ],
+            stream: false,
+            options: OllamaOptions {
+                temperature: options.temperature.unwrap_or(0.0),
+                num_predict: options.max_tokens,
+                seed: options.seed,
+                stop: options.stop.clone(),
+            },
+        }
+    }
+}
+
+fn parse_response(response: OllamaResponse) -> Result<String, ProviderError> {
+    if let Some(error) = response.error {
+        return Err(ProviderError::ApiError {
+            status_code: 400,
+            message: error,
+        });
+    }
+
+    let command = response
+        .message
+        .map(|message| sanitize_command(&message.content))
+        .unwrap_or_default();
+
+    if command.is_empty() {
+        return Err(ProviderError::InvalidResponse(
+            "Empty command response".to_string(),
+        ));
+    }
+
+    Ok(command)
+}
+
+#[async_trait]
+impl AIProvider for OllamaProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        let request = self.build_request(system_prompt, user_prompt, options);
+        let url = format!("{}/api/chat", self.base_url);
+
+        let mut builder = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.header("Authorization", format!("Bearer {api_key}"));
+        }
+
+        let response = builder.send().await?;
+        let status = response.status();
+
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ProviderError::AuthenticationError(
+                "Invalid API key or authentication failed".to_string(),
+            ));
+        }
+
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            // Ollama answers `{"error": "model 'x' not found"}`
+            let message = serde_json::from_str::<OllamaResponse>(&error_text)
+                .ok()
+                .and_then(|body| body.error)
+                .unwrap_or(error_text);
+            return Err(ProviderError::ApiError {
+                status_code: status.as_u16(),
+                message,
+            }
+            .redact(self.api_key.as_deref().unwrap_or_default()));
+        }
+
+        let body = read_response_body(response).await?;
+        let ollama_response: OllamaResponse = serde_json::from_slice(&body).map_err(|e| {
+            ProviderError::InvalidResponse(format!("Failed to parse JSON response: {e}"))
+        })?;
+
+        parse_response(ollama_response)
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        if config.model.is_empty() {
+            return Err(ProviderError::ConfigError(
+                "Model name is required".to_string(),
+            ));
+        }
+
+        if let Some(base_url) = config.get_base_url()
+            && !base_url.starts_with("http://")
+            && !base_url.starts_with("https://")
+        {
+            return Err(ProviderError::ConfigError(
+                "Base URL must start with http:// or https://".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: self.model.clone(),
+            provider: "Ollama".to_string(),
+            max_tokens: None,
+            supports_system_prompt: true,
+        }
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(self.clone())
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        "Ollama"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderType;
+
+    fn create_test_config() -> Config {
+        Config {
+            provider_type: ProviderType::Ollama,
+            api_key: None,
+            model: "llama3.1".to_string(),
+            base_url: None,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_ollama_provider_without_api_key() {
+        let config = create_test_config();
+        let provider = OllamaProvider::new(&config).unwrap();
+        assert_eq!(provider.base_url, "http://localhost:11434");
+        assert!(provider.api_key.is_none());
+        assert!(provider.validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_build_request() {
+        let provider = OllamaProvider::new(&create_test_config()).unwrap();
+        let json = serde_json::to_value(provider.build_request(
+            "system prompt",
+            "user prompt",
+            &GenerateOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "model": "llama3.1",
+                "messages": [
+                    {"role": "system", "content": "system prompt"},
+                    {"role": "user", "content": "user prompt"}
+                ],
+                "stream": false,
+                "options": {"temperature": 0.0}
+            })
+        );
+    }
+
+    #[test]
+    fn test_options_override_defaults() {
+        let provider = OllamaProvider::new(&create_test_config()).unwrap();
+        let options = GenerateOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(64),
+            seed: Some(7),
+            stop: vec!["\n\n".to_string()],
+        };
+        let json = serde_json::to_value(provider.build_request("s", "u", &options)).unwrap();
+
+        assert_eq!(
+            json["options"],
+            serde_json::json!({
+                "temperature": 0.5,
+                "num_predict": 64,
+                "seed": 7,
+                "stop": ["\n\n"]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_command_posts_to_api_chat() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"model": "llama3.1", "stream": false}),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"model":"llama3.1","message":{"role":"assistant","content":"ls -la\n"},"done":true}"#,
+            )
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = OllamaProvider::new(&config).unwrap();
+
+        let result = provider.generate_command("system", "list files").await;
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "ls -la");
+    }
+
+    #[test]
+    fn test_parse_response_strips_code_fence() {
+        let response: OllamaResponse = serde_json::from_str(
+            r#"{"message":{"role":"assistant","content":"```bash\nls -la\n```"}}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_response(response).unwrap(), "ls -la");
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_error() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/api/chat")
+            .with_status(404)
+            .with_body(r#"{"error":"model \"llama9\" not found, try pulling it first"}"#)
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let result = OllamaProvider::new(&config)
+            .unwrap()
+            .generate_command("system", "list files")
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(ProviderError::ApiError { status_code: 404, message })
+                if message.starts_with("model \"llama9\" not found")
+        ));
+    }
+}
@@ -1,10 +1,19 @@
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use super::{AIProvider, ModelInfo, ProviderError};
+use super::sanitize::sanitize_command;
+use super::sse::SseBuffer;
+use super::{
+    AIProvider, CommandStream, DEFAULT_REQUEST_TIMEOUT, GenerateOptions, ModelInfo, ProviderError,
+    Quota, alternatives_options, build_http_client, read_response_body,
+};
 use crate::config::Config;
+use crate::usage::{TokenUsage, UsageMeter};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OpenAIMessage {
@@ -18,18 +27,66 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     max_tokens: Option<u32>,
     temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    /// Number of completions to sample (`--count`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logit_bias: Option<HashMap<String, f32>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
+#[derive(Debug, Deserialize)]
+struct OpenAIResponseMessage {
+    content: Option<String>,
+}
+
+/// Accepts both the chat (`message.content`) and the legacy completions
+/// (`text`) choice shapes.
 #[derive(Debug, Deserialize)]
 struct OpenAIChoice {
-    message: OpenAIMessage,
+    message: Option<OpenAIResponseMessage>,
+    text: Option<String>,
     finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
+    #[serde(default)]
     choices: Vec<OpenAIChoice>,
     error: Option<OpenAIError>,
+    /// Top-level answer fields used by some OpenAI-compatible gateways.
+    content: Option<String>,
+    response: Option<String>,
+    usage: Option<TokenUsage>,
+}
+
+impl OpenAIResponse {
+    /// The answer stopped at `max_tokens` (`finish_reason: "length"`), so the
+    /// command may be cut off.
+    fn is_truncated(&self) -> bool {
+        self.choices
+            .first()
+            .and_then(|choice| choice.finish_reason.as_deref())
+            == Some("length")
+    }
+}
+
+/// One `data:` event of a streamed chat completion.
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    error: Option<OpenAIError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    delta: Option<OpenAIResponseMessage>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,14 +94,48 @@ struct OpenAIError {
     message: String,
     #[serde(rename = "type")]
     error_type: String,
+    #[allow(dead_code)]
     code: Option<String>,
 }
 
+/// `GET /models`, also served by most OpenAI-compatible servers.
+#[derive(Debug, Deserialize)]
+struct OpenAIModelList {
+    data: Vec<OpenAIModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModelEntry {
+    id: String,
+}
+
+/// OpenRouter's `GET /key` response; amounts are in US dollars.
+#[derive(Debug, Deserialize)]
+struct OpenRouterKeyResponse {
+    data: OpenRouterKeyData,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterKeyData {
+    usage: f64,
+    limit: Option<f64>,
+    limit_remaining: Option<f64>,
+}
+
+#[derive(Clone)]
 pub struct OpenAIProvider {
     client: Client,
     api_key: String,
     model: String,
-    base_url: String,
+    api_root: String,
+    logit_bias: Option<HashMap<String, f32>>,
+    /// Endpoint reporting the key's remaining credit; only OpenRouter has one
+    /// that works with an API key.
+    quota_url: Option<String>,
+    /// Tokens reported by the responses so far, shared by clones.
+    usage: UsageMeter,
+    /// Responses that stopped at `max_tokens`, shared by clones.
+    truncated: Arc<AtomicU32>,
 }
 
 impl OpenAIProvider {
@@ -60,27 +151,36 @@ impl OpenAIProvider {
             ));
         }
 
-        let base_url = config
-            .get_base_url()
-            .unwrap_or("https://api.openai.com")
-            .to_string();
+        let api_root = match &config.api_root {
+            Some(root) => root.trim_end_matches('/').to_string(),
+            None => {
+                api_root_from_base_url(config.get_base_url().unwrap_or("https://api.openai.com"))
+            }
+        };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .map_err(|e| {
-                ProviderError::ConfigError(format!("Failed to create HTTP client: {e}"))
-            })?;
+        let client = build_http_client(config, DEFAULT_REQUEST_TIMEOUT)?;
+        let quota_url = api_root
+            .contains("openrouter.ai")
+            .then(|| format!("{api_root}/key"));
 
         Ok(Self {
             client,
             api_key,
             model: config.model.clone(),
-            base_url,
+            api_root,
+            logit_bias: config.logit_bias.clone(),
+            quota_url,
+            usage: UsageMeter::default(),
+            truncated: Arc::default(),
         })
     }
 
-    fn build_request(&self, system_prompt: &str, user_prompt: &str) -> OpenAIRequest {
+    fn build_request(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> OpenAIRequest {
         let messages = vec![
             OpenAIMessage {
                 role: "system".to_string(),
@@ -95,83 +195,408 @@ impl OpenAIProvider {
         OpenAIRequest {
             model: self.model.clone(),
             messages,
-            max_tokens: Some(1024),
-            temperature: Some(0.0), // Use deterministic responses for command generation
+            max_tokens: Some(options.max_tokens.unwrap_or(1024)),
+            // Use deterministic responses for command generation
+            temperature: Some(options.temperature.unwrap_or(0.0)),
+            seed: options.seed,
+            stop: options.stop.clone(),
+            n: None,
+            logit_bias: self.logit_bias.clone(),
+            stream: false,
+        }
+    }
+
+    /// Posts `request` to the chat completions endpoint and turns HTTP error
+    /// statuses into provider errors.
+    async fn send_chat(&self, request: &OpenAIRequest) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}/chat/completions", self.api_root);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await?;
+
+        let status = response.status();
+
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ProviderError::AuthenticationError(
+                "Invalid API key or authentication failed".to_string(),
+            ));
+        }
+
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(ProviderError::RateLimitError(
+                "Rate limit exceeded. Please try again later.".to_string(),
+            ));
         }
+
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError {
+                status_code: status.as_u16(),
+                message: error_text,
+            }
+            .redact(&self.api_key));
+        }
+
+        Ok(response)
+    }
+
+    /// Sends a non-streaming `request`, decodes the response and records
+    /// its token usage and whether it was cut off.
+    async fn complete(&self, request: &OpenAIRequest) -> Result<OpenAIResponse, ProviderError> {
+        let response = self.send_chat(request).await?;
+        let body = read_response_body(response).await?;
+        let response: OpenAIResponse = serde_json::from_slice(&body).map_err(|e| {
+            ProviderError::InvalidResponse(format!("Failed to parse JSON response: {e}"))
+        })?;
+        if let Some(usage) = response.usage {
+            self.usage.record(usage);
+        }
+        if response.is_truncated() {
+            self.truncated.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(response)
     }
 
     fn parse_response(&self, response: OpenAIResponse) -> Result<String, ProviderError> {
         // Check for API error first
         if let Some(error) = response.error {
-            return match error.error_type.as_str() {
-                "insufficient_quota" | "billing_hard_limit_reached" => {
-                    Err(ProviderError::AuthenticationError(format!(
-                        "Quota exceeded: {}",
-                        error.message
-                    )))
-                }
-                "invalid_api_key" | "invalid_request_error" => {
-                    Err(ProviderError::AuthenticationError(error.message))
-                }
-                "rate_limit_exceeded" => Err(ProviderError::RateLimitError(error.message)),
-                _ => Err(ProviderError::ApiError {
-                    status_code: 400,
-                    message: error.message,
-                }),
-            };
+            return Err(api_error(error).redact(&self.api_key));
         }
 
-        // Extract the command from the response
-        let choice = response
-            .choices
-            .first()
-            .ok_or_else(|| ProviderError::InvalidResponse("No choices in response".to_string()))?;
+        // Extract the command: chat shape, then legacy `text`, then top-level
+        // gateway fields
+        let choice = response.choices.first();
+        let candidates = [
+            choice
+                .and_then(|c| c.message.as_ref())
+                .and_then(|m| m.content.as_deref()),
+            choice.and_then(|c| c.text.as_deref()),
+            response.content.as_deref(),
+            response.response.as_deref(),
+        ];
+
+        if let Some(command) = candidates
+            .into_iter()
+            .flatten()
+            .map(sanitize_command)
+            .find(|text| !text.is_empty())
+        {
+            return Ok(command);
+        }
+
+        Err(ProviderError::InvalidResponse(
+            if choice.is_none() && response.content.is_none() && response.response.is_none() {
+                "No choices in response".to_string()
+            } else {
+                "Empty command response".to_string()
+            },
+        ))
+    }
 
-        let command = choice.message.content.trim();
+    /// The command of every choice of an `n` > 1 response, without empty
+    /// ones and duplicates. Responses with at most one choice are read by
+    /// [`Self::parse_response`].
+    fn parse_choices(&self, response: OpenAIResponse) -> Result<Vec<String>, ProviderError> {
+        if response.error.is_some() || response.choices.len() <= 1 {
+            return self.parse_response(response).map(|command| vec![command]);
+        }
+
+        let mut commands: Vec<String> = Vec::new();
+        for choice in &response.choices {
+            let candidates = [
+                choice.message.as_ref().and_then(|m| m.content.as_deref()),
+                choice.text.as_deref(),
+            ];
+            if let Some(command) = candidates
+                .into_iter()
+                .flatten()
+                .map(sanitize_command)
+                .find(|text| !text.is_empty())
+                && !commands.contains(&command)
+            {
+                commands.push(command);
+            }
+        }
 
-        if command.is_empty() {
+        if commands.is_empty() {
             return Err(ProviderError::InvalidResponse(
                 "Empty command response".to_string(),
             ));
         }
+        Ok(commands)
+    }
+}
+
+fn api_error(error: OpenAIError) -> ProviderError {
+    match error.error_type.as_str() {
+        "insufficient_quota" | "billing_hard_limit_reached" => {
+            ProviderError::AuthenticationError(format!("Quota exceeded: {}", error.message))
+        }
+        "invalid_api_key" | "invalid_request_error" => {
+            ProviderError::AuthenticationError(error.message)
+        }
+        "rate_limit_exceeded" => ProviderError::RateLimitError(error.message),
+        _ => ProviderError::ApiError {
+            status_code: 400,
+            message: error.message,
+        },
+    }
+}
+
+/// The content delta of one streamed event; `None` for events without text
+/// and for the final `[DONE]`.
+fn parse_stream_data(data: &str) -> Result<Option<String>, ProviderError> {
+    if data == "[DONE]" {
+        return Ok(None);
+    }
+
+    let chunk: OpenAIStreamChunk = serde_json::from_str(data).map_err(|e| {
+        ProviderError::InvalidResponse(format!("Failed to parse stream event: {e}"))
+    })?;
+    if let Some(error) = chunk.error {
+        return Err(api_error(error));
+    }
+
+    Ok(chunk
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.delta)
+        .and_then(|delta| delta.content)
+        .filter(|content| !content.is_empty()))
+}
+
+/// State of a streamed response while it is being read.
+struct SseState {
+    response: reqwest::Response,
+    buffer: SseBuffer,
+    ready: VecDeque<Result<String, ProviderError>>,
+    done: bool,
+}
+
+impl SseState {
+    fn queue(&mut self, data: &str) {
+        if data == "[DONE]" {
+            self.done = true;
+        }
+        match parse_stream_data(data) {
+            Ok(Some(content)) => self.ready.push_back(Ok(content)),
+            Ok(None) => {}
+            Err(error) => {
+                self.ready.push_back(Err(error));
+                self.done = true;
+            }
+        }
+    }
+
+    /// The next content delta, reading more of the body as needed.
+    async fn next(mut self) -> Option<(Result<String, ProviderError>, Self)> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some((item, self));
+            }
+            if self.done {
+                return None;
+            }
+
+            match self.response.chunk().await {
+                Ok(Some(bytes)) => {
+                    for data in self.buffer.push(&bytes) {
+                        self.queue(&data);
+                    }
+                }
+                Ok(None) => {
+                    if let Some(data) = self.buffer.finish() {
+                        self.queue(&data);
+                    }
+                    self.done = true;
+                }
+                Err(error) => {
+                    self.ready.push_back(Err(error.into()));
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
+
+/// The model ids of a `GET /models` response, sorted.
+fn parse_model_list(body: &[u8]) -> Result<Vec<ModelInfo>, ProviderError> {
+    let list: OpenAIModelList = serde_json::from_slice(body).map_err(|e| {
+        ProviderError::InvalidResponse(format!("Failed to parse models response: {e}"))
+    })?;
+
+    let mut models: Vec<ModelInfo> = list
+        .data
+        .into_iter()
+        .map(|entry| ModelInfo {
+            name: entry.id,
+            provider: "OpenAI".to_string(),
+            max_tokens: None,
+            supports_system_prompt: true,
+        })
+        .collect();
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+fn parse_openrouter_key(body: &[u8]) -> Result<Quota, ProviderError> {
+    let key: OpenRouterKeyResponse = serde_json::from_slice(body).map_err(|e| {
+        ProviderError::InvalidResponse(format!("Failed to parse quota response: {e}"))
+    })?;
+    let data = key.data;
 
-        Ok(command.to_string())
+    Ok(Quota {
+        used: data.usage,
+        limit: data.limit,
+        remaining: data
+            .limit_remaining
+            .or_else(|| data.limit.map(|limit| (limit - data.usage).max(0.0))),
+    })
+}
+
+/// The API root for `base_url` when `apiRoot` is not set. A base URL that
+/// already names the chat completions endpoint or has a version segment
+/// (`/v1`, `/api/v2`, `/openai/v1beta`) is used as given; others get `/v1`.
+fn api_root_from_base_url(base_url: &str) -> String {
+    let base = base_url.trim_end_matches('/');
+    if let Some(root) = base.strip_suffix("/chat/completions") {
+        return root.to_string();
+    }
+
+    // Skip the scheme and host ("https:", "", "host")
+    let versioned = base.split('/').skip(3).any(|segment| {
+        segment
+            .strip_prefix('v')
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    });
+    if versioned {
+        base.to_string()
+    } else {
+        format!("{base}/v1")
     }
 }
 
 #[async_trait]
 impl AIProvider for OpenAIProvider {
-    async fn generate_command(
+    async fn generate_command_with(
         &self,
         system_prompt: &str,
         user_prompt: &str,
+        options: &GenerateOptions,
     ) -> Result<String, ProviderError> {
-        let request = self.build_request(system_prompt, user_prompt);
-        let url = format!("{}/v1/chat/completions", self.base_url);
+        let request = self.build_request(system_prompt, user_prompt, options);
+        let response = self.complete(&request).await?;
+        self.parse_response(response)
+    }
+
+    /// Samples all `n` completions in one request with the `n` parameter.
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> Result<Vec<String>, ProviderError> {
+        if n <= 1 {
+            return Ok(vec![
+                self.generate_command_with(system_prompt, user_prompt, options)
+                    .await?,
+            ]);
+        }
 
+        let request = OpenAIRequest {
+            n: Some(n),
+            ..self.build_request(system_prompt, user_prompt, &alternatives_options(options))
+        };
+        let response = self.complete(&request).await?;
+        self.parse_choices(response)
+    }
+
+    /// Streams the completion over server-sent events. The chunks are the
+    /// raw model output; callers sanitize the accumulated text.
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<CommandStream, ProviderError> {
+        let request = OpenAIRequest {
+            stream: true,
+            ..self.build_request(system_prompt, user_prompt, options)
+        };
+        let response = self.send_chat(&request).await?;
+
+        let state = SseState {
+            response,
+            buffer: SseBuffer::default(),
+            ready: VecDeque::new(),
+            done: false,
+        };
+        let api_key = self.api_key.clone();
+        Ok(Box::pin(
+            futures::stream::unfold(state, SseState::next)
+                .map(move |item| item.map_err(|e| e.redact(&api_key))),
+        ))
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
         let response = self
             .client
-            .post(&url)
+            .get(format!("{}/models", self.api_root))
             .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
             .send()
             .await?;
 
         let status = response.status();
-
         if status == reqwest::StatusCode::UNAUTHORIZED {
             return Err(ProviderError::AuthenticationError(
                 "Invalid API key or authentication failed".to_string(),
             ));
         }
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError {
+                status_code: status.as_u16(),
+                message: error_text,
+            }
+            .redact(&self.api_key));
+        }
 
-        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            return Err(ProviderError::RateLimitError(
-                "Rate limit exceeded. Please try again later.".to_string(),
+        let body = read_response_body(response).await?;
+        parse_model_list(&body)
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        let Some(url) = &self.quota_url else {
+            return Ok(None);
+        };
+
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ProviderError::AuthenticationError(
+                "Invalid API key or authentication failed".to_string(),
             ));
         }
-
         if !status.is_success() {
             let error_text = response
                 .text()
@@ -180,14 +605,12 @@ impl AIProvider for OpenAIProvider {
             return Err(ProviderError::ApiError {
                 status_code: status.as_u16(),
                 message: error_text,
-            });
+            }
+            .redact(&self.api_key));
         }
 
-        let openai_response: OpenAIResponse = response.json().await.map_err(|e| {
-            ProviderError::InvalidResponse(format!("Failed to parse JSON response: {e}"))
-        })?;
-
-        self.parse_response(openai_response)
+        let body = read_response_body(response).await?;
+        parse_openrouter_key(&body).map(Some)
     }
 
     fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
@@ -204,12 +627,13 @@ impl AIProvider for OpenAIProvider {
         }
 
         // Validate base URL format if provided
-        if let Some(base_url) = config.get_base_url() {
-            if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
-                return Err(ProviderError::ConfigError(
-                    "Base URL must start with http:// or https://".to_string(),
-                ));
-            }
+        if let Some(base_url) = config.get_base_url()
+            && !base_url.starts_with("http://")
+            && !base_url.starts_with("https://")
+        {
+            return Err(ProviderError::ConfigError(
+                "Base URL must start with http:// or https://".to_string(),
+            ));
         }
 
         Ok(())
@@ -224,6 +648,22 @@ impl AIProvider for OpenAIProvider {
         }
     }
 
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.usage.total()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(self.clone())
+    }
+
     fn get_provider_name(&self) -> &'static str {
         "OpenAI"
     }
@@ -240,9 +680,132 @@ mod tests {
             api_key: Some("test-key".to_string()),
             model: "gpt-4o".to_string(),
             base_url: None,
+            ..Config::default()
         }
     }
 
+    #[test]
+    fn test_parse_openrouter_key() {
+        let body = br#"{"data":{"label":"sk-or-v1-abc...","usage":2.5,"limit":10,"limit_remaining":7.5,"is_free_tier":false}}"#;
+        assert_eq!(
+            parse_openrouter_key(body).unwrap(),
+            Quota {
+                used: 2.5,
+                limit: Some(10.0),
+                remaining: Some(7.5),
+            }
+        );
+
+        let unlimited = br#"{"data":{"usage":0.25,"limit":null,"limit_remaining":null}}"#;
+        let quota = parse_openrouter_key(unlimited).unwrap();
+        assert_eq!(quota.remaining, None);
+        assert_eq!(quota.to_string(), "Remaining: no limit\nUsed:      $0.25\n");
+    }
+
+    #[tokio::test]
+    async fn test_remaining_quota() {
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        assert!(provider.quota_url.is_none());
+        assert_eq!(provider.remaining_quota().await.unwrap(), None);
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/v1/key")
+            .match_header("authorization", "Bearer test-key")
+            .with_body(r#"{"data":{"usage":1,"limit":5,"limit_remaining":4}}"#)
+            .create_async()
+            .await;
+        let provider = OpenAIProvider {
+            quota_url: Some(format!("{}/api/v1/key", server.url())),
+            ..provider
+        };
+
+        let quota = provider.remaining_quota().await.unwrap().unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            quota.to_string(),
+            "Remaining: $4.00 of $5.00\nUsed:      $1.00\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_command_stream_parses_sse() {
+        let body = "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\"```bash\\nls\"}}]}\n\n\
+                    : keep-alive\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\" -la\\n```\"}}]}\n\n\
+                    data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n\
+                    data: [DONE]\n\n";
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"stream": true}),
+            ))
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+
+        let mut chunks = Vec::new();
+        let text = crate::generate::stream_command(&provider, "system", "list", |chunk| {
+            chunks.push(chunk.to_string())
+        })
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(chunks, vec!["```bash\nls", " -la\n```"]);
+        assert_eq!(sanitize_command(&text), "ls -la");
+
+        // Options reach the streamed request too
+        let with_options = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"stream": true, "seed": 7}),
+            ))
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+        let options = GenerateOptions {
+            seed: Some(7),
+            ..GenerateOptions::default()
+        };
+        let _stream = provider
+            .generate_command_stream("system", "list", &options)
+            .await
+            .unwrap();
+        with_options.assert_async().await;
+    }
+
+    #[test]
+    fn test_parse_stream_error_event() {
+        let result =
+            parse_stream_data(r#"{"error":{"message":"Slow down","type":"rate_limit_exceeded"}}"#);
+        assert!(matches!(result, Err(ProviderError::RateLimitError(_))));
+        assert_eq!(parse_stream_data("[DONE]").unwrap(), None);
+    }
+
+    #[test]
+    fn test_openrouter_has_quota_url() {
+        let config = Config {
+            base_url: Some("https://openrouter.ai/api".to_string()),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+        assert_eq!(
+            provider.quota_url.as_deref(),
+            Some("https://openrouter.ai/api/v1/key")
+        );
+    }
+
     #[test]
     fn test_openai_provider_creation() {
         let config = create_test_config();
@@ -252,7 +815,7 @@ mod tests {
         let provider = provider.unwrap();
         assert_eq!(provider.api_key, "test-key");
         assert_eq!(provider.model, "gpt-4o");
-        assert_eq!(provider.base_url, "https://api.openai.com");
+        assert_eq!(provider.api_root, "https://api.openai.com/v1");
     }
 
     #[test]
@@ -264,7 +827,60 @@ mod tests {
         assert!(provider.is_ok());
 
         let provider = provider.unwrap();
-        assert_eq!(provider.base_url, "https://custom.openai.com");
+        assert_eq!(provider.api_root, "https://custom.openai.com/v1");
+
+        config.api_root = Some("https://gateway.example/openai/".to_string());
+        let provider = OpenAIProvider::new(&config).unwrap();
+        assert_eq!(provider.api_root, "https://gateway.example/openai");
+    }
+
+    #[test]
+    fn test_api_root_from_base_url() {
+        for (base_url, root) in [
+            ("https://api.openai.com", "https://api.openai.com/v1"),
+            ("http://localhost:1234/", "http://localhost:1234/v1"),
+            ("http://localhost:1234/v1", "http://localhost:1234/v1"),
+            ("http://localhost:8000/v1/", "http://localhost:8000/v1"),
+            (
+                "https://api.together.xyz/v1/chat/completions",
+                "https://api.together.xyz/v1",
+            ),
+            (
+                "https://gateway.example/openai/chat/completions",
+                "https://gateway.example/openai",
+            ),
+            ("https://llm.example/api/v2", "https://llm.example/api/v2"),
+            (
+                "https://llm.example/openai",
+                "https://llm.example/openai/v1",
+            ),
+            // Only path segments count, not the host
+            ("https://v1.example.com", "https://v1.example.com/v1"),
+        ] {
+            assert_eq!(api_root_from_base_url(base_url), root, "{base_url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_base_url_with_full_path_is_not_doubled() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(r#"{"choices":[{"message":{"content":"ls"}}]}"#)
+            .create_async()
+            .await;
+        let config = Config {
+            base_url: Some(format!("{}/v1/chat/completions", server.url())),
+            ..create_test_config()
+        };
+
+        let command = OpenAIProvider::new(&config)
+            .unwrap()
+            .generate_command("system", "list")
+            .await
+            .unwrap();
+        assert_eq!(command, "ls");
+        mock.assert_async().await;
     }
 
     #[test]
@@ -287,7 +903,8 @@ mod tests {
         let config = create_test_config();
         let provider = OpenAIProvider::new(&config).unwrap();
 
-        let request = provider.build_request("system prompt", "user prompt");
+        let request =
+            provider.build_request("system prompt", "user prompt", &GenerateOptions::default());
 
         assert_eq!(request.model, "gpt-4o");
         assert_eq!(request.messages.len(), 2);
@@ -299,6 +916,42 @@ mod tests {
         assert_eq!(request.temperature, Some(0.0));
     }
 
+    #[test]
+    fn test_options_override_defaults() {
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        let options = GenerateOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(64),
+            seed: Some(7),
+            stop: vec!["\n\n".to_string()],
+        };
+        let json = serde_json::to_value(provider.build_request("s", "u", &options)).unwrap();
+
+        assert_eq!(json["temperature"], 0.5);
+        assert_eq!(json["max_tokens"], 64);
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["stop"], serde_json::json!(["\n\n"]));
+    }
+
+    #[test]
+    fn test_logit_bias_serialization() {
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        let json =
+            serde_json::to_value(provider.build_request("s", "u", &GenerateOptions::default()))
+                .unwrap();
+        assert!(json.get("logit_bias").is_none());
+
+        let config = Config {
+            logit_bias: Some(HashMap::from([("40".to_string(), -50.0)])),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+        let json =
+            serde_json::to_value(provider.build_request("s", "u", &GenerateOptions::default()))
+                .unwrap();
+        assert_eq!(json["logit_bias"], serde_json::json!({"40": -50.0}));
+    }
+
     #[test]
     fn test_parse_successful_response() {
         let config = create_test_config();
@@ -306,13 +959,16 @@ mod tests {
 
         let response = OpenAIResponse {
             choices: vec![OpenAIChoice {
-                message: OpenAIMessage {
-                    role: "assistant".to_string(),
-                    content: "ls -la".to_string(),
-                },
+                message: Some(OpenAIResponseMessage {
+                    content: Some("ls -la".to_string()),
+                }),
+                text: None,
                 finish_reason: Some("stop".to_string()),
             }],
             error: None,
+            content: None,
+            response: None,
+            usage: None,
         };
 
         let result = provider.parse_response(response);
@@ -320,6 +976,241 @@ mod tests {
         assert_eq!(result.unwrap(), "ls -la");
     }
 
+    #[test]
+    fn test_parse_response_shapes() {
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        let parse = |json: &str| provider.parse_response(serde_json::from_str(json).unwrap());
+
+        // Chat completions
+        assert_eq!(
+            parse(r#"{"choices":[{"message":{"role":"assistant","content":"ls -la"}}]}"#).unwrap(),
+            "ls -la"
+        );
+        // Legacy completions
+        assert_eq!(
+            parse(r#"{"choices":[{"text":" ls -la\n","finish_reason":"stop"}]}"#).unwrap(),
+            "ls -la"
+        );
+        // Null chat content falls through to `text`
+        assert_eq!(
+            parse(r#"{"choices":[{"message":{"content":null},"text":"pwd"}]}"#).unwrap(),
+            "pwd"
+        );
+        // Top-level `content` / `response`
+        assert_eq!(parse(r#"{"content":"du -sh ."}"#).unwrap(), "du -sh .");
+        assert_eq!(parse(r#"{"response":"df -h"}"#).unwrap(), "df -h");
+
+        assert!(matches!(
+            parse(r#"{"choices":[]}"#),
+            Err(ProviderError::InvalidResponse(msg)) if msg == "No choices in response"
+        ));
+        assert!(matches!(
+            parse(r#"{"choices":[{"message":{"content":"  "}}]}"#),
+            Err(ProviderError::InvalidResponse(msg)) if msg == "Empty command response"
+        ));
+    }
+
+    #[test]
+    fn test_parse_model_list() {
+        let body = br#"{
+            "object": "list",
+            "data": [
+                {"id": "gpt-4o-mini", "object": "model", "created": 1721172741, "owned_by": "system"},
+                {"id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system"},
+                {"id": "o3-mini", "object": "model", "created": 1737146383, "owned_by": "system"}
+            ]
+        }"#;
+
+        let models = parse_model_list(body).unwrap();
+        let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["gpt-4o", "gpt-4o-mini", "o3-mini"]);
+        assert!(models.iter().all(|m| m.provider == "OpenAI"));
+
+        assert!(matches!(
+            parse_model_list(br#"{"error": "nope"}"#),
+            Err(ProviderError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_models_queries_models_endpoint() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer test-key")
+            .with_body(r#"{"data": [{"id": "llama3"}]}"#)
+            .create_async()
+            .await;
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+
+        let models = OpenAIProvider::new(&config)
+            .unwrap()
+            .list_models()
+            .await
+            .unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "llama3");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_length_finish_reason_is_truncated() {
+        let parse = |body: &str| serde_json::from_str::<OpenAIResponse>(body).unwrap();
+
+        let cut_off = parse(
+            r#"{"choices":[{"message":{"content":"find . -name '*.log' -exec"},"finish_reason":"length"}]}"#,
+        );
+        assert!(cut_off.is_truncated());
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        assert_eq!(
+            provider.parse_response(cut_off).unwrap(),
+            "find . -name '*.log' -exec"
+        );
+
+        let complete =
+            parse(r#"{"choices":[{"message":{"content":"ls -la"},"finish_reason":"stop"}]}"#);
+        assert!(!complete.is_truncated());
+        assert!(!parse(r#"{"content":"ls -la"}"#).is_truncated());
+    }
+
+    #[tokio::test]
+    async fn test_generate_counts_truncated_responses() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(
+                r#"{"choices":[{"message":{"content":"find . -exec"},"finish_reason":"length"}]}"#,
+            )
+            .create_async()
+            .await;
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+        assert_eq!(provider.truncated_responses(), 0);
+
+        let command = provider.generate_command("system", "find").await.unwrap();
+        assert_eq!(command, "find . -exec");
+        assert_eq!(provider.clone_box().truncated_responses(), 1);
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        let response: OpenAIResponse = serde_json::from_str(
+            r#"{
+                "choices": [{"message": {"content": "ls -la"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 812, "completion_tokens": 14, "total_tokens": 826,
+                          "prompt_tokens_details": {"cached_tokens": 0}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response.usage,
+            Some(TokenUsage {
+                prompt_tokens: 812,
+                completion_tokens: 14,
+                total_tokens: 826,
+            })
+        );
+
+        let without: OpenAIResponse =
+            serde_json::from_str(r#"{"choices": [{"message": {"content": "ls"}}]}"#).unwrap();
+        assert_eq!(without.usage, None);
+    }
+
+    #[tokio::test]
+    async fn test_generate_records_usage() {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(
+                r#"{"choices":[{"message":{"content":"ls"}}],
+                    "usage":{"prompt_tokens":100,"completion_tokens":5,"total_tokens":105}}"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+        assert_eq!(provider.token_usage(), None);
+
+        provider.generate_command("system", "list").await.unwrap();
+        // Clones share the running total
+        let clone = provider.clone_box();
+        clone.generate_command("system", "list").await.unwrap();
+        assert_eq!(
+            provider.token_usage(),
+            Some(TokenUsage {
+                prompt_tokens: 200,
+                completion_tokens: 10,
+                total_tokens: 210,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_choices() {
+        let provider = OpenAIProvider::new(&create_test_config()).unwrap();
+        let parse = |json: &str| provider.parse_choices(serde_json::from_str(json).unwrap());
+
+        let json = r#"{"choices":[
+            {"index":0,"message":{"content":"find . -mtime -7"},"finish_reason":"stop"},
+            {"index":1,"message":{"content":"```bash\nfind . -type f -mtime -7\n```"}},
+            {"index":2,"message":{"content":"find . -mtime -7"}},
+            {"index":3,"message":{"content":"  "}},
+            {"index":4,"text":"ls -lt"}
+        ]}"#;
+        assert_eq!(
+            parse(json).unwrap(),
+            vec!["find . -mtime -7", "find . -type f -mtime -7", "ls -lt"]
+        );
+
+        assert_eq!(
+            parse(r#"{"choices":[{"message":{"content":"pwd"}}]}"#).unwrap(),
+            vec!["pwd"]
+        );
+        assert!(matches!(
+            parse(r#"{"choices":[{"message":{"content":""}},{"text":" "}]}"#),
+            Err(ProviderError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_commands_sends_n() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({"n": 3, "temperature": 0.7}),
+            ))
+            .with_body(
+                r#"{"choices":[{"message":{"content":"ls"}},{"message":{"content":"ls -a"}},{"message":{"content":"ls"}}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let config = Config {
+            base_url: Some(server.url()),
+            ..create_test_config()
+        };
+        let provider = OpenAIProvider::new(&config).unwrap();
+        assert_eq!(
+            provider
+                .generate_commands("s", "u", 3, &GenerateOptions::default())
+                .await
+                .unwrap(),
+            vec!["ls", "ls -a"]
+        );
+        mock.assert_async().await;
+    }
+
     #[test]
     fn test_parse_error_response() {
         let config = create_test_config();
@@ -332,6 +1223,9 @@ mod tests {
                 error_type: "invalid_api_key".to_string(),
                 code: None,
             }),
+            content: None,
+            response: None,
+            usage: None,
         };
 
         let result = provider.parse_response(response);
@@ -379,6 +1273,80 @@ mod tests {
         assert!(model_info.supports_system_prompt);
     }
 
+    #[tokio::test]
+    async fn test_gzip_response_is_decoded() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"ls -la"},"finish_reason":"stop"}]}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_header("content-encoding", "gzip")
+            .with_body(compressed)
+            .create_async()
+            .await;
+
+        let mut config = create_test_config();
+        config.base_url = Some(server.url());
+        let provider = OpenAIProvider::new(&config).unwrap();
+
+        let result = provider.generate_command("system", "user").await;
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), "ls -la");
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_gzip_body_reports_decode_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_header("content-encoding", "gzip")
+            .with_body("definitely not gzip")
+            .create_async()
+            .await;
+
+        let mut config = create_test_config();
+        config.base_url = Some(server.url());
+        let provider = OpenAIProvider::new(&config).unwrap();
+
+        match provider.generate_command("system", "user").await {
+            Err(ProviderError::InvalidResponse(msg)) => {
+                assert!(msg.contains("decompress"), "unexpected message: {msg}")
+            }
+            other => panic!("Expected InvalidResponse, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_echoed_api_key_is_redacted() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_body(r#"{"error":{"message":"bad request for key test-key"}}"#)
+            .create_async()
+            .await;
+
+        let mut config = create_test_config();
+        config.base_url = Some(server.url());
+        let provider = OpenAIProvider::new(&config).unwrap();
+
+        let message = provider
+            .generate_command("system", "user")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(!message.contains("test-key"), "key leaked: {message}");
+        assert!(message.contains("***redacted***"));
+    }
+
     #[test]
     fn test_get_provider_name() {
         let config = create_test_config();
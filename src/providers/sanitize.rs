@@ -0,0 +1,116 @@
+use crate::heredoc::HeredocTracker;
+
+/// Cleans up a model response that should be a bare command: a response that
+/// starts with a markdown code fence (```` ```bash ````, optionally with a
+/// language tag) is reduced to the contents of its first fenced block, and a
+/// command wrapped in single backticks is unwrapped. Surrounding whitespace
+/// is trimmed; anything else is returned unchanged.
+pub fn sanitize_command(response: &str) -> String {
+    let text = response.trim();
+
+    if let Some(after_fence) = text.strip_prefix("```") {
+        return first_fenced_block(after_fence).trim().to_string();
+    }
+
+    if let Some(inner) = text
+        .strip_prefix('`')
+        .and_then(|rest| rest.strip_suffix('`'))
+        && !inner.contains('`')
+    {
+        return inner.trim().to_string();
+    }
+
+    text.to_string()
+}
+
+/// The body of a fenced block, given the text right after its opening
+/// backticks. An unterminated fence runs to the end of the text. Fences
+/// inside a heredoc body do not close the block.
+fn first_fenced_block(after_fence: &str) -> &str {
+    // ```ls -la``` on a single line has no language tag
+    let first_line = after_fence.lines().next().unwrap_or_default();
+    if let Some(end) = first_line.find("```") {
+        return &first_line[..end];
+    }
+
+    // Skip the rest of the opening line, which holds the language tag
+    let body = after_fence.split_once('\n').map_or("", |(_, body)| body);
+
+    let mut heredoc = HeredocTracker::default();
+    let mut offset: usize = 0;
+    for line in body.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        if !heredoc.in_body(content) && content.starts_with("```") {
+            return &body[..offset.saturating_sub(1)];
+        }
+        offset += line.len();
+    }
+
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fenced_commands() {
+        assert_eq!(sanitize_command("```bash\nls -la\n```"), "ls -la");
+        assert_eq!(sanitize_command("```\nls -la\n```\n"), "ls -la");
+        assert_eq!(sanitize_command("  ``` sh \ndf -h\n```  "), "df -h");
+        assert_eq!(sanitize_command("```ls -la```"), "ls -la");
+        assert_eq!(
+            sanitize_command("```bash\nfor f in *.log; do\n  gzip \"$f\"\ndone\n```"),
+            "for f in *.log; do\n  gzip \"$f\"\ndone"
+        );
+        // Unterminated and empty fences
+        assert_eq!(sanitize_command("```bash\nls -la"), "ls -la");
+        assert_eq!(sanitize_command("```\n```"), "");
+    }
+
+    #[test]
+    fn test_multiple_fenced_blocks_take_the_first() {
+        let response = "```bash\ngit status\n```\n\nor\n\n```bash\ngit diff\n```";
+        assert_eq!(sanitize_command(response), "git status");
+    }
+
+    #[test]
+    fn test_heredocs_pass_through_intact() {
+        let command = "cat > README.md <<'EOF'\n# Usage\n\n```bash\n  make run\n```\nEOF";
+        assert_eq!(
+            sanitize_command(&format!("```bash\n{command}\n```\nThis writes a README.")),
+            command
+        );
+
+        let command = "cat <<-\"END\"\n\tline one\n\n\t\tline two\n\tEND";
+        assert_eq!(sanitize_command(&format!("```\n{command}\n```")), command);
+        assert_eq!(sanitize_command(command), command);
+    }
+
+    #[test]
+    fn test_arithmetic_shift_does_not_open_a_heredoc() {
+        let response = "```bash
+echo $((1<<4))
+```
+This prints 16.";
+        assert_eq!(sanitize_command(response), "echo $((1<<4))");
+    }
+
+    #[test]
+    fn test_inline_backticks() {
+        assert_eq!(sanitize_command("`ls -la`"), "ls -la");
+        assert_eq!(sanitize_command(" `du -sh *` \n"), "du -sh *");
+        // Command substitution with backticks is left alone
+        assert_eq!(sanitize_command("echo `date`"), "echo `date`");
+        assert_eq!(sanitize_command("`echo `date``"), "`echo `date``");
+    }
+
+    #[test]
+    fn test_plain_commands_are_trimmed_only() {
+        assert_eq!(sanitize_command("  ls -la\n"), "ls -la");
+        assert_eq!(
+            sanitize_command("awk '{print $1}' file"),
+            "awk '{print $1}' file"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+/// Splits a server-sent events body into the payloads of its `data:` lines.
+/// Bytes are buffered until a line is complete, so events and multi-byte
+/// characters may be split across network chunks.
+#[derive(Debug, Default)]
+pub struct SseBuffer {
+    pending: Vec<u8>,
+}
+
+impl SseBuffer {
+    /// Adds `bytes` and returns the `data:` payloads of the lines they
+    /// complete. Comments, `event:`/`id:` fields and blank lines are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut payloads = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            payloads.extend(data_payload(&line));
+        }
+        payloads
+    }
+
+    /// The payload of a last line the stream ended without a newline after.
+    pub fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.pending);
+        data_payload(&line)
+    }
+}
+
+fn data_payload(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches(['\r', '\n']);
+    let data = line.strip_prefix("data:")?;
+    Some(data.strip_prefix(' ').unwrap_or(data).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b"data: {\"a\":").is_empty());
+        assert_eq!(
+            buffer.push(b"1}\n\ndata: [DONE]\n\n"),
+            vec!["{\"a\":1}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn test_non_data_lines_are_skipped() {
+        let mut buffer = SseBuffer::default();
+        let payloads = buffer.push(b": keep-alive\r\nevent: message\r\nid: 7\r\ndata:x\r\n\r\n");
+        assert_eq!(payloads, vec!["x"]);
+    }
+
+    #[test]
+    fn test_multibyte_character_split() {
+        let mut buffer = SseBuffer::default();
+        let bytes = "data: é\n".as_bytes();
+        assert!(buffer.push(&bytes[..7]).is_empty());
+        assert_eq!(buffer.push(&bytes[7..]), vec!["é"]);
+    }
+
+    #[test]
+    fn test_finish_returns_unterminated_line() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b"data: tail").is_empty());
+        assert_eq!(buffer.finish().as_deref(), Some("tail"));
+        assert_eq!(buffer.finish(), None);
+    }
+}
@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::providers::{
+    AIProvider, CommandStream, GenerateOptions, ModelInfo, ProviderError, Quota,
+};
+use crate::usage::TokenUsage;
+
+/// Token-bucket limiter. Time is passed in explicitly so the schedule can be
+/// tested without sleeping.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `requests_per_minute`, with bursts of up to `burst`
+    /// requests before throttling kicks in.
+    pub fn new(requests_per_minute: u32, burst: u32, now: Instant) -> Self {
+        let capacity = f64::from(burst.max(1));
+        RateLimiter {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: f64::from(requests_per_minute.max(1)) / 60.0,
+            last: now,
+        }
+    }
+
+    /// Even spacing with no burst allowance.
+    pub fn per_minute(requests_per_minute: u32) -> Self {
+        Self::new(requests_per_minute, 1, Instant::now())
+    }
+
+    /// Reserves a slot for a request made at `now` and returns how long the
+    /// caller must wait before sending it.
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        if now > self.last {
+            self.last = now;
+        }
+
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_per_sec)
+        }
+    }
+}
+
+/// Wraps a provider so every call first waits for a rate-limiter slot.
+/// Clones share the same limiter, so concurrent tasks are throttled together.
+pub struct RateLimitedProvider {
+    inner: Box<dyn AIProvider>,
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl RateLimitedProvider {
+    pub fn new(inner: Box<dyn AIProvider>, requests_per_minute: u32) -> Self {
+        Self {
+            inner,
+            limiter: Arc::new(Mutex::new(RateLimiter::per_minute(requests_per_minute))),
+        }
+    }
+
+    async fn wait_for_slot(&self) {
+        let wait = self
+            .limiter
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .reserve(Instant::now());
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for RateLimitedProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        self.wait_for_slot().await;
+        self.inner
+            .generate_command_with(system_prompt, user_prompt, options)
+            .await
+    }
+
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<CommandStream, ProviderError> {
+        self.wait_for_slot().await;
+        self.inner
+            .generate_command_stream(system_prompt, user_prompt, options)
+            .await
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(RateLimitedProvider {
+            inner: self.inner.clone_box(),
+            limiter: Arc::clone(&self.limiter),
+        })
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_rapid_calls_are_spaced_out() {
+        let t0 = Instant::now();
+        let mut limiter = RateLimiter::new(60, 1, t0);
+
+        let waits: Vec<Duration> = (0..4).map(|_| limiter.reserve(t0)).collect();
+        assert_eq!(waits, vec![secs(0), secs(1), secs(2), secs(3)]);
+    }
+
+    #[test]
+    fn test_burst_allowance() {
+        let t0 = Instant::now();
+        let mut limiter = RateLimiter::new(30, 3, t0);
+
+        let waits: Vec<Duration> = (0..5).map(|_| limiter.reserve(t0)).collect();
+        assert_eq!(waits, vec![secs(0), secs(0), secs(0), secs(2), secs(4)]);
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time_up_to_capacity() {
+        let t0 = Instant::now();
+        let mut limiter = RateLimiter::new(60, 2, t0);
+
+        assert_eq!(limiter.reserve(t0), secs(0));
+        assert_eq!(limiter.reserve(t0), secs(0));
+
+        // Long idle period: refill stops at capacity
+        let later = t0 + secs(600);
+        assert_eq!(limiter.reserve(later), secs(0));
+        assert_eq!(limiter.reserve(later), secs(0));
+        assert_eq!(limiter.reserve(later), secs(1));
+    }
+
+    #[test]
+    fn test_clock_going_backwards_does_not_refill() {
+        let t0 = Instant::now() + secs(10);
+        let mut limiter = RateLimiter::new(60, 1, t0);
+
+        assert_eq!(limiter.reserve(t0), secs(0));
+        assert_eq!(limiter.reserve(t0 - secs(5)), secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_provider_delegates() {
+        let mut mock = MockProvider::new();
+        mock.add_response(Ok("ls".to_string()));
+        mock.add_response(Ok("pwd".to_string()));
+
+        let provider = RateLimitedProvider::new(Box::new(mock), 60_000);
+        assert_eq!(provider.generate_command("s", "u").await.unwrap(), "ls");
+        assert_eq!(provider.generate_command("s", "u").await.unwrap(), "pwd");
+        assert_eq!(provider.get_provider_name(), "mock");
+    }
+}
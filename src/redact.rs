@@ -0,0 +1,91 @@
+use crate::context::SystemContext;
+use crate::prompt::abbreviate_home;
+
+/// Keeps personal details out of what is sent to the provider: paths under
+/// the home directory (in the working directory, listing, included file
+/// names and recent commands) are shortened to `~/...`, and the home directory itself is shown
+/// as `~`, so the user name does not leave the machine.
+pub fn redact_context(ctx: &mut SystemContext) {
+    let home = ctx.home_dir.trim_end_matches(['/', '\\']).to_string();
+    if home.is_empty() || home == "~" {
+        return;
+    }
+
+    ctx.current_dir = shorten_path(&ctx.current_dir, &home);
+    ctx.directory_listing = abbreviate_home(&ctx.directory_listing, &home);
+    for file in &mut ctx.included_files {
+        file.path = shorten_path(&file.path, &home);
+    }
+    for command in &mut ctx.recent_commands {
+        *command = abbreviate_home(command, &home);
+    }
+    ctx.home_dir = "~".to_string();
+}
+
+/// Leaves the directory listing out of the prompt (`--no-listing`,
+/// `shareDirectoryListing: false`), for directories whose file names are
+/// sensitive.
+pub fn omit_directory_listing(ctx: &mut SystemContext) {
+    ctx.directory_listing.clear();
+}
+
+fn shorten_path(path: &str, home: &str) -> String {
+    if path.trim_end_matches(['/', '\\']) == home {
+        "~".to_string()
+    } else {
+        abbreviate_home(path, home)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_utils::sample_context;
+    use crate::file_context::IncludedFile;
+
+    #[test]
+    fn test_paths_under_home_are_shortened() {
+        let mut ctx = SystemContext {
+            current_dir: "/home/alice/projects/api".to_string(),
+            home_dir: "/home/alice".to_string(),
+            directory_listing: "l 22 2024-05-01 12:30 env -> /home/alice/.secrets/env\n"
+                .to_string(),
+            included_files: vec![IncludedFile {
+                path: "/home/alice/projects/api/notes.md".to_string(),
+                content: "cached in /home/alicebackup".to_string(),
+            }],
+            ..sample_context()
+        };
+        redact_context(&mut ctx);
+
+        assert_eq!(ctx.current_dir, "~/projects/api");
+        assert_eq!(ctx.home_dir, "~");
+        assert_eq!(
+            ctx.directory_listing,
+            "l 22 2024-05-01 12:30 env -> ~/.secrets/env\n"
+        );
+        assert_eq!(ctx.included_files[0].path, "~/projects/api/notes.md");
+        assert!(!ctx.build_full_context().contains("/home/alice/"));
+
+        // The home directory itself, and running it twice
+        let mut ctx = sample_context();
+        redact_context(&mut ctx);
+        redact_context(&mut ctx);
+        assert_eq!(
+            (ctx.current_dir.as_str(), ctx.home_dir.as_str()),
+            ("~", "~")
+        );
+    }
+
+    #[test]
+    fn test_omitted_listing_is_left_out_of_full_context() {
+        let mut ctx = sample_context();
+        assert!(ctx.build_full_context().contains("file1"));
+
+        omit_directory_listing(&mut ctx);
+        assert!(ctx.directory_listing.is_empty());
+        let full = ctx.build_full_context();
+        assert!(!full.contains("file1"));
+        assert!(!full.contains("directory listing"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Roll the file over once it would grow past this many bytes.
+    pub max_bytes: u64,
+    /// Number of rotated files (`<name>.1` .. `<name>.N`) to keep.
+    pub keep: usize,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        RotationPolicy {
+            max_bytes: 10 * 1024 * 1024,
+            keep: 3,
+        }
+    }
+}
+
+/// Append-only log writer with size-based rotation.
+///
+/// When a write would push the active file past `max_bytes`, the file is
+/// renamed to `<name>.1`, older rotations shift up by one, and anything past
+/// `keep` is deleted.
+pub struct RotatingWriter {
+    path: PathBuf,
+    policy: RotationPolicy,
+    file: File,
+    size: u64,
+}
+
+impl RotatingWriter {
+    pub fn open(path: impl Into<PathBuf>, policy: RotationPolicy) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            policy,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.policy.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.rotated_path(self.policy.keep);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for n in (1..self.policy.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.policy.max_bytes {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn policy(max_bytes: u64, keep: usize) -> RotationPolicy {
+        RotationPolicy { max_bytes, keep }
+    }
+
+    #[test]
+    fn test_writes_below_threshold_do_not_rotate() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("debug.log");
+        let mut writer = RotatingWriter::open(&path, policy(100, 2)).unwrap();
+
+        writer.write_all(b"hello\n").unwrap();
+        writer.write_all(b"world\n").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nworld\n");
+        assert!(!writer.rotated_path(1).exists());
+    }
+
+    #[test]
+    fn test_rotates_past_threshold() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let mut writer = RotatingWriter::open(&path, policy(10, 2)).unwrap();
+
+        writer.write_all(b"first-000\n").unwrap();
+        writer.write_all(b"second-00\n").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second-00\n");
+        assert_eq!(
+            fs::read_to_string(writer.rotated_path(1)).unwrap(),
+            "first-000\n"
+        );
+    }
+
+    #[test]
+    fn test_prunes_beyond_keep_count() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let mut writer = RotatingWriter::open(&path, policy(5, 2)).unwrap();
+
+        for line in ["aaaa\n", "bbbb\n", "cccc\n", "dddd\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddd\n");
+        assert_eq!(
+            fs::read_to_string(writer.rotated_path(1)).unwrap(),
+            "cccc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(writer.rotated_path(2)).unwrap(),
+            "bbbb\n"
+        );
+        assert!(!writer.rotated_path(3).exists());
+    }
+
+    #[test]
+    fn test_existing_file_size_counts_towards_threshold() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("debug.log");
+        fs::write(&path, "existing\n").unwrap();
+
+        let mut writer = RotatingWriter::open(&path, policy(12, 1)).unwrap();
+        writer.write_all(b"new line\n").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new line\n");
+        assert_eq!(
+            fs::read_to_string(writer.rotated_path(1)).unwrap(),
+            "existing\n"
+        );
+    }
+}
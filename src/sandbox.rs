@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+
+use crate::context::detect_tools;
+
+/// A tool that can run a command with a read-only view of the filesystem
+/// (`--sandbox`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxTool {
+    /// bubblewrap (Linux)
+    Bwrap,
+    /// firejail (Linux)
+    Firejail,
+    /// Seatbelt profiles (macOS)
+    SandboxExec,
+}
+
+impl SandboxTool {
+    pub fn binary(&self) -> &'static str {
+        match self {
+            SandboxTool::Bwrap => "bwrap",
+            SandboxTool::Firejail => "firejail",
+            SandboxTool::SandboxExec => "sandbox-exec",
+        }
+    }
+
+    /// The tools supported on `os` (as in `std::env::consts::OS`), most
+    /// preferred first.
+    pub fn candidates(os: &str) -> &'static [SandboxTool] {
+        match os {
+            "linux" => &[SandboxTool::Bwrap, SandboxTool::Firejail],
+            "macos" => &[SandboxTool::SandboxExec],
+            _ => &[],
+        }
+    }
+
+    /// The argv that runs `shell -c command` in the sandbox. The filesystem
+    /// is read-only except for a private `/tmp` and the `writable` paths;
+    /// the network stays available.
+    pub fn wrap(&self, shell: &str, command: &str, writable: &[PathBuf]) -> Vec<String> {
+        let mut argv = vec![self.binary().to_string()];
+
+        match self {
+            SandboxTool::Bwrap => {
+                argv.extend(
+                    [
+                        "--ro-bind",
+                        "/",
+                        "/",
+                        "--dev",
+                        "/dev",
+                        "--proc",
+                        "/proc",
+                        "--tmpfs",
+                        "/tmp",
+                        "--unshare-all",
+                        "--share-net",
+                        "--die-with-parent",
+                    ]
+                    .map(String::from),
+                );
+                for path in writable {
+                    let path = path.display().to_string();
+                    argv.extend(["--bind".to_string(), path.clone(), path]);
+                }
+                argv.push("--".to_string());
+            }
+            SandboxTool::Firejail => {
+                argv.extend(
+                    ["--quiet", "--noprofile", "--read-only=/", "--private-tmp"].map(String::from),
+                );
+                argv.extend(
+                    writable
+                        .iter()
+                        .map(|path| format!("--read-write={}", path.display())),
+                );
+                argv.push("--".to_string());
+            }
+            SandboxTool::SandboxExec => {
+                argv.push("-p".to_string());
+                argv.push(seatbelt_profile(writable));
+            }
+        }
+
+        argv.extend([shell.to_string(), "-c".to_string(), command.to_string()]);
+        argv
+    }
+}
+
+/// A Seatbelt profile denying file writes outside temp directories, the
+/// terminal devices and `writable`.
+fn seatbelt_profile(writable: &[PathBuf]) -> String {
+    let mut allowed = vec![
+        "(literal \"/dev/null\")".to_string(),
+        "(regex #\"^/dev/tty\")".to_string(),
+        "(subpath \"/private/tmp\")".to_string(),
+        "(subpath \"/private/var/folders\")".to_string(),
+    ];
+    allowed.extend(
+        writable
+            .iter()
+            .map(|path| format!("(subpath {:?})", path.display().to_string())),
+    );
+
+    format!(
+        "(version 1) (allow default) (deny file-write*) (allow file-write* {})",
+        allowed.join(" ")
+    )
+}
+
+/// The first sandbox tool for `os` found in `dirs`, or `None` when sandboxing
+/// is unavailable.
+pub fn detect_sandbox(os: &str, dirs: &[PathBuf]) -> Option<SandboxTool> {
+    let candidates = SandboxTool::candidates(os);
+    let names: Vec<&str> = candidates.iter().map(SandboxTool::binary).collect();
+    let found = detect_tools(&names, dirs);
+
+    candidates
+        .iter()
+        .copied()
+        .find(|tool| found.iter().any(|name| name == tool.binary()))
+}
+
+/// The warning shown when `--sandbox` has to run the command unsandboxed.
+pub fn unavailable_warning(os: &str) -> String {
+    match SandboxTool::candidates(os) {
+        [] => format!("Warning: --sandbox is not supported on {os}; running without a sandbox."),
+        candidates => {
+            let names: Vec<&str> = candidates.iter().map(SandboxTool::binary).collect();
+            format!(
+                "Warning: no sandbox tool found ({}); running without a sandbox.",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bwrap_command() {
+        let argv = SandboxTool::Bwrap.wrap("bash", "ls -la", &[PathBuf::from("/home/u/out")]);
+        assert_eq!(argv[0], "bwrap");
+        assert!(argv.windows(3).any(|w| w == ["--ro-bind", "/", "/"]));
+        assert!(
+            argv.windows(3)
+                .any(|w| w == ["--bind", "/home/u/out", "/home/u/out"])
+        );
+        assert_eq!(argv[argv.len() - 4..], ["--", "bash", "-c", "ls -la"]);
+    }
+
+    #[test]
+    fn test_firejail_command() {
+        let argv = SandboxTool::Firejail.wrap("sh", "rm -rf build", &[PathBuf::from("/work")]);
+        assert_eq!(
+            argv,
+            [
+                "firejail",
+                "--quiet",
+                "--noprofile",
+                "--read-only=/",
+                "--private-tmp",
+                "--read-write=/work",
+                "--",
+                "sh",
+                "-c",
+                "rm -rf build",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sandbox_exec_command() {
+        let argv = SandboxTool::SandboxExec.wrap("zsh", "touch x", &[PathBuf::from("/Users/u")]);
+        assert_eq!(argv[..2], ["sandbox-exec", "-p"]);
+        assert!(argv[2].contains("(deny file-write*)"));
+        assert!(argv[2].contains("(subpath \"/Users/u\")"));
+        assert_eq!(argv[3..], ["zsh", "-c", "touch x"]);
+    }
+
+    #[cfg(unix)]
+    fn fake_tool(dir: &TempDir, name: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.path().join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_detect_sandbox() {
+        let dir = TempDir::new().unwrap();
+        let dirs = vec![dir.path().to_path_buf()];
+        assert_eq!(detect_sandbox("linux", &dirs), None);
+
+        fake_tool(&dir, "firejail");
+        assert_eq!(detect_sandbox("linux", &dirs), Some(SandboxTool::Firejail));
+
+        fake_tool(&dir, "bwrap");
+        assert_eq!(detect_sandbox("linux", &dirs), Some(SandboxTool::Bwrap));
+
+        fake_tool(&dir, "sandbox-exec");
+        assert_eq!(
+            detect_sandbox("macos", &dirs),
+            Some(SandboxTool::SandboxExec)
+        );
+        assert_eq!(detect_sandbox("windows", &dirs), None);
+    }
+
+    #[test]
+    fn test_unavailable_warning() {
+        assert!(unavailable_warning("linux").contains("bwrap, firejail"));
+        assert!(unavailable_warning("windows").contains("not supported on windows"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl FromStr for ShellKind {
+    type Err = String;
+
+    /// Accepts a shell name or path, e.g. `fish`, `/bin/zsh` or `pwsh.exe`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = Path::new(s)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match name.as_str() {
+            "bash" | "sh" => Ok(ShellKind::Bash),
+            "zsh" => Ok(ShellKind::Zsh),
+            "fish" => Ok(ShellKind::Fish),
+            "powershell" | "pwsh" => Ok(ShellKind::PowerShell),
+            _ => Err(format!(
+                "unsupported shell '{s}' (expected bash, zsh, fish or powershell)"
+            )),
+        }
+    }
+}
+
+impl ShellKind {
+    /// Picks the shell from `$SHELL`, falling back to PowerShell on Windows
+    /// and bash elsewhere.
+    pub fn detect(shell_env: Option<&str>) -> Self {
+        shell_env
+            .and_then(|shell| shell.parse().ok())
+            .unwrap_or(if cfg!(target_os = "windows") {
+                ShellKind::PowerShell
+            } else {
+                ShellKind::Bash
+            })
+    }
+
+    /// A statement setting and exporting `name` to `value`.
+    pub fn export(self, name: &str, value: &str) -> String {
+        match self {
+            ShellKind::Bash | ShellKind::Zsh => format!("export {name}={}", posix_quote(value)),
+            ShellKind::Fish => format!("set -gx {name} {}", fish_quote(value)),
+            ShellKind::PowerShell => format!("$env:{name} = {}", powershell_quote(value)),
+        }
+    }
+
+    /// A statement prepending `dir` to `PATH`.
+    pub fn prepend_path(self, dir: &str) -> String {
+        match self {
+            ShellKind::Bash | ShellKind::Zsh => {
+                format!("export PATH={}:\"$PATH\"", posix_quote(dir))
+            }
+            ShellKind::Fish => format!("set -gx PATH {} $PATH", fish_quote(dir)),
+            ShellKind::PowerShell => format!(
+                "$env:PATH = {} + [IO.Path]::PathSeparator + $env:PATH",
+                powershell_quote(dir)
+            ),
+        }
+    }
+
+    /// A statement defining `name` as a shortcut for `command`. PowerShell
+    /// aliases cannot carry arguments, so a function is used there.
+    pub fn alias(self, name: &str, command: &str) -> String {
+        match self {
+            ShellKind::Bash | ShellKind::Zsh => format!("alias {name}={}", posix_quote(command)),
+            ShellKind::Fish => format!("alias {name} {}", fish_quote(command)),
+            ShellKind::PowerShell => format!("function {name} {{ {command} }}"),
+        }
+    }
+}
+
+/// Longest alias name suggested.
+const MAX_ALIAS_LEN: usize = 24;
+
+/// Derives an alias name from the program and its first argument, e.g.
+/// `git log --oneline` becomes `git-log` and `du -sh * | sort -h` becomes
+/// `du-sh`. Environment assignments and `sudo` are skipped.
+pub fn suggest_alias_name(command: &str) -> String {
+    let first_stage = command
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split(['|', ';', '&'])
+        .next()
+        .unwrap_or_default();
+
+    let mut words = first_stage
+        .split_whitespace()
+        .skip_while(|word| word.contains('=') || *word == "sudo");
+    let program = words
+        .next()
+        .map(|word| word.rsplit(['/', '\\']).next().unwrap_or(word))
+        .unwrap_or_default();
+    let argument = words.next().unwrap_or_default();
+
+    sanitize_alias_name(&format!("{program}-{argument}"))
+}
+
+/// Lowercases `raw` and keeps it to letters, digits and single dashes, so it
+/// is a valid alias name in every supported shell.
+pub fn sanitize_alias_name(raw: &str) -> String {
+    let mut name = String::new();
+    for ch in raw.chars().flat_map(char::to_lowercase) {
+        if ch.is_ascii_alphanumeric() {
+            name.push(ch);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.truncate(MAX_ALIAS_LEN);
+
+    let name = name.trim_end_matches('-');
+    if name.is_empty() {
+        "cmd".to_string()
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("cmd-{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Renders the setup script for `eval "$(sh-aid env)"` (or `| source` in
+/// fish, `| Invoke-Expression` in PowerShell).
+pub fn render_env(shell: ShellKind, path_dir: Option<&str>, vars: &[(&str, String)]) -> String {
+    let mut output = String::new();
+
+    if let Some(dir) = path_dir {
+        output.push_str(&shell.prepend_path(dir));
+        output.push('\n');
+    }
+    for (name, value) in vars {
+        output.push_str(&shell.export(name, value));
+        output.push('\n');
+    }
+
+    output
+}
+
+fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn powershell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        vec![
+            ("SHAID_PROVIDER", "OpenAI".to_string()),
+            ("SHAID_MODEL", "it's-4o".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_parse_and_detect() {
+        assert_eq!("/usr/bin/zsh".parse(), Ok(ShellKind::Zsh));
+        assert_eq!("FISH".parse(), Ok(ShellKind::Fish));
+        assert_eq!("pwsh.exe".parse(), Ok(ShellKind::PowerShell));
+        assert!("tcsh".parse::<ShellKind>().is_err());
+
+        assert_eq!(
+            ShellKind::detect(Some("/opt/homebrew/bin/fish")),
+            ShellKind::Fish
+        );
+        if !cfg!(target_os = "windows") {
+            assert_eq!(ShellKind::detect(None), ShellKind::Bash);
+            assert_eq!(ShellKind::detect(Some("/bin/tcsh")), ShellKind::Bash);
+        }
+    }
+
+    #[test]
+    fn test_bash_and_zsh_syntax() {
+        let expected = "export PATH='/opt/sh aid/bin':\"$PATH\"\n\
+                        export SHAID_PROVIDER='OpenAI'\n\
+                        export SHAID_MODEL='it'\\''s-4o'\n";
+        assert_eq!(
+            render_env(ShellKind::Bash, Some("/opt/sh aid/bin"), &vars()),
+            expected
+        );
+        assert_eq!(
+            render_env(ShellKind::Zsh, Some("/opt/sh aid/bin"), &vars()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_fish_syntax() {
+        assert_eq!(
+            render_env(ShellKind::Fish, Some("/opt/bin"), &vars()),
+            "set -gx PATH '/opt/bin' $PATH\n\
+             set -gx SHAID_PROVIDER 'OpenAI'\n\
+             set -gx SHAID_MODEL 'it\\'s-4o'\n"
+        );
+    }
+
+    #[test]
+    fn test_alias_syntax() {
+        let command = "grep -rn 'TODO' src";
+        assert_eq!(
+            ShellKind::Bash.alias("grep-rn", command),
+            r"alias grep-rn='grep -rn '\''TODO'\'' src'"
+        );
+        assert_eq!(
+            ShellKind::Zsh.alias("grep-rn", command),
+            ShellKind::Bash.alias("grep-rn", command)
+        );
+        assert_eq!(
+            ShellKind::Fish.alias("grep-rn", command),
+            r"alias grep-rn 'grep -rn \'TODO\' src'"
+        );
+        assert_eq!(
+            ShellKind::PowerShell.alias("grep-rn", command),
+            "function grep-rn { grep -rn 'TODO' src }"
+        );
+    }
+
+    #[test]
+    fn test_alias_names() {
+        assert_eq!(suggest_alias_name("git log --oneline -10"), "git-log");
+        assert_eq!(suggest_alias_name("du -sh * | sort -h"), "du-sh");
+        assert_eq!(
+            suggest_alias_name("sudo LC_ALL=C /usr/bin/apt list --upgradable"),
+            "apt-list"
+        );
+        assert_eq!(suggest_alias_name("ls"), "ls");
+
+        assert_eq!(sanitize_alias_name("Docker PS -a"), "docker-ps-a");
+        assert_eq!(sanitize_alias_name("--"), "cmd");
+        assert_eq!(sanitize_alias_name("7z-x"), "cmd-7z-x");
+        assert_eq!(sanitize_alias_name(&"x".repeat(40)).len(), MAX_ALIAS_LEN);
+    }
+
+    #[test]
+    fn test_powershell_syntax() {
+        assert_eq!(
+            render_env(ShellKind::PowerShell, None, &vars()),
+            "$env:SHAID_PROVIDER = 'OpenAI'\n\
+             $env:SHAID_MODEL = 'it''s-4o'\n"
+        );
+        assert_eq!(
+            ShellKind::PowerShell.prepend_path(r"C:\Tools"),
+            r"$env:PATH = 'C:\Tools' + [IO.Path]::PathSeparator + $env:PATH"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use futures::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::providers::{
+    AIProvider, CommandStream, GenerateOptions, ModelInfo, ProviderError, Quota,
+};
+use crate::usage::TokenUsage;
+
+/// Where the time of a run went, shown with `--verbose`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Gathering the system context (tool probes, listings, subprocesses).
+    pub context: Duration,
+    /// Waiting on the provider, summed over every call of the run.
+    pub api: Duration,
+    /// From startup until the result was ready.
+    pub total: Duration,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "context: {}, api: {}, total: {}",
+            format_duration(self.context),
+            format_duration(self.api),
+            format_duration(self.total)
+        )
+    }
+}
+
+/// `40ms` below a second, `1.2s` above.
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// Running total of the time spent in provider calls. Clones share the
+/// total.
+#[derive(Debug, Clone, Default)]
+pub struct ApiTimer {
+    total: Arc<Mutex<Duration>>,
+}
+
+impl ApiTimer {
+    pub fn add(&self, elapsed: Duration) {
+        *self
+            .total
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) += elapsed;
+    }
+
+    pub fn total(&self) -> Duration {
+        *self
+            .total
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Wraps a provider and adds the duration of every call to an [`ApiTimer`].
+/// A stream counts until it is dropped, so a stream cut short by
+/// `--stop-at-command` only counts the part that was read.
+pub struct TimedProvider {
+    inner: Box<dyn AIProvider>,
+    timer: ApiTimer,
+}
+
+impl TimedProvider {
+    pub fn new(inner: Box<dyn AIProvider>, timer: ApiTimer) -> Self {
+        Self { inner, timer }
+    }
+}
+
+/// A stream that adds its lifetime to the timer when dropped.
+struct TimedStream {
+    inner: CommandStream,
+    started: Instant,
+    timer: ApiTimer,
+}
+
+impl Stream for TimedStream {
+    type Item = Result<String, ProviderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl Drop for TimedStream {
+    fn drop(&mut self) {
+        self.timer.add(self.started.elapsed());
+    }
+}
+
+#[async_trait]
+impl AIProvider for TimedProvider {
+    async fn generate_command_with(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<String, ProviderError> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .generate_command_with(system_prompt, user_prompt, options)
+            .await;
+        self.timer.add(started.elapsed());
+        result
+    }
+
+    async fn generate_commands(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        n: u32,
+        options: &GenerateOptions,
+    ) -> Result<Vec<String>, ProviderError> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .generate_commands(system_prompt, user_prompt, n, options)
+            .await;
+        self.timer.add(started.elapsed());
+        result
+    }
+
+    async fn generate_command_stream(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<CommandStream, ProviderError> {
+        let started = Instant::now();
+        match self
+            .inner
+            .generate_command_stream(system_prompt, user_prompt, options)
+            .await
+        {
+            Ok(inner) => Ok(Box::pin(TimedStream {
+                inner,
+                started,
+                timer: self.timer.clone(),
+            })),
+            Err(error) => {
+                self.timer.add(started.elapsed());
+                Err(error)
+            }
+        }
+    }
+
+    async fn remaining_quota(&self) -> Result<Option<Quota>, ProviderError> {
+        self.inner.remaining_quota().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    fn token_usage(&self) -> Option<TokenUsage> {
+        self.inner.token_usage()
+    }
+
+    fn truncated_responses(&self) -> u32 {
+        self.inner.truncated_responses()
+    }
+
+    fn fell_back_from(&self) -> Option<String> {
+        self.inner.fell_back_from()
+    }
+
+    fn validate_config(&self, config: &Config) -> Result<(), ProviderError> {
+        self.inner.validate_config(config)
+    }
+
+    fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info()
+    }
+
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn clone_box(&self) -> Box<dyn AIProvider> {
+        Box::new(TimedProvider::new(
+            self.inner.clone_box(),
+            self.timer.clone(),
+        ))
+    }
+
+    fn get_provider_name(&self) -> &'static str {
+        self.inner.get_provider_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::test_utils::MockProvider;
+    use futures::StreamExt;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_timings_display() {
+        let timings = Timings {
+            context: ms(40),
+            api: ms(1200),
+            total: ms(1300),
+        };
+        assert_eq!(timings.to_string(), "context: 40ms, api: 1.2s, total: 1.3s");
+    }
+
+    #[test]
+    fn test_api_timer_sums_calls() {
+        let timer = ApiTimer::default();
+        let shared = timer.clone();
+        timer.add(ms(300));
+        shared.add(ms(450));
+        assert_eq!(timer.total(), ms(750));
+
+        let timings = Timings {
+            context: ms(5),
+            api: timer.total(),
+            total: ms(2500),
+        };
+        assert_eq!(timings.to_string(), "context: 5ms, api: 750ms, total: 2.5s");
+    }
+
+    #[tokio::test]
+    async fn test_timed_provider_records_calls_and_streams() {
+        let timer = ApiTimer::default();
+        let provider = TimedProvider::new(
+            Box::new(MockProvider::with_stream(&["ls", " -la"])),
+            timer.clone(),
+        );
+
+        let mut stream = provider
+            .generate_command_stream("s", "u", &GenerateOptions::default())
+            .await
+            .unwrap();
+        tokio::time::sleep(ms(20)).await;
+        assert_eq!(timer.total(), Duration::ZERO);
+        while stream.next().await.is_some() {}
+        drop(stream);
+        assert!(timer.total() >= ms(20));
+
+        assert_eq!(provider.generate_command("s", "u").await.unwrap(), "ls -la");
+    }
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Add;
+use std::sync::{Arc, Mutex};
+
+/// Tokens billed for one or more requests, as reported by the provider
+/// (OpenAI's `usage` object).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl TokenUsage {
+    /// The usage added since `earlier`, a previous reading of the same
+    /// running total.
+    pub fn since(self, earlier: Option<TokenUsage>) -> TokenUsage {
+        let earlier = earlier.unwrap_or_default();
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_sub(earlier.completion_tokens),
+            total_tokens: self.total_tokens.saturating_sub(earlier.total_tokens),
+        }
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            total_tokens: self.total_tokens + other.total_tokens,
+        }
+    }
+}
+
+impl fmt::Display for TokenUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} prompt + {} completion = {} tokens",
+            self.prompt_tokens, self.completion_tokens, self.total_tokens
+        )
+    }
+}
+
+/// Adds up two optional readings; `None` only when neither reported usage.
+pub fn sum_usage(a: Option<TokenUsage>, b: Option<TokenUsage>) -> Option<TokenUsage> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Running total of the usage reported by a provider's responses. Clones
+/// share the total.
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter {
+    total: Arc<Mutex<Option<TokenUsage>>>,
+}
+
+impl UsageMeter {
+    pub fn record(&self, usage: TokenUsage) {
+        let mut total = self
+            .total
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *total = sum_usage(*total, Some(usage));
+    }
+
+    pub fn total(&self) -> Option<TokenUsage> {
+        *self
+            .total
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// US dollars per million prompt and completion tokens. Approximate list
+/// prices, only for the estimate shown with `--verbose`.
+const PRICES_PER_MILLION: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("o3-mini", 1.10, 4.40),
+    ("o4-mini", 1.10, 4.40),
+];
+
+/// The estimated cost of `usage` with `model` in US dollars, or `None` for
+/// models without a known price. Dated snapshots (`gpt-4o-2024-08-06`) are
+/// priced like their base model.
+pub fn estimate_cost(model: &str, usage: &TokenUsage) -> Option<f64> {
+    let (_, prompt, completion) = PRICES_PER_MILLION
+        .iter()
+        .filter(|(name, _, _)| {
+            model == *name
+                || model
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with("-20"))
+        })
+        .max_by_key(|(name, _, _)| name.len())?;
+
+    Some(
+        (f64::from(usage.prompt_tokens) * prompt + f64::from(usage.completion_tokens) * completion)
+            / 1_000_000.0,
+    )
+}
+
+/// The line `--verbose` prints after a generation, e.g. "Tokens: 812
+/// prompt + 14 completion = 826 tokens (~$0.0022)".
+pub fn usage_summary(model: &str, usage: &TokenUsage) -> String {
+    match estimate_cost(model, usage) {
+        Some(cost) => format!("Tokens: {usage} (~${cost:.4})"),
+        None => format!("Tokens: {usage}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_meter_sums_and_since() {
+        let meter = UsageMeter::default();
+        assert_eq!(meter.total(), None);
+
+        meter.record(usage(800, 10));
+        let before = meter.total();
+        meter.clone().record(usage(820, 12));
+        assert_eq!(meter.total(), Some(usage(1620, 22)));
+        assert_eq!(meter.total().unwrap().since(before), usage(820, 12));
+        assert_eq!(usage(5, 1).since(None), usage(5, 1));
+
+        assert_eq!(sum_usage(None, Some(usage(1, 1))), Some(usage(1, 1)));
+        assert_eq!(sum_usage(None, None), None);
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let cost = estimate_cost("gpt-4o", &usage(1_000_000, 100_000)).unwrap();
+        assert!((cost - 3.5).abs() < 1e-9);
+
+        // The longest matching name wins, and dated snapshots are matched
+        let mini = estimate_cost("gpt-4o-mini-2024-07-18", &usage(1_000_000, 0)).unwrap();
+        assert!((mini - 0.15).abs() < 1e-9);
+
+        assert_eq!(estimate_cost("llama3.1", &usage(10, 10)), None);
+        assert_eq!(estimate_cost("gpt-4o-audio-preview", &usage(10, 10)), None);
+    }
+
+    #[test]
+    fn test_display_and_summary() {
+        assert_eq!(
+            usage(812, 14).to_string(),
+            "812 prompt + 14 completion = 826 tokens"
+        );
+        assert_eq!(
+            usage_summary("gpt-4o", &usage(812, 14)),
+            "Tokens: 812 prompt + 14 completion = 826 tokens (~$0.0022)"
+        );
+        assert_eq!(
+            usage_summary("llama3.1", &usage(812, 14)),
+            "Tokens: 812 prompt + 14 completion = 826 tokens"
+        );
+    }
+}
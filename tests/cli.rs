@@ -0,0 +1,174 @@
+//! Runs the `sh-aid` binary against a mock OpenAI server.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+/// A config in `dir` pointing the OpenAI provider at `base_url`.
+fn write_config(dir: &Path, base_url: &str) {
+    fs::write(
+        dir.join("config.json"),
+        format!(r#"{{"type": "OpenAI", "apiKey": "test-key", "model": "gpt-4o", "baseUrl": "{base_url}"}}"#),
+    )
+    .unwrap();
+}
+
+/// Runs the binary with `args` in an empty environment rooted at `home`,
+/// with stdin closed so it never waits for an answer.
+fn sh_aid(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sh-aid"))
+        .args(args)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("SHAID_CONFIG_DIR", home)
+        .current_dir(home)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn mock_completion(server: &mut mockito::ServerGuard, command: &str) -> mockito::Mock {
+    server
+        .mock("POST", "/v1/chat/completions")
+        .with_body(serde_json::json!({"choices": [{"message": {"content": command}}]}).to_string())
+        .create()
+}
+
+#[test]
+fn test_json_output_is_one_object_and_never_runs() {
+    let mut server = mockito::Server::new();
+    let mock = mock_completion(&mut server, "touch ran.txt");
+    let home = TempDir::new().unwrap();
+    write_config(home.path(), &server.url());
+
+    let output = sh_aid(home.path(), &["--format", "json", "create a file"]);
+    assert!(output.status.success(), "{output:?}");
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(value["command"], "touch ran.txt");
+    assert!(!home.path().join("ran.txt").exists());
+    mock.assert();
+
+    let output = sh_aid(home.path(), &["--format", "json", "--yes", "create a file"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--format json"));
+    assert!(!home.path().join("ran.txt").exists());
+}
+
+#[test]
+fn test_bare_env_file_flag_does_not_take_the_prompt() {
+    let mut server = mockito::Server::new();
+    let mock = server
+        .mock("POST", "/v1/chat/completions")
+        .match_header("authorization", "Bearer key-from-dotenv")
+        .match_body(mockito::Matcher::Regex("list files".to_string()))
+        .with_body(serde_json::json!({"choices": [{"message": {"content": "ls"}}]}).to_string())
+        .create();
+    let home = TempDir::new().unwrap();
+    fs::write(
+        home.path().join("config.json"),
+        format!(
+            r#"{{"type": "OpenAI", "apiKey": "", "model": "gpt-4o", "baseUrl": "{}"}}"#,
+            server.url()
+        ),
+    )
+    .unwrap();
+    fs::write(home.path().join(".env"), "OPENAI_API_KEY=key-from-dotenv\n").unwrap();
+
+    let output = sh_aid(
+        home.path(),
+        &["--format", "json", "--env-file", "list", "files"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    mock.assert();
+}
+
+#[test]
+fn test_env_file_applies_to_subcommands() {
+    let home = TempDir::new().unwrap();
+    write_config(home.path(), "https://gateway.example/v1");
+    fs::write(
+        home.path().join("custom.env"),
+        "SHAID_MODEL=model-from-dotenv\n",
+    )
+    .unwrap();
+
+    let output = sh_aid(home.path(), &["--env-file=custom.env", "env"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("model-from-dotenv"));
+}
+
+#[test]
+fn test_prompt_starting_with_a_subcommand_name() {
+    let mut server = mockito::Server::new();
+    let home = TempDir::new().unwrap();
+    write_config(home.path(), &server.url());
+
+    for prompt in [
+        ["config", "files", "in", "etc"],
+        ["env", "vars", "with", "path"],
+    ] {
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(prompt.join(" ")))
+            .with_body(
+                serde_json::json!({"choices": [{"message": {"content": "ls /etc"}}]}).to_string(),
+            )
+            .create();
+
+        let mut args = vec!["--format", "json"];
+        args.extend(prompt);
+        let output = sh_aid(home.path(), &args);
+        assert!(output.status.success(), "{output:?}");
+        mock.assert();
+    }
+
+    // Real subcommands still work
+    let output = sh_aid(home.path(), &["config", "get", "model"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "gpt-4o");
+}
+
+#[test]
+fn test_braces_in_prompt_reach_the_model_unchanged() {
+    let mut server = mockito::Server::new();
+    let mock = server
+        .mock("POST", "/v1/chat/completions")
+        .match_body(mockito::Matcher::Regex(
+            r"docker ps --format '\{\{\.Names\}\}'".to_string(),
+        ))
+        .with_body(
+            serde_json::json!({"choices": [{"message": {"content": "docker ps"}}]}).to_string(),
+        )
+        .create();
+    let home = TempDir::new().unwrap();
+    write_config(home.path(), &server.url());
+
+    let output = sh_aid(
+        home.path(),
+        &["--format", "json", "like docker ps --format '{{.Names}}'"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    mock.assert();
+}
+
+#[test]
+fn test_text_output_is_only_the_command() {
+    let mut server = mockito::Server::new();
+    let mock = mock_completion(&mut server, "```bash\nls -la\n```");
+    let home = TempDir::new().unwrap();
+    write_config(home.path(), &server.url());
+
+    // Status lines, timings and request details all go to stderr
+    let output = sh_aid(home.path(), &["-vv", "list", "files"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ls -la\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Loading configuration..."));
+    mock.assert();
+}
@@ -0,0 +1,86 @@
+//! Drives the library entry points against a mock OpenAI server, the way a
+//! tool embedding sh-aid would.
+
+use std::time::SystemTime;
+
+use sh_aid::config::{Config, ProviderType};
+use sh_aid::context::SystemContext;
+use sh_aid::error::ShaidError;
+
+fn context() -> SystemContext {
+    SystemContext {
+        os_type: "linux".to_string(),
+        os_release: "6.1".to_string(),
+        platform: "unix".to_string(),
+        arch: "x86_64".to_string(),
+        shell: "/bin/bash".to_string(),
+        current_dir: "/home/user".to_string(),
+        home_dir: "/home/user".to_string(),
+        cpu_model: "Intel Core i7".to_string(),
+        cpu_cores: 8,
+        total_memory_mb: 16384,
+        free_memory_mb: 8192,
+        directory_listing: "file1\nfile2".to_string(),
+        available_tools: vec!["git".to_string()],
+        included_files: Vec::new(),
+        is_wsl: false,
+        online: None,
+        aliases: Vec::new(),
+        recent_commands: Vec::new(),
+        allowed_fields: None,
+        gathered_at: SystemTime::now(),
+        note_age_after: None,
+    }
+}
+
+fn config(base_url: String) -> Config {
+    Config {
+        provider_type: ProviderType::OpenAI,
+        api_key: Some("test-key".to_string()),
+        model: "gpt-4o".to_string(),
+        base_url: Some(base_url),
+        ..Config::default()
+    }
+}
+
+async fn mock_completion(server: &mut mockito::ServerGuard, content: &str) -> mockito::Mock {
+    server
+        .mock("POST", "/v1/chat/completions")
+        .match_body(mockito::Matcher::Regex("file1\\\\nfile2".to_string()))
+        .with_body(serde_json::json!({"choices": [{"message": {"content": content}}]}).to_string())
+        .create_async()
+        .await
+}
+
+#[tokio::test]
+async fn test_generate_cleans_and_checks_the_command() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = mock_completion(
+        &mut server,
+        "Here's the command:\n```bash\nls -S\n```\nThis sorts by size.",
+    )
+    .await;
+
+    let config = Config {
+        strict_level: Some(1),
+        ..config(server.url())
+    };
+    let command = sh_aid::generate(&config, &context(), "list files by size")
+        .await
+        .unwrap();
+    assert_eq!(command, "ls -S");
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_generate_refuses_commands_outside_the_policy() {
+    let mut server = mockito::Server::new_async().await;
+    mock_completion(&mut server, "rm -rf build").await;
+
+    let config = Config {
+        allowed_commands: vec!["git".to_string()],
+        ..config(server.url())
+    };
+    let result = sh_aid::generate(&config, &context(), "clean").await;
+    assert!(matches!(result, Err(ShaidError::Policy(_))));
+}